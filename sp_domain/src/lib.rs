@@ -8,6 +8,11 @@ pub use predicate::*;
 
 pub mod predicate_parser;
 
+pub mod spec;
+pub use spec::*;
+
+pub mod spec_parser;
+
 pub mod state;
 pub use state::*;
 
@@ -17,9 +17,12 @@ pub enum Predicate {
     NEQ(PredicateValue, PredicateValue),
     TON(PredicateValue, PredicateValue),
     TOFF(PredicateValue, PredicateValue),
-    MEMBER(PredicateValue, PredicateValue), // GT(PredicateValue, PredicateValue),
-                                            // LT(PredicateValue, PredicateValue),
-                                            // INDOMAIN(PredicateValue, Vec<PredicateValue>)
+    MEMBER(PredicateValue, PredicateValue),
+    GT(PredicateValue, PredicateValue),
+    LT(PredicateValue, PredicateValue),
+    GEQ(PredicateValue, PredicateValue),
+    LEQ(PredicateValue, PredicateValue),
+    // INDOMAIN(PredicateValue, Vec<PredicateValue>)
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone, Default)]
@@ -63,12 +66,13 @@ pub enum Compute {
     TimeStamp,
     Random(i32), // random number 0 < x < n
     Any,         // Free variable, can take on any value after this action.
-                 // If we need more advanced functions we can add them here
-                 //TakeNext(SPValue, Vec<SPValue>), // to be impl when needed
-                 //TakeBefore(SPValue, Vec<SPValue>),
-                 // Add(Box<Compute>, Box<Compute>),
-                 // Sub(Box<Compute>, Box<Compute>),
-                 // Join(Box<Compute>, Box<Compute>),
+    // Arithmetic on numbers, the operands are values or arithmetic.
+    Add(Box<Compute>, Box<Compute>),
+    Sub(Box<Compute>, Box<Compute>),
+    // If we need more advanced functions we can add them here
+    //TakeNext(SPValue, Vec<SPValue>), // to be impl when needed
+    //TakeBefore(SPValue, Vec<SPValue>),
+    // Join(Box<Compute>, Box<Compute>),
 }
 
 impl<'a> PredicateValue {
@@ -181,6 +185,10 @@ impl fmt::Display for Predicate {
             Predicate::MEMBER(t, d) => {
                 format!("is {t} a member of {d}?")
             }
+            Predicate::GT(x, y) => format!("{x} > {y}"),
+            Predicate::LT(x, y) => format!("{x} < {y}"),
+            Predicate::GEQ(x, y) => format!("{x} >= {y}"),
+            Predicate::LEQ(x, y) => format!("{x} <= {y}"),
         };
 
         write!(fmtr, "{}", &s)
@@ -209,7 +217,11 @@ impl Predicate {
             | Predicate::NEQ(x, y)
             | Predicate::TON(x, y)
             | Predicate::TOFF(x, y)
-            | Predicate::MEMBER(x, y) => {
+            | Predicate::MEMBER(x, y)
+            | Predicate::GT(x, y)
+            | Predicate::LT(x, y)
+            | Predicate::GEQ(x, y)
+            | Predicate::LEQ(x, y) => {
                 x.upd_state_path(state);
                 y.upd_state_path(state);
             }
@@ -229,7 +241,11 @@ impl Predicate {
             | Predicate::NEQ(pv1, pv2)
             | Predicate::TON(pv1, pv2)
             | Predicate::TOFF(pv1, pv2)
-            | Predicate::MEMBER(pv1, pv2) => {
+            | Predicate::MEMBER(pv1, pv2)
+            | Predicate::GT(pv1, pv2)
+            | Predicate::LT(pv1, pv2)
+            | Predicate::GEQ(pv1, pv2)
+            | Predicate::LEQ(pv1, pv2) => {
                 pv1.replace_variable_path(mapping);
                 pv2.replace_variable_path(mapping);
             }
@@ -249,7 +265,11 @@ impl Predicate {
             | Predicate::NEQ(x, y)
             | Predicate::TON(x, y)
             | Predicate::TOFF(x, y)
-            | Predicate::MEMBER(x, y) => {
+            | Predicate::MEMBER(x, y)
+            | Predicate::GT(x, y)
+            | Predicate::LT(x, y)
+            | Predicate::GEQ(x, y)
+            | Predicate::LEQ(x, y) => {
                 if let PredicateValue::SPPath(p, _) = x {
                     s.push(p.clone())
                 }
@@ -306,7 +326,11 @@ impl Predicate {
             | Predicate::NEQ(x, y)
             | Predicate::TON(x, y)
             | Predicate::TOFF(x, y)
-            | Predicate::MEMBER(x, y) => {
+            | Predicate::MEMBER(x, y)
+            | Predicate::GT(x, y)
+            | Predicate::LT(x, y)
+            | Predicate::GEQ(x, y)
+            | Predicate::LEQ(x, y) => {
                 let remove_x = match x {
                     PredicateValue::SPValue(_) => false,
                     PredicateValue::SPPath(p, _) => !only.contains(p),
@@ -326,28 +350,41 @@ impl Predicate {
     }
 }
 
-impl Action {
-    pub fn new(var: SPPath, value: Compute) -> Self {
-        Action {
-            var,
-            value,
-            state_path: None,
+impl Compute {
+    /// Return the variables read when computing the new value
+    pub fn support(&self) -> Vec<SPPath> {
+        let mut s = Vec::new();
+        match self {
+            Compute::PredicateValue(PredicateValue::SPPath(p, _)) => s.push(p.clone()),
+            Compute::PredicateValue(PredicateValue::SPValue(_)) => {}
+            Compute::Predicate(p) => s.extend(p.support()),
+            Compute::Function(xs) => xs.iter().for_each(|(p, v)| {
+                s.extend(p.support());
+                if let PredicateValue::SPPath(p, _) = v {
+                    s.push(p.clone());
+                }
+            }),
+            Compute::TimeStamp | Compute::Random(_) | Compute::Any => {}
+            Compute::Add(a, b) | Compute::Sub(a, b) => {
+                s.extend(a.support());
+                s.extend(b.support());
+            }
         }
+        s.sort();
+        s.dedup();
+        s
     }
 
-    pub fn upd_state_path(&mut self, state: &SPState) {
-        match &self.state_path {
-            Some(sp) if sp.state_id != state.id() => self.state_path = state.state_path(&self.var),
-            None => self.state_path = state.state_path(&self.var),
-            _ => {}
-        }
+    pub fn sum(lhs: Compute, rhs: Compute) -> Self {
+        Compute::Add(Box::new(lhs), Box::new(rhs))
+    }
+
+    pub fn difference(lhs: Compute, rhs: Compute) -> Self {
+        Compute::Sub(Box::new(lhs), Box::new(rhs))
     }
 
     pub fn replace_variable_path(&mut self, mapping: &HashMap<SPPath, SPPath>) {
-        if let Some(np) = mapping.get(&self.var) {
-            self.var = np.clone();
-        }
-        match &mut self.value {
+        match self {
             Compute::PredicateValue(pv) => {
                 pv.replace_variable_path(mapping);
             }
@@ -360,11 +397,128 @@ impl Action {
                     v.replace_variable_path(mapping);
                 });
             }
+            Compute::Add(a, b) | Compute::Sub(a, b) => {
+                a.replace_variable_path(mapping);
+                b.replace_variable_path(mapping);
+            }
             Compute::Random(_) => {}
             Compute::TimeStamp | Compute::Any => {}
         }
     }
 
+    /// Evaluate an arithmetic expression. `value` looks up the
+    /// operands, integers are promoted to floats when mixed.
+    fn arithmetic<F>(&self, value: &F) -> SPResult<SPValue>
+    where
+        F: Fn(&PredicateValue) -> Option<SPValue>,
+    {
+        let (a, b, op) = match self {
+            Compute::PredicateValue(pv) => {
+                return value(pv).ok_or_else(|| {
+                    SPError::No(format!("arithmetic did not find a value for: {pv}"))
+                })
+            }
+            Compute::Add(a, b) => (a.arithmetic(value)?, b.arithmetic(value)?, "+"),
+            Compute::Sub(a, b) => (a.arithmetic(value)?, b.arithmetic(value)?, "-"),
+            c => return Err(SPError::No(format!("{c} can not be used in arithmetic"))),
+        };
+        let add = op == "+";
+        let res = match (&a, &b) {
+            (SPValue::Int32(x), SPValue::Int32(y)) if add => x.checked_add(*y).map(SPValue::Int32),
+            (SPValue::Int32(x), SPValue::Int32(y)) => x.checked_sub(*y).map(SPValue::Int32),
+            (SPValue::Float32(_) | SPValue::Int32(_), SPValue::Float32(_) | SPValue::Int32(_)) => {
+                let (x, y) = (as_f32(&a), as_f32(&b));
+                Some(SPValue::Float32(if add { x + y } else { x - y }))
+            }
+            _ => {
+                return Err(SPError::No(format!(
+                    "arithmetic on non-numeric values: {a} {op} {b}"
+                )))
+            }
+        };
+        res.ok_or_else(|| SPError::No(format!("arithmetic overflow: {a} {op} {b}")))
+    }
+}
+
+fn as_f32(v: &SPValue) -> f32 {
+    match v {
+        SPValue::Int32(x) => *x as f32,
+        SPValue::Float32(x) => *x,
+        _ => f32::NAN,
+    }
+}
+
+/// Compare two numbers, `None` if any of them is not a number.
+fn compare_numbers(a: &SPValue, b: &SPValue) -> Option<std::cmp::Ordering> {
+    match (a, b) {
+        (SPValue::Int32(x), SPValue::Int32(y)) => Some(x.cmp(y)),
+        (SPValue::Float32(_) | SPValue::Int32(_), SPValue::Float32(_) | SPValue::Int32(_)) => {
+            as_f32(a).partial_cmp(&as_f32(b))
+        }
+        _ => None,
+    }
+}
+
+/// Evaluate one of the ordering predicates on the values of its operands.
+fn ordering_holds(p: &Predicate, a: &SPValue, b: &SPValue) -> bool {
+    use std::cmp::Ordering;
+    match (p, compare_numbers(a, b)) {
+        (Predicate::GT(..), Some(o)) => o == Ordering::Greater,
+        (Predicate::LT(..), Some(o)) => o == Ordering::Less,
+        (Predicate::GEQ(..), Some(o)) => o != Ordering::Less,
+        (Predicate::LEQ(..), Some(o)) => o != Ordering::Greater,
+        _ => {
+            eprintln!("ERROR: can not compare {a} and {b} in {p}");
+            false
+        }
+    }
+}
+
+impl fmt::Display for Compute {
+    fn fmt(&self, fmtr: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Compute::PredicateValue(PredicateValue::SPValue(v)) => write!(fmtr, "{v}"),
+            Compute::PredicateValue(PredicateValue::SPPath(p, _)) => write!(fmtr, "{p}"),
+            Compute::Predicate(p) => write!(fmtr, "{p}"),
+            Compute::Function(xs) => {
+                for (p, v) in xs {
+                    write!(fmtr, "[if {p} then {v}]")?;
+                }
+                Ok(())
+            }
+            Compute::Any => write!(fmtr, "?"),
+            Compute::Random(n) => write!(fmtr, "rnd({n})"),
+            Compute::TimeStamp => write!(fmtr, "T"),
+            Compute::Add(a, b) => write!(fmtr, "({a} + {b})"),
+            Compute::Sub(a, b) => write!(fmtr, "({a} - {b})"),
+        }
+    }
+}
+
+impl Action {
+    pub fn new(var: SPPath, value: Compute) -> Self {
+        Action {
+            var,
+            value,
+            state_path: None,
+        }
+    }
+
+    pub fn upd_state_path(&mut self, state: &SPState) {
+        match &self.state_path {
+            Some(sp) if sp.state_id != state.id() => self.state_path = state.state_path(&self.var),
+            None => self.state_path = state.state_path(&self.var),
+            _ => {}
+        }
+    }
+
+    pub fn replace_variable_path(&mut self, mapping: &HashMap<SPPath, SPPath>) {
+        if let Some(np) = mapping.get(&self.var) {
+            self.var = np.clone();
+        }
+        self.value.replace_variable_path(mapping);
+    }
+
     pub fn revert_action(&self, state: &mut SPState) -> SPResult<()> {
         match &self.state_path {
             Some(sp) => state.revert_next(sp),
@@ -403,17 +557,7 @@ impl Action {
     }
 
     pub fn val_to_string(&self) -> String {
-        match &self.value {
-            Compute::PredicateValue(PredicateValue::SPValue(v)) => v.to_string(),
-            Compute::PredicateValue(PredicateValue::SPPath(p, _)) => p.to_string(),
-            Compute::Predicate(p) => p.to_string(),
-            Compute::Function(xs) => xs.iter().fold(String::default(), |acc, (p, v)| {
-                format!("{acc}[if {p} then {v}]")
-            }),
-            Compute::Any => "?".to_string(),
-            Compute::Random(n) => format!("rnd({n})"),
-            Compute::TimeStamp => "T".to_string(),
-        }
+        self.value.to_string()
     }
 
     pub fn to_string_short(&self) -> String {
@@ -552,9 +696,18 @@ impl EvaluatePredicate for Predicate {
                     );
                     false
                 }
-            } // Predicate::GT(lp, rp) => {}
-              // Predicate::LT(lp, rp) => {}
-              // Predicate::INDOMAIN(value, domain) => {}
+            }
+            Predicate::GT(lp, rp)
+            | Predicate::LT(lp, rp)
+            | Predicate::GEQ(lp, rp)
+            | Predicate::LEQ(lp, rp) => {
+                if let (Some(a), Some(b)) = (lp.sp_value(state), rp.sp_value(state)) {
+                    ordering_holds(self, a, b)
+                } else {
+                    eprintln!("ERROR: eval in predicate {self}: path not found in\n{state}");
+                    false
+                }
+            } // Predicate::INDOMAIN(value, domain) => {}
         }
     }
 
@@ -643,9 +796,17 @@ impl EvaluatePredicate for Predicate {
                 } else {
                     false
                 }
-            } // Predicate::GT(lp, rp) => {}
-              // Predicate::LT(lp, rp) => {}
-              // Predicate::INDOMAIN(value, domain) => {}
+            }
+            Predicate::GT(lp, rp)
+            | Predicate::LT(lp, rp)
+            | Predicate::GEQ(lp, rp)
+            | Predicate::LEQ(lp, rp) => {
+                if let (Some(a), Some(b)) = (lp.sp_value2(state), rp.sp_value2(state)) {
+                    ordering_holds(self, a, b)
+                } else {
+                    false
+                }
+            } // Predicate::INDOMAIN(value, domain) => {}
         }
     }
 }
@@ -686,6 +847,9 @@ impl NextAction for Action {
             Compute::Random(n) => Some(SPValue::Int32(rand::thread_rng().gen_range(0..*n))),
             Compute::TimeStamp => Some(SPValue::Time(std::time::SystemTime::now())),
             Compute::Any => None,
+            c @ (Compute::Add(..) | Compute::Sub(..)) => {
+                Some(c.arithmetic(&|pv: &PredicateValue| pv.sp_value(state).cloned())?)
+            }
         };
 
         if let Some(c) = c {
@@ -733,6 +897,9 @@ impl NextAction for Action {
             Compute::Random(n) => Some(SPValue::Int32(rand::thread_rng().gen_range(0..*n))),
             Compute::TimeStamp => Some(SPValue::Time(std::time::SystemTime::now())),
             Compute::Any => None,
+            c @ (Compute::Add(..) | Compute::Sub(..)) => {
+                Some(c.arithmetic(&|pv: &PredicateValue| pv.sp_value2(state).cloned())?)
+            }
         };
 
         if let Some(c) = c {
@@ -864,6 +1031,118 @@ macro_rules! p {
         )
     }};
 
+    ([ $lhs:expr ] > [ $rhs:expr ]) => {{
+        Predicate::GT(
+            $lhs .to_predicate_value(),
+            $rhs .to_predicate_value(),
+        )
+    }};
+
+    ([ $lhs:expr ] > $($rhs:tt).+) => {{
+        Predicate::GT(
+            $lhs .to_predicate_value(),
+            $($rhs).+ .to_predicate_value(),
+        )
+    }};
+
+    ($($lhs:tt).+ > [ $rhs:expr ]) => {{
+        Predicate::GT(
+            $($lhs).+ .to_predicate_value(),
+            $rhs .to_predicate_value(),
+        )
+    }};
+
+    ($($lhs:tt).+ > $($rhs:tt).+) => {{
+        Predicate::GT(
+            $($lhs).+ .to_predicate_value(),
+            $($rhs).+ .to_predicate_value(),
+        )
+    }};
+
+    ([ $lhs:expr ] < [ $rhs:expr ]) => {{
+        Predicate::LT(
+            $lhs .to_predicate_value(),
+            $rhs .to_predicate_value(),
+        )
+    }};
+
+    ([ $lhs:expr ] < $($rhs:tt).+) => {{
+        Predicate::LT(
+            $lhs .to_predicate_value(),
+            $($rhs).+ .to_predicate_value(),
+        )
+    }};
+
+    ($($lhs:tt).+ < [ $rhs:expr ]) => {{
+        Predicate::LT(
+            $($lhs).+ .to_predicate_value(),
+            $rhs .to_predicate_value(),
+        )
+    }};
+
+    ($($lhs:tt).+ < $($rhs:tt).+) => {{
+        Predicate::LT(
+            $($lhs).+ .to_predicate_value(),
+            $($rhs).+ .to_predicate_value(),
+        )
+    }};
+
+    ([ $lhs:expr ] >= [ $rhs:expr ]) => {{
+        Predicate::GEQ(
+            $lhs .to_predicate_value(),
+            $rhs .to_predicate_value(),
+        )
+    }};
+
+    ([ $lhs:expr ] >= $($rhs:tt).+) => {{
+        Predicate::GEQ(
+            $lhs .to_predicate_value(),
+            $($rhs).+ .to_predicate_value(),
+        )
+    }};
+
+    ($($lhs:tt).+ >= [ $rhs:expr ]) => {{
+        Predicate::GEQ(
+            $($lhs).+ .to_predicate_value(),
+            $rhs .to_predicate_value(),
+        )
+    }};
+
+    ($($lhs:tt).+ >= $($rhs:tt).+) => {{
+        Predicate::GEQ(
+            $($lhs).+ .to_predicate_value(),
+            $($rhs).+ .to_predicate_value(),
+        )
+    }};
+
+    ([ $lhs:expr ] <= [ $rhs:expr ]) => {{
+        Predicate::LEQ(
+            $lhs .to_predicate_value(),
+            $rhs .to_predicate_value(),
+        )
+    }};
+
+    ([ $lhs:expr ] <= $($rhs:tt).+) => {{
+        Predicate::LEQ(
+            $lhs .to_predicate_value(),
+            $($rhs).+ .to_predicate_value(),
+        )
+    }};
+
+    ($($lhs:tt).+ <= [ $rhs:expr ]) => {{
+        Predicate::LEQ(
+            $($lhs).+ .to_predicate_value(),
+            $rhs .to_predicate_value(),
+        )
+    }};
+
+    ($($lhs:tt).+ <= $($rhs:tt).+) => {{
+        Predicate::LEQ(
+            $($lhs).+ .to_predicate_value(),
+            $($rhs).+ .to_predicate_value(),
+        )
+    }};
+
     // negation
     (! $($inner:tt)+ ) => {{
         // println!("matched negation: {}", stringify!($($inner)+));
@@ -899,8 +1178,88 @@ pub fn unary_expr_to_action(lhs: &PredicateValue, c: Compute) -> Action {
     }
 }
 
+/// An action assigning `lhs` the result of `op` applied to `x` and `y`.
+pub fn arithmetic_expr_to_action(
+    lhs: &PredicateValue, x: &PredicateValue, y: &PredicateValue,
+    op: fn(Compute, Compute) -> Compute,
+) -> Action {
+    let c = op(
+        Compute::PredicateValue(x.clone()),
+        Compute::PredicateValue(y.clone()),
+    );
+    unary_expr_to_action(lhs, c)
+}
+
 #[macro_export]
 macro_rules! a {
+    ([ $lhs:expr ] += [ $rhs:expr ]) => {{
+        let lhs = $lhs .to_predicate_value();
+        let rhs = $rhs .to_predicate_value();
+        arithmetic_expr_to_action(&lhs, &lhs, &rhs, Compute::sum)
+    }};
+
+    ([ $lhs:expr ] += $($rhs:tt).+) => {{
+        let lhs = $lhs .to_predicate_value();
+        let rhs = $($rhs).+ .to_predicate_value();
+        arithmetic_expr_to_action(&lhs, &lhs, &rhs, Compute::sum)
+    }};
+
+    ($($lhs:tt).+ += [ $rhs:expr ]) => {{
+        let lhs = $($lhs).+ .to_predicate_value();
+        let rhs = $rhs .to_predicate_value();
+        arithmetic_expr_to_action(&lhs, &lhs, &rhs, Compute::sum)
+    }};
+
+    ($($lhs:tt).+ += $($rhs:tt).+) => {{
+        let lhs = $($lhs).+ .to_predicate_value();
+        let rhs = $($rhs).+ .to_predicate_value();
+        arithmetic_expr_to_action(&lhs, &lhs, &rhs, Compute::sum)
+    }};
+
+    ([ $lhs:expr ] -= [ $rhs:expr ]) => {{
+        let lhs = $lhs .to_predicate_value();
+        let rhs = $rhs .to_predicate_value();
+        arithmetic_expr_to_action(&lhs, &lhs, &rhs, Compute::difference)
+    }};
+
+    ([ $lhs:expr ] -= $($rhs:tt).+) => {{
+        let lhs = $lhs .to_predicate_value();
+        let rhs = $($rhs).+ .to_predicate_value();
+        arithmetic_expr_to_action(&lhs, &lhs, &rhs, Compute::difference)
+    }};
+
+    ($($lhs:tt).+ -= [ $rhs:expr ]) => {{
+        let lhs = $($lhs).+ .to_predicate_value();
+        let rhs = $rhs .to_predicate_value();
+        arithmetic_expr_to_action(&lhs, &lhs, &rhs, Compute::difference)
+    }};
+
+    ($($lhs:tt).+ -= $($rhs:tt).+) => {{
+        let lhs = $($lhs).+ .to_predicate_value();
+        let rhs = $($rhs).+ .to_predicate_value();
+        arithmetic_expr_to_action(&lhs, &lhs, &rhs, Compute::difference)
+    }};
+
+    ([ $lhs:expr ] = [ $x:expr ] + [ $y:expr ]) => {{
+        let lhs = $lhs .to_predicate_value();
+        arithmetic_expr_to_action(&lhs, &$x .to_predicate_value(), &$y .to_predicate_value(), Compute::sum)
+    }};
+
+    ($($lhs:tt).+ = $($x:tt).+ + $($y:tt).+) => {{
+        let lhs = $($lhs).+ .to_predicate_value();
+        arithmetic_expr_to_action(&lhs, &$($x).+ .to_predicate_value(), &$($y).+ .to_predicate_value(), Compute::sum)
+    }};
+
+    ([ $lhs:expr ] = [ $x:expr ] - [ $y:expr ]) => {{
+        let lhs = $lhs .to_predicate_value();
+        arithmetic_expr_to_action(&lhs, &$x .to_predicate_value(), &$y .to_predicate_value(), Compute::difference)
+    }};
+
+    ($($lhs:tt).+ = $($x:tt).+ - $($y:tt).+) => {{
+        let lhs = $($lhs).+ .to_predicate_value();
+        arithmetic_expr_to_action(&lhs, &$($x).+ .to_predicate_value(), &$($y).+ .to_predicate_value(), Compute::difference)
+    }};
+
     ([ $lhs:expr ] = [ $rhs:expr ]) => {{
         let lhs = $lhs .to_predicate_value();
         let rhs = $rhs .to_predicate_value();
@@ -1027,6 +1386,73 @@ mod sp_value_test {
         assert!(!eq2.eval(&s));
     }
 
+    #[test]
+    fn eval_ordering() {
+        let s = state!(["a", "b"] => 2, ["a", "c"] => 2.5, ["k", "l"] => "two");
+        let ab = SPPath::from(&["a", "b"]);
+        let ac = SPPath::from(&["a", "c"]);
+        let kl = SPPath::from(&["k", "l"]);
+        assert!(p!(ab > 1).eval(&s));
+        assert!(!p!(ab > 2).eval(&s));
+        assert!(p!(ab >= 2).eval(&s));
+        assert!(p!(ab < ac).eval(&s));
+        assert!(p!(ac <= 2.5).eval(&s));
+        assert!(!p!([ab] < [1]).eval(&s));
+        // only numbers can be compared
+        assert!(!p!(kl > 1).eval(&s));
+        assert!(!p!(kl <= 1).eval(&s));
+    }
+
+    #[test]
+    fn arithmetic_actions() {
+        let x = SPPath::from("x");
+        let y = SPPath::from("y");
+        let mut s = state!(x => 2, y => 3);
+
+        let a = a!(x += 1);
+        assert_eq!(
+            a,
+            Action::new(
+                x.clone(),
+                Compute::sum(
+                    Compute::PredicateValue(PredicateValue::SPPath(x.clone(), None)),
+                    Compute::PredicateValue(PredicateValue::SPValue(1.to_spvalue())),
+                )
+            )
+        );
+        assert_eq!(a.to_string(), "x := (x + 1)");
+        a.next(&mut s).unwrap();
+        s.take_transition();
+        assert_eq!(s.sp_value_from_path(&x), Some(&3.to_spvalue()));
+
+        a!(x = y - x).next(&mut s).unwrap();
+        s.take_transition();
+        assert_eq!(s.sp_value_from_path(&x), Some(&0.to_spvalue()));
+
+        a!([x] -= [2.5]).next(&mut s).unwrap();
+        s.take_transition();
+        assert_eq!(s.sp_value_from_path(&x), Some(&(-2.5).to_spvalue()));
+
+        let mut s = state!(x => i32::MAX, y => "three");
+        assert!(a!(x += 1).next(&mut s).is_err());
+        assert!(a!(x = y + 1).next(&mut s).is_err());
+
+        let mut nested = Action::new(
+            x.clone(),
+            Compute::difference(
+                Compute::sum(
+                    Compute::PredicateValue(PredicateValue::SPPath(y.clone(), None)),
+                    Compute::PredicateValue(PredicateValue::SPValue(1.to_spvalue())),
+                ),
+                Compute::PredicateValue(PredicateValue::SPPath(x.clone(), None)),
+            ),
+        );
+        assert_eq!(nested.value.support(), vec![x.clone(), y.clone()]);
+        let mapping: HashMap<_, _> = vec![(y, SPPath::from("z"))].into_iter().collect();
+        nested.replace_variable_path(&mapping);
+        assert_eq!(nested.value.support(), vec![x, SPPath::from("z")]);
+    }
+
     #[test]
     fn support_pred() {
         let ab = SPPath::from(&["a", "b"]);
@@ -2,6 +2,7 @@ use super::*;
 use Predicate::*;
 use PredicateValue::SPPath as PVP;
 use PredicateValue::SPValue as PVV;
+use std::fmt;
 
 peg::parser!(pub grammar pred_parser() for str {
     rule _() =  quiet!{[' ' | '\t']*}
@@ -20,14 +21,34 @@ peg::parser!(pub grammar pred_parser() for str {
         / _ n:$(['a'..='z' | 'A'..='Z' | '_']+) _ { PVV(n.to_spvalue()) }
         / _ "\"" n:$(!['"'] [_])* "\"" _ { PVV(
             n.into_iter().collect::<Vec<_>>().join("").to_spvalue()) }
-        / _ n:$(['0'..='9']+) _ { let i: i32 = n.parse().unwrap();
+        / _ n:$("-"? ['0'..='9']+ "." ['0'..='9']+) _ { let f: f32 = n.parse().unwrap();
+                              PVV(f.to_spvalue())
+    }
+        / _ n:$("-"? ['0'..='9']+) _ { let i: i32 = n.parse().unwrap();
                               PVV(i.to_spvalue())
     }
 
     pub rule eq() -> Predicate
         = p1:value() _ "==" _ p2:value() { EQ(p1,p2) }
         / p1:value() _ "!=" _ p2:value() { NEQ(p1,p2) }
-        / p:path() _ !['='|'!'] { EQ(PVP(p, None), PVV(true.to_spvalue())) }
+        / p1:value() _ ">=" _ p2:value() { GEQ(p1,p2) }
+        / p1:value() _ "<=" _ p2:value() { LEQ(p1,p2) }
+        / p1:value() _ ">" _ p2:value() { GT(p1,p2) }
+        / p1:value() _ "<" _ p2:value() { LT(p1,p2) }
+        / p:path() _ !['='|'!'|'<'|'>'] { EQ(PVP(p, None), PVV(true.to_spvalue())) }
+
+    rule compute() -> Compute
+        = _ "?" _ { Compute::Any }
+        / a:value() "+" b:value() {
+            Compute::Add(Box::new(Compute::PredicateValue(a)), Box::new(Compute::PredicateValue(b)))
+        }
+        / a:value() "-" b:value() {
+            Compute::Sub(Box::new(Compute::PredicateValue(a)), Box::new(Compute::PredicateValue(b)))
+        }
+        / v:value() { Compute::PredicateValue(v) }
+
+    pub rule action() -> Action
+        = _ p:path() _ ":=" c:compute() { Action::new(p, c) }
 
     pub rule pred() -> Predicate = precedence!{
         _ p:eq() { p }
@@ -67,6 +88,112 @@ peg::parser!(pub grammar pred_parser() for str {
     }
 });
 
+/// Writes predicates in the syntax accepted by `pred_parser`, so that
+/// they can be parsed back. Only valid if `is_parsable` holds.
+pub(crate) struct ParsablePredicate<'a>(pub &'a Predicate);
+
+fn parsable_value(v: &PredicateValue) -> String {
+    match v {
+        PVP(p, _) => format!("p:{p}"),
+        PVV(SPValue::String(s)) => format!("\"{s}\""),
+        // keep the decimals, 5.0 should not become an integer.
+        PVV(SPValue::Float32(f)) => format!("{f:?}"),
+        PVV(v) => format!("{v}"),
+    }
+}
+
+fn is_parsable_value(v: &PredicateValue) -> bool {
+    match v {
+        PVP(p, _) => {
+            !p.path.is_empty()
+                && p.path.iter().all(|n| {
+                    !n.is_empty() && n.chars().all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '/')
+                })
+        }
+        PVV(SPValue::Bool(_)) | PVV(SPValue::Int32(_)) => true,
+        PVV(SPValue::Float32(f)) => f.is_finite() && !format!("{f:?}").contains('e'),
+        PVV(SPValue::String(s)) => !s.contains('"'),
+        PVV(_) => false,
+    }
+}
+
+fn is_parsable(p: &Predicate) -> bool {
+    match p {
+        AND(x) | OR(x) => x.iter().all(is_parsable),
+        NOT(p) => is_parsable(p),
+        TRUE | FALSE => true,
+        EQ(x, y) | NEQ(x, y) | GT(x, y) | LT(x, y) | GEQ(x, y) | LEQ(x, y) => {
+            is_parsable_value(x) && is_parsable_value(y)
+        }
+        XOR(_) | TON(..) | TOFF(..) | MEMBER(..) => false,
+    }
+}
+
+impl fmt::Display for ParsablePredicate<'_> {
+    fn fmt(&self, fmtr: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let value = parsable_value;
+        let join = |x: &[Predicate], op: &str| {
+            let children: Vec<_> = x.iter().map(|p| ParsablePredicate(p).to_string()).collect();
+            format!("({})", children.join(op))
+        };
+        match self.0 {
+            AND(x) if x.is_empty() => write!(fmtr, "TRUE"),
+            OR(x) if x.is_empty() => write!(fmtr, "FALSE"),
+            AND(x) => write!(fmtr, "{}", join(x, " && ")),
+            OR(x) => write!(fmtr, "{}", join(x, " || ")),
+            NOT(p) => write!(fmtr, "!({})", ParsablePredicate(p)),
+            EQ(x, y) => write!(fmtr, "{} == {}", value(x), value(y)),
+            NEQ(x, y) => write!(fmtr, "{} != {}", value(x), value(y)),
+            GT(x, y) => write!(fmtr, "{} > {}", value(x), value(y)),
+            LT(x, y) => write!(fmtr, "{} < {}", value(x), value(y)),
+            GEQ(x, y) => write!(fmtr, "{} >= {}", value(x), value(y)),
+            LEQ(x, y) => write!(fmtr, "{} <= {}", value(x), value(y)),
+            p => write!(fmtr, "{p}"),
+        }
+    }
+}
+
+impl Predicate {
+    /// The predicate in the syntax of `pred_parser`. `None` if the
+    /// syntax can not express it, e.g. timers.
+    pub fn to_parsable_string(&self) -> Option<String> {
+        is_parsable(self).then(|| ParsablePredicate(self).to_string())
+    }
+}
+
+impl Action {
+    /// Parse an action written as `p:x := value`, `p:x := a + b`,
+    /// `p:x := a - b` or `p:x := ?`.
+    pub fn from_string(from: &str) -> Option<Self> {
+        pred_parser::action(from).ok()
+    }
+
+    /// The action in the syntax of `pred_parser::action`. `None` if the
+    /// syntax can not express it, e.g. time stamps.
+    pub fn to_parsable_string(&self) -> Option<String> {
+        let var = PVP(self.var.clone(), None);
+        if !is_parsable_value(&var) {
+            return None;
+        }
+        let binary = |a: &Compute, b: &Compute, op: &str| match (a, b) {
+            (Compute::PredicateValue(a), Compute::PredicateValue(b))
+                if is_parsable_value(a) && is_parsable_value(b) =>
+            {
+                Some(format!("{} {} {}", parsable_value(a), op, parsable_value(b)))
+            }
+            _ => None,
+        };
+        let value = match &self.value {
+            Compute::Any => Some("?".to_string()),
+            Compute::PredicateValue(v) if is_parsable_value(v) => Some(parsable_value(v)),
+            Compute::Add(a, b) => binary(a, b, "+"),
+            Compute::Sub(a, b) => binary(a, b, "-"),
+            _ => None,
+        }?;
+        Some(format!("{} := {}", parsable_value(&var), value))
+    }
+}
+
 #[test]
 fn parse_values() {
     assert_eq!(
@@ -107,6 +234,20 @@ fn parse_values() {
     );
 }
 
+#[test]
+fn parse_ordering() {
+    let x = SPPath::from("x");
+    let y = SPPath::from("y");
+    assert_eq!(pred_parser::pred("p:x > 3"), Ok(p!(x > 3)));
+    assert_eq!(pred_parser::pred("p:x >= -3"), Ok(p!(x >= [-3])));
+    assert_eq!(pred_parser::pred("p:x < 2.5"), Ok(p!(x < 2.5)));
+    assert_eq!(
+        pred_parser::pred("p:x <= p:y && p:y"),
+        Ok(p!([x <= y] && [y]))
+    );
+    assert_eq!(pred_parser::pred("p:x > 0 -> p:y"), Ok(p!([x > 0] => [y])));
+}
+
 #[test]
 fn parse_more_tests() {
     let e = "p:cylinders2/x == left";
@@ -203,3 +344,35 @@ fn parse_predicate() {
     let impl1 = "p:hej == TRUE -> (p:hopp == FALSE || TRUE)";
     assert_eq!(pred_parser::pred(impl1), Ok(impl2));
 }
+
+#[test]
+fn parse_actions() {
+    let x = SPPath::from("x");
+    let y = SPPath::from("y");
+    assert_eq!(Action::from_string("p:x := 3"), Some(a!(x = 3)));
+    assert_eq!(Action::from_string("p:x := p:y"), Some(a!(x = y)));
+    assert_eq!(Action::from_string("p:x := p:x + 1"), Some(a!(x += 1)));
+    assert_eq!(Action::from_string("p:x := p:x - 1"), Some(a!(x -= 1)));
+    assert_eq!(Action::from_string("p:x := -1"), Some(a!(x = [-1])));
+    assert_eq!(Action::from_string("p:x := ?"), Some(a!(x?)));
+    assert_eq!(Action::from_string("p:x = 3"), None);
+}
+
+#[test]
+fn write_parsable() {
+    let x = SPPath::from("x");
+    let y = SPPath::from("y");
+    let p = OR(vec![p!([x == 5.0] && [!y]), p!(y == "a b")]);
+    let s = p.to_parsable_string().unwrap();
+    assert_eq!(s, "((p:x == 5.0 && !(p:y == true)) || p:y == \"a b\")");
+    assert_eq!(pred_parser::pred(&s), Ok(p));
+
+    let timer = Predicate::TON(PredicateValue::SPPath(x.clone(), None), 10.to_predicate_value());
+    assert_eq!(timer.to_parsable_string(), None);
+
+    for a in [a!(x = "left"), a!(x -= 2), a!(x?)] {
+        let s = a.to_parsable_string().unwrap();
+        assert_eq!(Action::from_string(&s), Some(a));
+    }
+    assert_eq!(Action::new(x, Compute::TimeStamp).to_parsable_string(), None);
+}
@@ -0,0 +1,154 @@
+//! Temporal logic specifications over predicates.
+
+use super::*;
+use predicate_parser::ParsablePredicate;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+/// A temporal logic formula with predicates as atoms. Both LTL (`G`,
+/// `F`, `X`, `U`) and CTL (`AG`, `EF`, ...) operators are included,
+/// but backends may only support one of the fragments; see `is_ltl`
+/// and `is_ctl`.
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
+pub enum Spec {
+    Pred(Predicate),
+    Not(Box<Spec>),
+    And(Vec<Spec>),
+    Or(Vec<Spec>),
+    Implies(Box<Spec>, Box<Spec>),
+
+    // LTL
+    G(Box<Spec>),
+    F(Box<Spec>),
+    X(Box<Spec>),
+    U(Box<Spec>, Box<Spec>),
+
+    // CTL
+    AG(Box<Spec>),
+    AF(Box<Spec>),
+    AX(Box<Spec>),
+    AU(Box<Spec>, Box<Spec>),
+    EG(Box<Spec>),
+    EF(Box<Spec>),
+    EX(Box<Spec>),
+    EU(Box<Spec>, Box<Spec>),
+}
+
+impl Default for Spec {
+    fn default() -> Self {
+        Spec::Pred(Predicate::TRUE)
+    }
+}
+
+impl Spec {
+    pub fn from_string(from: &str) -> Option<Self> {
+        spec_parser::spec_parser::spec(from).ok()
+    }
+
+    /// The formula does not contain any temporal operators.
+    pub fn is_propositional(&self) -> bool {
+        match self {
+            Spec::Pred(_) => true,
+            Spec::Not(s) => s.is_propositional(),
+            Spec::And(v) | Spec::Or(v) => v.iter().all(|s| s.is_propositional()),
+            Spec::Implies(a, b) => a.is_propositional() && b.is_propositional(),
+            _ => false,
+        }
+    }
+
+    /// The formula only uses LTL operators.
+    pub fn is_ltl(&self) -> bool {
+        match self {
+            Spec::Pred(_) => true,
+            Spec::Not(s) | Spec::G(s) | Spec::F(s) | Spec::X(s) => s.is_ltl(),
+            Spec::And(v) | Spec::Or(v) => v.iter().all(|s| s.is_ltl()),
+            Spec::Implies(a, b) | Spec::U(a, b) => a.is_ltl() && b.is_ltl(),
+            _ => false,
+        }
+    }
+
+    /// The formula only uses CTL operators.
+    pub fn is_ctl(&self) -> bool {
+        match self {
+            Spec::Pred(_) => true,
+            Spec::Not(s)
+            | Spec::AG(s)
+            | Spec::AF(s)
+            | Spec::AX(s)
+            | Spec::EG(s)
+            | Spec::EF(s)
+            | Spec::EX(s) => s.is_ctl(),
+            Spec::And(v) | Spec::Or(v) => v.iter().all(|s| s.is_ctl()),
+            Spec::Implies(a, b) | Spec::AU(a, b) | Spec::EU(a, b) => a.is_ctl() && b.is_ctl(),
+            _ => false,
+        }
+    }
+
+    /// If the formula states that a predicate should always hold,
+    /// i.e. is on the form `G p` or `AG p`, return the predicate.
+    pub fn invariant(&self) -> Option<Predicate> {
+        match self {
+            Spec::G(s) | Spec::AG(s) => match s.as_ref() {
+                Spec::Pred(p) => Some(p.clone()),
+                _ => None,
+            },
+            _ => None,
+        }
+    }
+
+    pub fn predicates(&self) -> Vec<&Predicate> {
+        match self {
+            Spec::Pred(p) => vec![p],
+            Spec::And(v) | Spec::Or(v) => v.iter().flat_map(|s| s.predicates()).collect(),
+            Spec::Implies(a, b) | Spec::U(a, b) | Spec::AU(a, b) | Spec::EU(a, b) => {
+                let mut r = a.predicates();
+                r.extend(b.predicates());
+                r
+            }
+            Spec::Not(s)
+            | Spec::G(s)
+            | Spec::F(s)
+            | Spec::X(s)
+            | Spec::AG(s)
+            | Spec::AF(s)
+            | Spec::AX(s)
+            | Spec::EG(s)
+            | Spec::EF(s)
+            | Spec::EX(s) => s.predicates(),
+        }
+    }
+}
+
+impl From<Predicate> for Spec {
+    fn from(p: Predicate) -> Self {
+        Spec::Pred(p)
+    }
+}
+
+impl fmt::Display for Spec {
+    fn fmt(&self, fmtr: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let join = |x: &[Spec], op: &str| {
+            let children: Vec<_> = x.iter().map(|s| s.to_string()).collect();
+            format!("({})", children.join(op))
+        };
+        match self {
+            Spec::Pred(p) => write!(fmtr, "{{{}}}", ParsablePredicate(p)),
+            Spec::Not(s) => write!(fmtr, "!{s}"),
+            Spec::And(x) => write!(fmtr, "{}", join(x, " & ")),
+            Spec::Or(x) => write!(fmtr, "{}", join(x, " | ")),
+            Spec::Implies(a, b) => write!(fmtr, "({a} -> {b})"),
+            Spec::G(s) => write!(fmtr, "G {s}"),
+            Spec::F(s) => write!(fmtr, "F {s}"),
+            Spec::X(s) => write!(fmtr, "X {s}"),
+            Spec::U(a, b) => write!(fmtr, "({a} U {b})"),
+            Spec::AG(s) => write!(fmtr, "AG {s}"),
+            Spec::AF(s) => write!(fmtr, "AF {s}"),
+            Spec::AX(s) => write!(fmtr, "AX {s}"),
+            Spec::AU(a, b) => write!(fmtr, "A[{a} U {b}]"),
+            Spec::EG(s) => write!(fmtr, "EG {s}"),
+            Spec::EF(s) => write!(fmtr, "EF {s}"),
+            Spec::EX(s) => write!(fmtr, "EX {s}"),
+            Spec::EU(a, b) => write!(fmtr, "E[{a} U {b}]"),
+        }
+    }
+}
@@ -0,0 +1,134 @@
+use super::*;
+use predicate_parser::pred_parser;
+use Spec::*;
+
+fn bx(s: Spec) -> Box<Spec> {
+    Box::new(s)
+}
+
+peg::parser!(pub grammar spec_parser() for str {
+    rule _() =  quiet!{[' ' | '\t' | '\n']*}
+
+    // predicates are written within braces using the predicate syntax.
+    rule atom() -> Spec =
+        "{" p:$((!['}'] [_])*) "}" {?
+            pred_parser::pred(p).map(Pred).or(Err("predicate"))
+        }
+
+    // the until inside the brackets is parsed as the ltl operator.
+    rule until() -> Spec
+        = "A" _ "[" _ s:spec() _ "]" {?
+            match s { U(a, c) => Ok(AU(a, c)), _ => Err("until") }
+        }
+        / "E" _ "[" _ s:spec() _ "]" {?
+            match s { U(a, c) => Ok(EU(a, c)), _ => Err("until") }
+        }
+
+    pub rule spec() -> Spec = precedence!{
+            a:@ _ "->" _ c:(@) { Implies(bx(a), bx(c)) }
+        --
+            a:@ _ "|" _ c:(@) {
+                match c {
+                    Or(x) => {
+                        let mut v = vec![a];
+                        v.extend(x);
+                        Or(v)
+                    }
+                    _ => Or(vec![a, c])
+                }
+            }
+        --
+            a:@ _ "&" _ c:(@) {
+                match c {
+                    And(x) => {
+                        let mut v = vec![a];
+                        v.extend(x);
+                        And(v)
+                    }
+                    _ => And(vec![a, c])
+                }
+            }
+        --
+            a:@ _ "U" _ c:(@) { U(bx(a), bx(c)) }
+        --
+            _ "!" _ s:@ { Not(bx(s)) }
+            _ "AG" _ s:@ { AG(bx(s)) }
+            _ "AF" _ s:@ { AF(bx(s)) }
+            _ "AX" _ s:@ { AX(bx(s)) }
+            _ "EG" _ s:@ { EG(bx(s)) }
+            _ "EF" _ s:@ { EF(bx(s)) }
+            _ "EX" _ s:@ { EX(bx(s)) }
+            _ "G" _ s:@ { G(bx(s)) }
+            _ "F" _ s:@ { F(bx(s)) }
+            _ "X" _ s:@ { X(bx(s)) }
+        --
+            _ u:until() _ { u }
+            _ "(" _ s:spec() _ ")" _ { s }
+            _ a:atom() _ { a }
+    }
+});
+
+#[test]
+fn parse_specs() {
+    let x = SPPath::from("x");
+    let y = SPPath::from("y");
+
+    assert_eq!(spec_parser::spec("{p:x}"), Ok(Pred(p!(x))));
+    assert_eq!(spec_parser::spec("G {p:x}"), Ok(G(bx(Pred(p!(x))))));
+    assert_eq!(
+        spec_parser::spec("AG ({p:x} -> EF {p:y == \"done\"})"),
+        Ok(AG(bx(Implies(
+            bx(Pred(p!(x))),
+            bx(EF(bx(Pred(p!(y == "done")))))
+        )))),
+    );
+    assert_eq!(
+        spec_parser::spec("{!p:x} U {p:y} & F {p:x} & G !{p:y}"),
+        Ok(And(vec![
+            U(bx(Pred(p!(!x))), bx(Pred(p!(y)))),
+            F(bx(Pred(p!(x)))),
+            G(bx(Not(bx(Pred(p!(y)))))),
+        ])),
+    );
+    assert_eq!(
+        spec_parser::spec("E[{p:x} U {p:y}]"),
+        Ok(EU(bx(Pred(p!(x))), bx(Pred(p!(y))))),
+    );
+    assert!(spec_parser::spec("G {p:x").is_err());
+    assert!(spec_parser::spec("{p:x ==}").is_err());
+}
+
+#[test]
+fn spec_display_roundtrip() {
+    let x = SPPath::from("x");
+    let y = SPPath::from("y");
+    let specs = vec![
+        AG(bx(Implies(
+            bx(Pred(p!(x))),
+            bx(EF(bx(Pred(p!([y == "done"] || [!x]))))),
+        ))),
+        Not(bx(And(vec![
+            U(bx(Pred(p!(x != 5))), bx(Pred(p!(y)))),
+            F(bx(Pred(Predicate::TRUE))),
+        ]))),
+        AU(
+            bx(EX(bx(Pred(p!(x))))),
+            bx(Or(vec![Pred(p!(x)), X(bx(Pred(p!(y))))])),
+        ),
+        G(bx(Pred(p!([x >= 0]
+            && [x < y]
+            && [y <= [-2]]
+            && [x > 1.5])))),
+    ];
+    for s in specs {
+        let text = s.to_string();
+        assert_eq!(Spec::from_string(&text), Some(s), "{}", text);
+    }
+    assert!(Spec::from_string("AG {p:x}").unwrap().is_ctl());
+    assert!(!Spec::from_string("AG {p:x}").unwrap().is_ltl());
+    assert!(Spec::from_string("G F {p:x}").unwrap().is_ltl());
+    assert_eq!(
+        Spec::from_string("G {p:x}").unwrap().invariant(),
+        Some(p!(x))
+    );
+}
@@ -8,6 +8,37 @@ pub struct Transition {
     pub path: SPPath,
     pub guard: Predicate,
     pub actions: Vec<Action>,
+    /// Used by cost optimal planning. Transitions without a cost are free.
+    #[serde(default)]
+    pub cost: Option<Cost>,
+}
+
+/// What it costs to take a transition, or to execute a whole plan.
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Serialize, Deserialize, Default)]
+pub struct Cost {
+    /// Abstract cost, e.g. wear or money.
+    pub cost: u32,
+    /// Execution time in milliseconds.
+    pub duration: u32,
+    pub energy: u32,
+}
+
+/// Adding saturates, a plan can not cost more than `u32::MAX`.
+impl std::ops::Add for Cost {
+    type Output = Cost;
+    fn add(self, other: Cost) -> Cost {
+        Cost {
+            cost: self.cost.saturating_add(other.cost),
+            duration: self.duration.saturating_add(other.duration),
+            energy: self.energy.saturating_add(other.energy),
+        }
+    }
+}
+
+impl std::iter::Sum for Cost {
+    fn sum<I: Iterator<Item = Cost>>(iter: I) -> Cost {
+        iter.fold(Cost::default(), |acc, c| acc + c)
+    }
 }
 
 impl Transition {
@@ -16,9 +47,15 @@ impl Transition {
             path,
             guard,
             actions,
+            cost: None,
         }
     }
 
+    pub fn with_cost(mut self, cost: Cost) -> Self {
+        self.cost = Some(cost);
+        self
+    }
+
     pub fn upd_state_path(&mut self, state: &SPState) {
         self.guard.upd_state_path(state);
         self.actions
@@ -107,4 +144,20 @@ mod test_items {
         s.take_transition();
         assert_eq!(s.sp_value_from_path(&ab).unwrap(), &3.to_spvalue());
     }
+
+    #[test]
+    fn costs_saturate() {
+        let big = Cost {
+            cost: u32::MAX,
+            duration: u32::MAX - 1,
+            energy: 1,
+        };
+        let one = Cost {
+            cost: 1,
+            duration: 2,
+            energy: 1,
+        };
+        let sum: Cost = vec![big, one].into_iter().sum();
+        assert_eq!(sum, Cost { cost: u32::MAX, duration: u32::MAX, energy: 2 });
+    }
 }
@@ -8,6 +8,10 @@ pub struct Variable {
     pub value_type: SPValueType,
     pub domain: Vec<SPValue>,
     pub initial_state: SPValue,
+    /// Bounds (inclusive) of integer variables. The domain holds
+    /// all values in the range.
+    #[serde(default)]
+    pub range: Option<(i32, i32)>,
 }
 
 impl ToPredicateValue for Variable {
@@ -29,6 +33,7 @@ impl Variable {
             value_type,
             domain,
             initial_state: SPValue::Unknown,
+            range: None,
         }
     }
 
@@ -38,6 +43,27 @@ impl Variable {
             value_type: SPValueType::Bool,
             domain: vec![false.to_spvalue(), true.to_spvalue()],
             initial_state: SPValue::Unknown,
+            range: None,
+        }
+    }
+
+    /// An integer variable taking values in `min..=max`.
+    pub fn new_range(path: SPPath, min: i32, max: i32) -> Self {
+        Self {
+            path,
+            value_type: SPValueType::Int32,
+            domain: (min..=max).map(|i| i.to_spvalue()).collect(),
+            initial_state: SPValue::Unknown,
+            range: Some((min, max)),
+        }
+    }
+
+    /// Is the value allowed by the range of this variable, if any.
+    pub fn in_range(&self, value: &SPValue) -> bool {
+        match (self.range, value) {
+            (None, _) => true,
+            (Some((min, max)), SPValue::Int32(i)) => min <= *i && *i <= max,
+            _ => false,
         }
     }
 }
@@ -0,0 +1,584 @@
+use crate::TransitionSystemModel;
+use serde::{Deserialize, Serialize};
+use sp_domain::*;
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::fs::File;
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+use super::{Planner, PlanningError, PlanningResult};
+
+/// 64 bit FNV-1a. Unlike `DefaultHasher` the result is the same
+/// across rust versions, so hashes can be used in file names and
+/// persisted keys.
+pub fn stable_hash(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf29ce484222325, |h, b| {
+        (h ^ u64::from(*b)).wrapping_mul(0x100000001b3)
+    })
+}
+
+/// Hash of everything in the model that affects planning. Cached plans
+/// are only valid for the model version they were computed for.
+pub fn model_hash(model: &TransitionSystemModel) -> u64 {
+    let mut s = String::new();
+    for v in &model.vars {
+        s += &format!(
+            "var {} {:?} {:?} {:?};",
+            v.path, v.value_type, v.domain, v.range
+        );
+    }
+    for p in &model.state_predicates {
+        s += &format!("pred {} {};", p.path, p.predicate);
+    }
+    for t in &model.transitions {
+        let actions: Vec<_> = t.actions.iter().map(|a| a.to_string()).collect();
+        s += &format!("trans {} {} {:?} {:?};", t.path, t.guard, actions, t.cost);
+    }
+    for i in &model.invariants {
+        s += &format!("inv {} {};", i.path, i.predicate);
+    }
+    for u in &model.uncontrollable {
+        s += &format!("unc {u};");
+    }
+    stable_hash(s.as_bytes())
+}
+
+/// Where cached plans are kept.
+pub trait PlanStorage: Send {
+    fn get(&self, key: &str) -> Option<PlanningResult>;
+    fn insert(&mut self, key: &str, plan: &PlanningResult) -> Result<(), PlanningError>;
+    fn remove(&mut self, key: &str) -> Result<(), PlanningError>;
+    fn keys(&self) -> Vec<String>;
+
+    /// Persist any pending changes.
+    fn flush(&mut self) -> Result<(), PlanningError> {
+        Ok(())
+    }
+}
+
+/// Keeps the plans in memory only.
+#[derive(Debug, Clone, Default)]
+pub struct MemoryStorage {
+    plans: HashMap<String, PlanningResult>,
+}
+
+impl PlanStorage for MemoryStorage {
+    fn get(&self, key: &str) -> Option<PlanningResult> {
+        self.plans.get(key).cloned()
+    }
+
+    fn insert(&mut self, key: &str, plan: &PlanningResult) -> Result<(), PlanningError> {
+        self.plans.insert(key.to_string(), plan.clone());
+        Ok(())
+    }
+
+    fn remove(&mut self, key: &str) -> Result<(), PlanningError> {
+        self.plans.remove(key);
+        Ok(())
+    }
+
+    fn keys(&self) -> Vec<String> {
+        self.plans.keys().cloned().collect()
+    }
+}
+
+/// Keeps the plans in memory and writes them all to a snappy
+/// compressed json file when flushed.
+#[derive(Debug, Clone)]
+pub struct SnapFileStorage {
+    path: PathBuf,
+    memory: MemoryStorage,
+    dirty: bool,
+}
+
+impl SnapFileStorage {
+    /// Open the file, a missing file gives an empty storage.
+    pub fn open(path: &Path) -> Result<Self, PlanningError> {
+        let mut storage = SnapFileStorage {
+            path: path.to_path_buf(),
+            memory: MemoryStorage::default(),
+            dirty: false,
+        };
+        if path.exists() {
+            let mut buffer = String::new();
+            snap::read::FrameDecoder::new(File::open(path)?).read_to_string(&mut buffer)?;
+            storage.memory.plans =
+                serde_json::from_str(&buffer).map_err(|e| PlanningError::Io(e.to_string()))?;
+        }
+        Ok(storage)
+    }
+}
+
+impl PlanStorage for SnapFileStorage {
+    fn get(&self, key: &str) -> Option<PlanningResult> {
+        self.memory.get(key)
+    }
+
+    fn insert(&mut self, key: &str, plan: &PlanningResult) -> Result<(), PlanningError> {
+        self.dirty = true;
+        self.memory.insert(key, plan)
+    }
+
+    fn remove(&mut self, key: &str) -> Result<(), PlanningError> {
+        self.dirty = true;
+        self.memory.remove(key)
+    }
+
+    fn keys(&self) -> Vec<String> {
+        self.memory.keys()
+    }
+
+    fn flush(&mut self) -> Result<(), PlanningError> {
+        if !self.dirty {
+            return Ok(());
+        }
+        if let Some(dir) = self.path.parent() {
+            std::fs::create_dir_all(dir)?;
+        }
+        let s = serde_json::to_string(&self.memory.plans)
+            .map_err(|e| PlanningError::Io(e.to_string()))?;
+        let mut writer = snap::write::FrameEncoder::new(File::create(&self.path)?);
+        writer.write_all(s.as_bytes())?;
+        writer.flush()?;
+        self.dirty = false;
+        Ok(())
+    }
+}
+
+/// Keeps the plans in a sled database.
+#[cfg(feature = "sled")]
+pub struct SledStorage {
+    db: sled::Db,
+}
+
+#[cfg(feature = "sled")]
+fn sled_error(e: sled::Error) -> PlanningError {
+    PlanningError::Io(e.to_string())
+}
+
+#[cfg(feature = "sled")]
+impl SledStorage {
+    pub fn open(path: &Path) -> Result<Self, PlanningError> {
+        Ok(SledStorage {
+            db: sled::open(path).map_err(sled_error)?,
+        })
+    }
+}
+
+#[cfg(feature = "sled")]
+impl PlanStorage for SledStorage {
+    fn get(&self, key: &str) -> Option<PlanningResult> {
+        let value = self.db.get(key).ok()??;
+        serde_json::from_slice(&value).ok()
+    }
+
+    fn insert(&mut self, key: &str, plan: &PlanningResult) -> Result<(), PlanningError> {
+        let value = serde_json::to_vec(plan).map_err(|e| PlanningError::Io(e.to_string()))?;
+        self.db.insert(key, value).map_err(sled_error)?;
+        Ok(())
+    }
+
+    fn remove(&mut self, key: &str) -> Result<(), PlanningError> {
+        self.db.remove(key).map_err(sled_error)?;
+        Ok(())
+    }
+
+    fn keys(&self) -> Vec<String> {
+        self.db
+            .iter()
+            .keys()
+            .flatten()
+            .map(|k| String::from_utf8_lossy(&k).into_owned())
+            .collect()
+    }
+
+    fn flush(&mut self) -> Result<(), PlanningError> {
+        self.db.flush().map_err(sled_error)?;
+        Ok(())
+    }
+}
+
+/// The available storage backends.
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Serialize, Deserialize, Default)]
+pub enum PlanCacheBackend {
+    #[default]
+    Memory,
+    /// One file per model version, `store-<hash>.sz`.
+    SnapFile,
+    /// A database shared by all model versions, `plan_cache.sled`.
+    #[cfg(feature = "sled")]
+    Sled,
+}
+
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
+pub struct PlanCacheConfig {
+    pub backend: PlanCacheBackend,
+    /// Where persistent backends keep their files.
+    pub directory: PathBuf,
+    /// The least recently used plans are evicted when there are more.
+    pub max_entries: Option<usize>,
+    /// Persistent backends are flushed after this many inserts, and
+    /// when the cache is dropped.
+    pub flush_every: usize,
+}
+
+impl Default for PlanCacheConfig {
+    fn default() -> Self {
+        PlanCacheConfig {
+            backend: PlanCacheBackend::Memory,
+            directory: PathBuf::from("."),
+            max_entries: Some(10000),
+            flush_every: 100,
+        }
+    }
+}
+
+impl PlanCacheConfig {
+    /// Open the cache for a model.
+    pub fn open(&self, model: &TransitionSystemModel) -> Result<PlanCache, PlanningError> {
+        let storage: Box<dyn PlanStorage> = match self.backend {
+            PlanCacheBackend::Memory => Box::new(MemoryStorage::default()),
+            PlanCacheBackend::SnapFile => {
+                let file = format!("store-{:016x}.sz", model_hash(model));
+                Box::new(SnapFileStorage::open(&self.directory.join(file))?)
+            }
+            #[cfg(feature = "sled")]
+            PlanCacheBackend::Sled => {
+                Box::new(SledStorage::open(&self.directory.join("plan_cache.sled"))?)
+            }
+        };
+        PlanCache::new(storage, self.max_entries, self.flush_every)
+    }
+}
+
+/// Hit/miss statistics of a `PlanCache`.
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Serialize, Deserialize, Default)]
+pub struct CacheMetrics {
+    pub hits: u64,
+    pub misses: u64,
+    pub inserts: u64,
+    pub evictions: u64,
+    /// Number of plans currently in the cache.
+    pub entries: usize,
+}
+
+impl CacheMetrics {
+    pub fn lookups(&self) -> u64 {
+        self.hits + self.misses
+    }
+
+    /// Fraction of lookups that were hits, 0 when nothing has been looked up.
+    pub fn hit_rate(&self) -> f64 {
+        if self.lookups() == 0 {
+            0.0
+        } else {
+            self.hits as f64 / self.lookups() as f64
+        }
+    }
+}
+
+struct CacheInner {
+    storage: Box<dyn PlanStorage>,
+    max_entries: Option<usize>,
+    flush_every: usize,
+    unflushed: usize,
+    /// Last use of each key, for lru eviction.
+    recency: HashMap<String, u64>,
+    clock: u64,
+    /// Keys that are currently being planned for.
+    pending: HashSet<String>,
+    metrics: CacheMetrics,
+}
+
+impl CacheInner {
+    fn touch(&mut self, key: &str) {
+        self.clock += 1;
+        self.recency.insert(key.to_string(), self.clock);
+    }
+
+    fn evict(&mut self) -> Result<(), PlanningError> {
+        let max = match self.max_entries {
+            Some(max) => max,
+            None => return Ok(()),
+        };
+        while self.recency.len() > max {
+            let oldest = self
+                .recency
+                .iter()
+                .min_by_key(|(_, t)| **t)
+                .map(|(k, _)| k.clone());
+            if let Some(oldest) = oldest {
+                self.recency.remove(&oldest);
+                self.storage.remove(&oldest)?;
+                self.metrics.evictions += 1;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Drop for CacheInner {
+    fn drop(&mut self) {
+        let _res = self.storage.flush();
+    }
+}
+
+/// A cache of planning results, keyed on the model version, the goals
+/// and the relevant part of the state. Cloning gives a handle to the
+/// same cache.
+///
+/// Only store optimal plans. Otherwise we might end up with two plans
+/// of different length for doing the same thing, and the redundant
+/// moves can make us livelock by moving away from the goal. Failing
+/// to find a plan is not stored either, a longer horizon or a changed
+/// model may well find one.
+#[derive(Clone)]
+pub struct PlanCache {
+    inner: Arc<Mutex<CacheInner>>,
+}
+
+impl fmt::Debug for PlanCache {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "PlanCache({:?})", self.metrics())
+    }
+}
+
+impl Default for PlanCache {
+    fn default() -> Self {
+        let config = PlanCacheConfig::default();
+        PlanCache::new(
+            Box::new(MemoryStorage::default()),
+            config.max_entries,
+            config.flush_every,
+        )
+        .expect("memory storage can not fail")
+    }
+}
+
+impl PlanCache {
+    pub fn new(
+        storage: Box<dyn PlanStorage>, max_entries: Option<usize>, flush_every: usize,
+    ) -> Result<Self, PlanningError> {
+        let mut inner = CacheInner {
+            storage,
+            max_entries,
+            flush_every,
+            unflushed: 0,
+            recency: HashMap::new(),
+            clock: 0,
+            pending: HashSet::new(),
+            metrics: CacheMetrics::default(),
+        };
+        for key in inner.storage.keys() {
+            inner.touch(&key);
+        }
+        inner.evict()?;
+        Ok(PlanCache {
+            inner: Arc::new(Mutex::new(inner)),
+        })
+    }
+
+    /// The key of a planning request. Only the model variables of the
+    /// state are used. The horizon and the planner are part of the
+    /// key, as they decide which plan, if any, is found.
+    pub fn key(
+        model: &TransitionSystemModel, goals: &[(Predicate, Option<Predicate>)], state: &SPState,
+        disabled: &[SPPath], max_steps: u32, planner: &dyn Planner,
+    ) -> String {
+        let mut request = format!(
+            "planner {} {:?} {max_steps};",
+            planner.name(),
+            planner.objective()
+        );
+        for (g, i) in goals {
+            match i {
+                Some(i) => request += &format!("goal {g} {i};"),
+                None => request += &format!("goal {g};"),
+            }
+        }
+        for v in &model.vars {
+            match state.sp_value_from_path(&v.path) {
+                Some(value) => request += &format!("{}={value};", v.path),
+                None => request += &format!("{}=?;", v.path),
+            }
+        }
+        for d in disabled {
+            request += &format!("disabled {d};");
+        }
+        format!(
+            "{:016x}-{:016x}",
+            model_hash(model),
+            stable_hash(request.as_bytes())
+        )
+    }
+
+    pub fn get(&self, key: &str) -> Option<PlanningResult> {
+        let mut inner = self.inner.lock().unwrap();
+        match inner.storage.get(key) {
+            Some(plan) => {
+                inner.metrics.hits += 1;
+                inner.touch(key);
+                Some(plan)
+            }
+            None => {
+                inner.metrics.misses += 1;
+                None
+            }
+        }
+    }
+
+    pub fn insert(&self, key: &str, plan: &PlanningResult) -> Result<(), PlanningError> {
+        let mut inner = self.inner.lock().unwrap();
+        inner.pending.remove(key);
+        inner.storage.insert(key, plan)?;
+        inner.touch(key);
+        inner.metrics.inserts += 1;
+        inner.evict()?;
+        inner.unflushed += 1;
+        if inner.unflushed >= inner.flush_every {
+            inner.unflushed = 0;
+            inner.storage.flush()?;
+        }
+        Ok(())
+    }
+
+    /// Mark that a plan for `key` is being computed. Returns false if
+    /// the plan is already cached or someone else is computing it.
+    pub fn claim(&self, key: &str) -> bool {
+        let mut inner = self.inner.lock().unwrap();
+        if inner.recency.contains_key(key) {
+            return false;
+        }
+        inner.pending.insert(key.to_string())
+    }
+
+    /// Give up a claim without inserting a plan.
+    pub fn release(&self, key: &str) {
+        self.inner.lock().unwrap().pending.remove(key);
+    }
+
+    pub fn flush(&self) -> Result<(), PlanningError> {
+        let mut inner = self.inner.lock().unwrap();
+        inner.unflushed = 0;
+        inner.storage.flush()
+    }
+
+    pub fn metrics(&self) -> CacheMetrics {
+        let inner = self.inner.lock().unwrap();
+        CacheMetrics {
+            entries: inner.recency.len(),
+            ..inner.metrics
+        }
+    }
+}
+
+#[cfg(test)]
+mod cache_tests {
+    use super::*;
+    use crate::planning::{ExplicitPlanner, NuXmvPlanner, PlanObjective};
+
+    fn model() -> TransitionSystemModel {
+        let x = SPPath::from("x");
+        let mut tsm = TransitionSystemModel::default();
+        tsm.vars.push(Variable::new_boolean(x.clone()));
+        tsm.transitions
+            .push(Transition::new("set_x".into(), p!(!x), vec![a!(x)]));
+        tsm
+    }
+
+    fn result(plan_length: u32) -> PlanningResult {
+        PlanningResult {
+            plan_found: true,
+            plan_length,
+            ..PlanningResult::default()
+        }
+    }
+
+    #[test]
+    fn stable_keys() {
+        assert_eq!(stable_hash(b""), 0xcbf29ce484222325);
+        assert_eq!(stable_hash(b"a"), 0xaf63dc4c8601ec8c);
+
+        let tsm = model();
+        let x = SPPath::from("x");
+        let goals = [(p!(x), None)];
+        let planner = ExplicitPlanner::new(PlanObjective::Steps);
+        let key = |tsm: &TransitionSystemModel, state: &SPState| {
+            PlanCache::key(tsm, &goals, state, &[], 10, &planner)
+        };
+        let k = key(&tsm, &state!(x => false));
+        // other variables in the state are ignored
+        let y = SPPath::from("y");
+        assert_eq!(k, key(&tsm, &state!(x => false, y => 3)));
+        assert_ne!(k, key(&tsm, &state!(x => true)));
+
+        // compiling the model against a state does not change the key
+        let mut compiled = tsm.clone();
+        compiled
+            .transitions
+            .iter_mut()
+            .for_each(|t| t.upd_state_path(&state!(x => false)));
+        assert_eq!(k, key(&compiled, &state!(x => false)));
+
+        let mut changed = tsm.clone();
+        changed.vars.push(Variable::new_boolean("z".into()));
+        assert_ne!(k, key(&changed, &state!(x => false)));
+
+        // the horizon and the planner decide which plan is found
+        let state = state!(x => false);
+        assert_ne!(k, PlanCache::key(&tsm, &goals, &state, &[], 20, &planner));
+        let cheapest = ExplicitPlanner::new(PlanObjective::Cost);
+        assert_ne!(k, PlanCache::key(&tsm, &goals, &state, &[], 10, &cheapest));
+        let nuxmv = NuXmvPlanner::default();
+        assert_ne!(k, PlanCache::key(&tsm, &goals, &state, &[], 10, &nuxmv));
+    }
+
+    #[test]
+    fn lru_eviction_and_metrics() {
+        let cache = PlanCache::new(Box::new(MemoryStorage::default()), Some(2), 1).unwrap();
+        cache.insert("a", &result(1)).unwrap();
+        cache.insert("b", &result(2)).unwrap();
+        assert_eq!(cache.get("a"), Some(result(1)));
+        cache.insert("c", &result(3)).unwrap();
+
+        // b was used least recently
+        assert_eq!(cache.get("b"), None);
+        assert_eq!(cache.get("c"), Some(result(3)));
+        assert_eq!(
+            cache.metrics(),
+            CacheMetrics {
+                hits: 2,
+                misses: 1,
+                inserts: 3,
+                evictions: 1,
+                entries: 2,
+            }
+        );
+
+        assert!(!cache.claim("a"));
+        assert!(cache.claim("d"));
+        assert!(!cache.claim("d"));
+        cache.release("d");
+        assert!(cache.claim("d"));
+    }
+
+    #[test]
+    fn snap_file_storage() {
+        let dir = tempfile::tempdir().unwrap();
+        let config = PlanCacheConfig {
+            backend: PlanCacheBackend::SnapFile,
+            directory: dir.path().to_path_buf(),
+            ..PlanCacheConfig::default()
+        };
+        let tsm = model();
+        {
+            let cache = config.open(&tsm).unwrap();
+            cache.insert("a", &result(1)).unwrap();
+        }
+        let cache = config.open(&tsm).unwrap();
+        assert_eq!(cache.get("a"), Some(result(1)));
+        assert_eq!(cache.metrics().entries, 1);
+    }
+}
@@ -0,0 +1,52 @@
+use crate::TransitionSystemModel;
+use serde::{Deserialize, Serialize};
+use sp_domain::*;
+
+use super::PlanningFrame;
+
+/// What a planner optimizes for.
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Serialize, Deserialize, Default)]
+pub enum PlanObjective {
+    /// The fewest number of transitions.
+    #[default]
+    Steps,
+    /// The lowest total `Cost::cost`.
+    Cost,
+    /// The lowest total `Cost::duration`. This is not the makespan, the
+    /// runner may execute independent transitions at the same time.
+    TotalDuration,
+    /// The lowest total `Cost::energy`.
+    Energy,
+}
+
+impl PlanObjective {
+    /// What taking the transition adds to the objective.
+    pub fn weight(&self, t: &Transition) -> u32 {
+        let cost = t.cost.unwrap_or_default();
+        match self {
+            PlanObjective::Steps => 1,
+            PlanObjective::Cost => cost.cost,
+            PlanObjective::TotalDuration => cost.duration,
+            PlanObjective::Energy => cost.energy,
+        }
+    }
+
+    /// The value of the objective for a plan with total cost `cost`.
+    pub fn value(&self, cost: &Cost, plan_length: u32) -> u32 {
+        match self {
+            PlanObjective::Steps => plan_length,
+            PlanObjective::Cost => cost.cost,
+            PlanObjective::TotalDuration => cost.duration,
+            PlanObjective::Energy => cost.energy,
+        }
+    }
+}
+
+/// The summed cost of the transitions taken in the trace.
+pub fn plan_cost(model: &TransitionSystemModel, trace: &[PlanningFrame]) -> Cost {
+    trace
+        .iter()
+        .flat_map(|f| model.transitions.iter().find(|t| t.path == f.transition))
+        .map(|t| t.cost.unwrap_or_default())
+        .sum()
+}
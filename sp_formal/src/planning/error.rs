@@ -0,0 +1,58 @@
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::time::Duration;
+
+/// Everything that can go wrong when planning or model checking.
+#[derive(Debug, PartialEq, Serialize, Deserialize, Clone)]
+pub enum PlanningError {
+    /// No nuXmv (or NuSMV) binary could be found.
+    BinaryNotFound,
+    /// The solver did not finish in time.
+    Timeout(Duration),
+    /// Planning was cancelled before it finished.
+    Cancelled,
+    /// The solver rejected the generated model.
+    Syntax {
+        line: usize,
+        message: String,
+        model_line: String,
+    },
+    /// The solver output could not be understood.
+    TraceParse(String),
+    /// The model uses something the planner can not encode.
+    Unsupported(String),
+    /// The planning request itself is malformed, e.g. variables are
+    /// missing from the state.
+    InvalidProblem(String),
+    /// The solver process exited abnormally.
+    ProcessFailed(String),
+    Io(String),
+}
+
+impl fmt::Display for PlanningError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PlanningError::BinaryNotFound => write!(f, "nu[X|x|s]mv not found. check your path!"),
+            PlanningError::Timeout(d) => write!(f, "planning timed out after {}ms", d.as_millis()),
+            PlanningError::Cancelled => write!(f, "planning was cancelled"),
+            PlanningError::Syntax {
+                line,
+                message,
+                model_line,
+            } => write!(f, "syntax problem at line {line}: {message}\n{model_line}"),
+            PlanningError::TraceParse(s) => write!(f, "could not parse the trace: {s}"),
+            PlanningError::Unsupported(s) => write!(f, "unsupported: {s}"),
+            PlanningError::InvalidProblem(s) => write!(f, "invalid planning problem: {s}"),
+            PlanningError::ProcessFailed(s) => write!(f, "the solver failed: {s}"),
+            PlanningError::Io(s) => write!(f, "io error: {s}"),
+        }
+    }
+}
+
+impl std::error::Error for PlanningError {}
+
+impl From<std::io::Error> for PlanningError {
+    fn from(e: std::io::Error) -> Self {
+        PlanningError::Io(e.to_string())
+    }
+}
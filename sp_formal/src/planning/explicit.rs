@@ -0,0 +1,488 @@
+use crate::planning::*;
+use std::cmp::Reverse;
+use std::collections::{BinaryHeap, HashMap};
+use std::time::Instant;
+
+/// A pure rust planner that does a breadth first search over the
+/// explicit state space of the model. It follows the same semantics
+/// as the nuXmv encoding: exactly one transition is taken per step,
+/// variables not modified by the transition keep their values, and
+/// every visited state must satisfy the global invariants.
+///
+/// Each goal is tracked separately as in the nuXmv LTL encoding, i.e.
+/// all goals need to be reached at some point along the plan, and the
+/// optional invariant of a goal needs to hold until it is reached.
+///
+/// The search is uniform-cost over the weights given by the
+/// objective, ties are broken by the number of steps. With the
+/// default objective this is a breadth first search.
+///
+/// Only use this for small models, the state space is enumerated.
+#[derive(Debug, Clone, Default)]
+pub struct ExplicitPlanner {
+    pub objective: PlanObjective,
+}
+
+impl ExplicitPlanner {
+    pub fn new(objective: PlanObjective) -> Self {
+        ExplicitPlanner { objective }
+    }
+}
+
+#[derive(Debug, Clone)]
+struct SearchNode {
+    state: SPState,
+    reached: Vec<bool>,
+    parent: Option<usize>,
+    transition: SPPath,
+}
+
+fn state_key(model: &TransitionSystemModel, state: &SPState, reached: &[bool]) -> String {
+    let values: Vec<_> = model
+        .vars
+        .iter()
+        .map(|v| state.sp_value_from_path(&v.path))
+        .collect();
+    format!("{values:?}{reached:?}")
+}
+
+pub(super) fn update_state_predicates(model: &TransitionSystemModel, state: &mut SPState) {
+    let values: Vec<_> = model
+        .state_predicates
+        .iter()
+        .map(|p| (p.path.clone(), p.predicate.eval(state).to_spvalue()))
+        .collect();
+    state.add_variables(values);
+}
+
+fn invariants_hold(model: &TransitionSystemModel, state: &SPState) -> bool {
+    model.invariants.iter().all(|i| i.predicate.eval(state))
+}
+
+/// Update which goals have been reached in this state. Returns false
+/// if an invariant of a goal that is yet to be reached is violated.
+fn update_goals(
+    goals: &[(Predicate, Option<Predicate>)], state: &SPState, reached: &mut [bool],
+) -> bool {
+    for (i, (goal, invar)) in goals.iter().enumerate() {
+        if reached[i] {
+            continue;
+        }
+        if goal.eval(state) {
+            reached[i] = true;
+        } else if let Some(invar) = invar {
+            if !invar.eval(state) {
+                return false;
+            }
+        }
+    }
+    true
+}
+
+/// Compute all successor states when taking transition `t` in `state`.
+/// Actions that assign `Compute::Any` branch over the domain of the
+/// variable. As in nuXmv, the transition can not be taken if it would
+/// leave the range of an integer variable.
+fn successors(model: &TransitionSystemModel, t: &Transition, state: &SPState) -> Vec<SPState> {
+    if !t.eval(state) {
+        return vec![];
+    }
+
+    let mut next = state.clone();
+    if t.next(&mut next).is_err() {
+        return vec![];
+    }
+    let out_of_range = t.actions.iter().any(|a| {
+        let var = model.vars.iter().find(|v| v.path == a.var);
+        let value = next
+            .state_value_from_path(&a.var)
+            .and_then(|v| v.next_value().as_ref());
+        matches!((var, value), (Some(var), Some(value)) if !var.in_range(value))
+    });
+    if out_of_range {
+        return vec![];
+    }
+
+    let mut states = vec![next];
+    for a in t.actions.iter().filter(|a| a.value == Compute::Any) {
+        let domain = model
+            .vars
+            .iter()
+            .find(|v| v.path == a.var)
+            .map(|v| v.domain.clone())
+            .unwrap_or_default();
+        states = states
+            .into_iter()
+            .flat_map(|s| {
+                domain.iter().flat_map(move |value| {
+                    let mut s = s.clone();
+                    s.next_from_path(&a.var, value.clone()).ok().map(|_| s)
+                })
+            })
+            .collect();
+    }
+
+    states
+        .into_iter()
+        .map(|mut s| {
+            s.take_transition();
+            update_state_predicates(model, &mut s);
+            s
+        })
+        .collect()
+}
+
+fn make_trace(nodes: &[SearchNode], last: usize) -> Vec<PlanningFrame> {
+    let mut trace = Vec::new();
+    let mut current = Some(last);
+    while let Some(idx) = current {
+        let node = &nodes[idx];
+        trace.push(PlanningFrame {
+            state: node.state.clone(),
+            transition: node.transition.clone(),
+        });
+        current = node.parent;
+    }
+    trace.reverse();
+    trace
+}
+
+impl Planner for ExplicitPlanner {
+    fn name(&self) -> &str {
+        "explicit"
+    }
+
+    fn objective(&self) -> PlanObjective {
+        self.objective
+    }
+
+    fn plan_cancellable(
+        &mut self, model: &TransitionSystemModel, goals: &[(Predicate, Option<Predicate>)],
+        state: &SPState, max_steps: u32, cancel: &CancellationToken,
+    ) -> Result<PlanningResult, PlanningError> {
+        let start = Instant::now();
+
+        let paths: Vec<_> = model.vars.iter().map(|v| v.path.clone()).collect();
+        if let Some(missing) = paths.iter().find(|p| state.sp_value_from_path(p).is_none()) {
+            return Err(PlanningError::InvalidProblem(format!(
+                "all variables need a valuation! missing {missing}"
+            )));
+        }
+        let mut initial = state.filter_by_paths(&paths);
+        update_state_predicates(model, &mut initial);
+
+        let no_plan = |duration| PlanningResult {
+            plan_found: false,
+            plan_length: 0,
+            trace: vec![PlanningFrame {
+                transition: "".into(),
+                state: state.clone(),
+            }],
+            time_to_solve: duration,
+            cost: Cost::default(),
+        };
+
+        let mut reached = vec![false; goals.len()];
+        if !invariants_hold(model, &initial) || !update_goals(goals, &initial, &mut reached) {
+            return Ok(no_plan(start.elapsed()));
+        }
+
+        let mut nodes = vec![SearchNode {
+            state: initial,
+            reached,
+            parent: None,
+            transition: SPPath::default(),
+        }];
+        // the cheapest way found to each state, skip states that can
+        // only be reached by paths that are both more expensive and longer.
+        let mut visited: HashMap<String, (u64, u32)> = HashMap::new();
+        visited.insert(state_key(model, &nodes[0].state, &nodes[0].reached), (0, 0));
+
+        let mut queue = BinaryHeap::new();
+        queue.push(Reverse((0u64, 0u32, 0usize)));
+
+        while let Some(Reverse((cost, depth, idx))) = queue.pop() {
+            cancel.check()?;
+            if nodes[idx].reached.iter().all(|r| *r) {
+                let trace = make_trace(&nodes, idx);
+                return Ok(PlanningResult {
+                    plan_found: true,
+                    plan_length: trace.len() as u32 - 1,
+                    cost: plan_cost(model, &trace),
+                    trace,
+                    time_to_solve: start.elapsed(),
+                });
+            }
+            if depth >= max_steps {
+                continue;
+            }
+
+            for t in &model.transitions {
+                let next_cost = cost + self.objective.weight(t) as u64;
+                for next in successors(model, t, &nodes[idx].state) {
+                    if !invariants_hold(model, &next) {
+                        continue;
+                    }
+                    let mut reached = nodes[idx].reached.clone();
+                    if !update_goals(goals, &next, &mut reached) {
+                        continue;
+                    }
+                    let key = state_key(model, &next, &reached);
+                    match visited.get(&key) {
+                        Some((c, d)) if *c <= next_cost && *d <= depth + 1 => continue,
+                        _ => {}
+                    }
+                    nodes.push(SearchNode {
+                        state: next,
+                        reached,
+                        parent: Some(idx),
+                        transition: t.path.clone(),
+                    });
+                    visited.insert(key, (next_cost, depth + 1));
+                    queue.push(Reverse((next_cost, depth + 1, nodes.len() - 1)));
+                }
+            }
+        }
+
+        Ok(no_plan(start.elapsed()))
+    }
+}
+
+#[cfg(test)]
+mod explicit_planner_tests {
+    use super::*;
+
+    fn two_bits() -> (TransitionSystemModel, SPPath, SPPath) {
+        let mut tsm = TransitionSystemModel::default();
+        let x = Variable::new_boolean("x".into());
+        let y = Variable::new_boolean("y".into());
+        tsm.vars.push(x.clone());
+        tsm.vars.push(y.clone());
+        tsm.transitions
+            .push(Transition::new("set_x".into(), p!(!x), vec![a!(x)]));
+        tsm.transitions.push(Transition::new(
+            "set_y".into(),
+            p!([x] && [!y]),
+            vec![a!(y)],
+        ));
+        tsm.transitions
+            .push(Transition::new("reset_x".into(), p!(x), vec![a!(!x)]));
+        (tsm, x.path, y.path)
+    }
+
+    #[test]
+    fn explicit_finds_shortest_plan() {
+        let (tsm, x, y) = two_bits();
+        let state = state!(x => false, y => false);
+
+        let result = ExplicitPlanner::default()
+            .plan(&tsm, &[(p!(y), None)], &state, 5)
+            .unwrap();
+        assert!(result.plan_found);
+        assert_eq!(result.plan_length, 2);
+        let trans: Vec<_> = result
+            .trace
+            .iter()
+            .map(|f| f.transition.to_string())
+            .collect();
+        assert_eq!(trans, vec!["", "set_x", "set_y"]);
+        assert_eq!(
+            result.trace[2].state.sp_value_from_path(&y),
+            Some(&true.to_spvalue())
+        );
+
+        // goal already fulfilled
+        let result = ExplicitPlanner::default()
+            .plan(&tsm, &[(p!(!y), None)], &state, 5)
+            .unwrap();
+        assert!(result.plan_found);
+        assert_eq!(result.plan_length, 0);
+
+        // too short horizon
+        let result = ExplicitPlanner::default()
+            .plan(&tsm, &[(p!(y), None)], &state, 1)
+            .unwrap();
+        assert!(!result.plan_found);
+    }
+
+    #[test]
+    fn explicit_respects_invariants() {
+        let (mut tsm, x, y) = two_bits();
+        let state = state!(x => false, y => false);
+
+        // both goals need to be reached at some point, but not at the same time.
+        let goals = [(p!(y), None), (p!(!x), None)];
+        let result = ExplicitPlanner::default()
+            .plan(&tsm, &goals, &state, 5)
+            .unwrap();
+        assert!(result.plan_found);
+
+        // x must be kept false until y is reached, impossible.
+        let goals = [(p!(y), Some(p!(!x)))];
+        let result = ExplicitPlanner::default()
+            .plan(&tsm, &goals, &state, 5)
+            .unwrap();
+        assert!(!result.plan_found);
+
+        tsm.invariants
+            .push(NamedPredicate::new("never_y".into(), p!(!y)));
+        let result = ExplicitPlanner::default()
+            .plan(&tsm, &[(p!(y), None)], &state, 5)
+            .unwrap();
+        assert!(!result.plan_found);
+    }
+
+    #[test]
+    fn integer_ranges_and_arithmetic() {
+        let mut tsm = TransitionSystemModel::default();
+        let parts = Variable::new_range("parts".into(), 0, 3);
+        tsm.vars.push(parts.clone());
+        let parts = parts.path;
+        tsm.transitions.push(Transition::new(
+            "add_two".into(),
+            p!(parts < 3),
+            vec![a!(parts += 2)],
+        ));
+        tsm.transitions.push(Transition::new(
+            "remove_one".into(),
+            p!(parts > 0),
+            vec![a!(parts -= 1)],
+        ));
+        let state = state!(parts => 0);
+
+        // 0 -> 2 -> 1 -> 3, as 0 -> 2 -> 4 leaves the range.
+        let goals = [(p!(parts >= 3), None)];
+        for backend in [
+            PlannerBackend::Explicit(PlanObjective::Steps),
+            PlannerBackend::Symbolic,
+        ] {
+            let result = backend.create().plan(&tsm, &goals, &state, 5).unwrap();
+            assert!(result.plan_found);
+            assert_eq!(result.plan_length, 3);
+            assert_eq!(
+                result.trace[3].state.sp_value_from_path(&parts),
+                Some(&3.to_spvalue())
+            );
+        }
+    }
+
+    #[test]
+    fn cost_optimal_plans() {
+        let mut tsm = TransitionSystemModel::default();
+        let pos = Variable::new_range("pos".into(), 0, 2);
+        tsm.vars.push(pos.clone());
+        let pos = pos.path;
+        let slow = Cost {
+            cost: 1,
+            duration: 10,
+            energy: 1,
+        };
+        let fast = Cost {
+            cost: 1,
+            duration: 3,
+            energy: 5,
+        };
+        tsm.transitions.push(
+            Transition::new("direct".into(), p!(pos == 0), vec![a!(pos = 2)]).with_cost(slow),
+        );
+        tsm.transitions
+            .push(Transition::new("step".into(), p!(pos < 2), vec![a!(pos += 1)]).with_cost(fast));
+        let state = state!(pos => 0);
+        let goals = [(p!(pos == 2), None)];
+
+        let plan = |objective| {
+            let result = ExplicitPlanner::new(objective)
+                .plan(&tsm, &goals, &state, 5)
+                .unwrap();
+            let trans: Vec<_> = result
+                .trace
+                .iter()
+                .map(|f| f.transition.to_string())
+                .collect();
+            (trans, result.cost)
+        };
+
+        assert_eq!(
+            plan(PlanObjective::Steps),
+            (vec!["".into(), "direct".into()], slow)
+        );
+        assert_eq!(plan(PlanObjective::Energy).0, vec!["", "direct"]);
+        // equal cost, the shortest plan wins
+        assert_eq!(plan(PlanObjective::Cost).0, vec!["", "direct"]);
+        assert_eq!(
+            plan(PlanObjective::TotalDuration),
+            (vec!["".into(), "step".into(), "step".into()], fast + fast)
+        );
+    }
+
+    #[tokio::test]
+    async fn planners_behind_trait_objects() {
+        let (tsm, x, y) = two_bits();
+        let state = state!(x => false, y => false);
+        let goals = vec![(p!(y), None)];
+
+        let mut planner = PlannerBackend::Explicit(PlanObjective::Steps).create();
+        assert_eq!(planner.name(), "explicit");
+        assert_eq!(
+            planner.plan(&tsm, &goals, &state, 5).unwrap().plan_length,
+            2
+        );
+
+        let cancel = CancellationToken::new();
+        cancel.cancel();
+        assert_eq!(
+            planner.plan_cancellable(&tsm, &goals, &state, 5, &cancel),
+            Err(PlanningError::Cancelled)
+        );
+
+        let planner = PlannerBackend::Symbolic.create();
+        let (planner, result) =
+            plan_in_background(planner, tsm, goals, state, 5, CancellationToken::new()).await;
+        assert_eq!(planner.name(), "symbolic");
+        assert_eq!(result.unwrap().plan_length, 2);
+    }
+
+    #[tokio::test]
+    async fn async_planning_with_progress() {
+        let (tsm, x, y) = two_bits();
+        let state = state!(x => false, y => false);
+        let goals = vec![(p!(y), None)];
+        let mut planner = PlannerBackend::Explicit(PlanObjective::Steps).create();
+
+        let (tx, mut rx) = tokio::sync::mpsc::unbounded_channel();
+        let cancel = CancellationToken::new();
+        let result = planner
+            .plan_async(&tsm, &goals, &state, 5, &cancel, Some(&tx))
+            .await;
+        assert_eq!(result.unwrap().plan_length, 2);
+        assert_eq!(
+            rx.recv().await,
+            Some(PlanningProgress::Searching { bound: 5 })
+        );
+        assert_eq!(
+            rx.recv().await,
+            Some(PlanningProgress::PlanFound { length: 2 })
+        );
+
+        cancel.cancel();
+        let result = planner
+            .plan_async(&tsm, &goals, &state, 5, &cancel, None)
+            .await;
+        assert_eq!(result, Err(PlanningError::Cancelled));
+    }
+
+    #[tokio::test]
+    async fn cancelling_wakes_up_waiters() {
+        let cancel = CancellationToken::new();
+        let waiter = tokio::spawn({
+            let cancel = cancel.clone();
+            async move { cancel.cancelled().await }
+        });
+        tokio::task::yield_now().await;
+        cancel.cancel();
+        let done = tokio::time::timeout(std::time::Duration::from_secs(1), waiter).await;
+        assert!(done.is_ok());
+        // and later waiters complete right away.
+        cancel.cancelled().await;
+    }
+}
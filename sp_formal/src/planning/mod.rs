@@ -1,7 +1,12 @@
 use crate::TransitionSystemModel;
 use serde::{Deserialize, Serialize};
 use sp_domain::*;
-use std::collections::HashMap;
+use std::fmt;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use tokio::sync::Notify;
 
 #[derive(Debug, PartialEq, Serialize, Deserialize, Clone, Default)]
 pub struct PlanningResult {
@@ -9,6 +14,9 @@ pub struct PlanningResult {
     pub plan_length: u32,
     pub trace: Vec<PlanningFrame>,
     pub time_to_solve: std::time::Duration,
+    /// The total cost of the transitions in the plan.
+    #[serde(default)]
+    pub cost: Cost,
 }
 
 #[derive(Debug, PartialEq, Serialize, Deserialize, Clone, Default)]
@@ -19,110 +27,293 @@ pub struct PlanningFrame {
     pub transition: SPPath,
 }
 
-pub trait Planner {
-    fn plan(
-        model: &TransitionSystemModel, goal: &[(Predicate, Option<Predicate>)], state: &SPState,
-        max_steps: u32,
-    ) -> Result<PlanningResult, String>;
+#[derive(Debug, Default)]
+struct Cancelled {
+    flag: AtomicBool,
+    notify: Notify,
 }
 
-mod nuxmv;
-pub use nuxmv::*;
+/// Shared flag used to ask a planner to give up. Cloning the token
+/// gives a handle to the same flag.
+#[derive(Debug, Clone, Default)]
+pub struct CancellationToken(Arc<Cancelled>);
+
+impl CancellationToken {
+    pub fn new() -> Self {
+        CancellationToken::default()
+    }
+
+    pub fn cancel(&self) {
+        self.0.flag.store(true, Ordering::SeqCst);
+        self.0.notify.notify_waiters();
+    }
 
-#[derive(Clone, Debug, Hash, Eq, PartialEq, Serialize, Deserialize)]
-struct PlannerRequestKey {
-    goal: String,
-    state: String,
+    pub fn is_cancelled(&self) -> bool {
+        self.0.flag.load(Ordering::SeqCst)
+    }
+
+    /// Completes when the token has been cancelled.
+    pub async fn cancelled(&self) {
+        // created before checking the flag, so that a concurrent
+        // cancel can not be missed.
+        let notified = self.0.notify.notified();
+        if !self.is_cancelled() {
+            notified.await;
+        }
+    }
+
+    /// Convenience for planners, returns `Err(Cancelled)` if cancelled.
+    pub fn check(&self) -> Result<(), PlanningError> {
+        if self.is_cancelled() {
+            Err(PlanningError::Cancelled)
+        } else {
+            Ok(())
+        }
+    }
 }
 
-#[derive(Clone, Debug, Default)]
-pub struct PlanningStore {
-    cache: HashMap<PlannerRequestKey, PlanningResult>,
-    hits: i64,
-    lookups: i64,
+/// Cancels the token when dropped, e.g. together with a future.
+struct CancelOnDrop(CancellationToken);
+
+impl Drop for CancelOnDrop {
+    fn drop(&mut self) {
+        self.0.cancel();
+    }
 }
 
-pub fn plan_with_cache(
-    model: &TransitionSystemModel, goals: &[(Predicate, Option<Predicate>)], state: &SPState,
-    max_steps: u32, store: &mut PlanningStore,
-) -> Result<PlanningResult, String> {
-    let now = std::time::Instant::now();
-    // filter the state based on the ts model and serialize it to make it hashable
-    let paths: Vec<_> = model.vars.iter().map(|v| v.path.clone()).collect();
-    let filtered_state = state.filter_by_paths(&paths);
-    let state_str = filtered_state.to_string();
-
-    // serialize goals
-    let goal_str = goals
-        .iter()
-        .map(|(g, i)| {
-            let i = if let Some(i) = i {
-                i.to_string()
-            } else {
-                "".to_string()
+/// Progress reported while planning asynchronously. Planners that
+/// look for cheaper plans once one is found use the objective value
+/// to beat as the bound of those searches.
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
+pub enum PlanningProgress {
+    /// Started looking for plans of at most `bound` steps.
+    Searching { bound: u32 },
+    /// There is no plan of `bound` steps. For incremental searches
+    /// there is no plan of at most `bound` steps.
+    NoPlan { bound: u32 },
+    /// A plan of `length` steps was found. The search may continue
+    /// looking for better plans.
+    PlanFound { length: u32 },
+}
+
+pub type ProgressSender = tokio::sync::mpsc::UnboundedSender<PlanningProgress>;
+
+/// The result of `Planner::plan_async`.
+pub type PlanFuture<'a> =
+    Pin<Box<dyn Future<Output = Result<PlanningResult, PlanningError>> + Send + 'a>>;
+
+pub(crate) fn report_progress(progress: Option<&ProgressSender>, p: PlanningProgress) {
+    if let Some(tx) = progress {
+        // nobody listening is fine.
+        let _ = tx.send(p);
+    }
+}
+
+/// A planning backend. Planners are used through `Box<dyn Planner>`
+/// so that the backend can be chosen at runtime, see `PlannerBackend`.
+/// Use `plan_async` to plan from async code.
+pub trait Planner: PlannerClone + Send {
+    /// Name of the backend, used in logs and cache keys.
+    fn name(&self) -> &str;
+
+    /// What the plans found are optimal with respect to.
+    fn objective(&self) -> PlanObjective {
+        PlanObjective::Steps
+    }
+
+    /// Search for a plan reaching all goals within `max_steps`. The
+    /// planner should check the token regularly and return
+    /// `PlanningError::Cancelled` when it has been cancelled.
+    fn plan_cancellable(
+        &mut self, model: &TransitionSystemModel, goals: &[(Predicate, Option<Predicate>)],
+        state: &SPState, max_steps: u32, cancel: &CancellationToken,
+    ) -> Result<PlanningResult, PlanningError>;
+
+    fn plan(
+        &mut self, model: &TransitionSystemModel, goals: &[(Predicate, Option<Predicate>)],
+        state: &SPState, max_steps: u32,
+    ) -> Result<PlanningResult, PlanningError> {
+        self.plan_cancellable(model, goals, state, max_steps, &CancellationToken::new())
+    }
+
+    /// Plan without blocking the async runtime. Cancelling the token,
+    /// or dropping the future, stops the planner. By default a copy of
+    /// the planner runs `plan_cancellable` on the blocking thread pool.
+    fn plan_async<'a>(
+        &'a mut self, model: &'a TransitionSystemModel,
+        goals: &'a [(Predicate, Option<Predicate>)], state: &'a SPState, max_steps: u32,
+        cancel: &'a CancellationToken, progress: Option<&'a ProgressSender>,
+    ) -> PlanFuture<'a> {
+        let planner = self.clone_box();
+        Box::pin(async move {
+            // the planner stops when its own token is cancelled.
+            report_progress(progress, PlanningProgress::Searching { bound: max_steps });
+            let inner = CancellationToken::new();
+            let _cancel_on_drop = CancelOnDrop(inner.clone());
+            let task = plan_in_background(
+                planner,
+                model.clone(),
+                goals.to_vec(),
+                state.clone(),
+                max_steps,
+                inner,
+            );
+            let result = tokio::select! {
+                biased;
+                _ = cancel.cancelled() => Err(PlanningError::Cancelled),
+                (_, result) = task => result,
             };
-            format!("{g}+{i}")
+            if let Ok(result) = &result {
+                let p = if result.plan_found {
+                    PlanningProgress::PlanFound {
+                        length: result.plan_length,
+                    }
+                } else {
+                    PlanningProgress::NoPlan { bound: max_steps }
+                };
+                report_progress(progress, p);
+            }
+            result
         })
-        .collect::<Vec<_>>()
-        .join("");
-    let key = PlannerRequestKey {
-        goal: goal_str,
-        state: state_str,
-    };
-
-    store.lookups += 1;
-    if let Some(plan) = store.cache.get(&key) {
-        store.hits += 1;
-        println!(
-            "Used cached plan! Current plan count {}, hit% {}, lookup time {} ms",
-            store.cache.len(),
-            ((100 * store.hits) / store.lookups),
-            now.elapsed().as_millis()
-        );
-        return Ok(plan.clone());
-    } else {
-        println!(
-            "Did not use cached plan! Current plan count {}, hit% {}, lookup time {} ms",
-            store.cache.len(),
-            ((100 * store.hits) / store.lookups),
-            now.elapsed().as_millis()
-        );
-    }
-
-    let result = NuXmvPlanner::plan(model, goals, state, max_steps);
-
-    if let Ok(result) = &result {
-        if result.plan_found {
-            // assert_eq!(result.plan_length, result2.plan_length);
-            println!(
-                "plan_result: {} {}",
-                result.plan_length,
-                result.time_to_solve.as_millis()
-            );
-            println!("we have a plan of length {}", result.plan_length);
-            println!("nuxmv time: {}ms", result.time_to_solve.as_millis());
+    }
+}
+
+/// Makes `Box<dyn Planner>` cloneable. Implemented for all planners
+/// that are `Clone`.
+pub trait PlannerClone {
+    fn clone_box(&self) -> Box<dyn Planner>;
+}
+
+impl<T: Planner + Clone + 'static> PlannerClone for T {
+    fn clone_box(&self) -> Box<dyn Planner> {
+        Box::new(self.clone())
+    }
+}
+
+impl Clone for Box<dyn Planner> {
+    fn clone(&self) -> Self {
+        self.clone_box()
+    }
+}
+
+impl fmt::Debug for dyn Planner {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Planner({})", self.name())
+    }
+}
+
+/// Run a planner on the blocking thread pool. The planner is handed
+/// back together with the result so that it can be reused.
+pub async fn plan_in_background(
+    mut planner: Box<dyn Planner>, model: TransitionSystemModel,
+    goals: Vec<(Predicate, Option<Predicate>)>, state: SPState, max_steps: u32,
+    cancel: CancellationToken,
+) -> (Box<dyn Planner>, Result<PlanningResult, PlanningError>) {
+    let task = tokio::task::spawn_blocking(move || {
+        let result = planner.plan_cancellable(&model, &goals, &state, max_steps, &cancel);
+        (planner, result)
+    });
+    match task.await {
+        Ok(r) => r,
+        Err(e) => std::panic::resume_unwind(e.into_panic()),
+    }
+}
+
+/// The available planners, e.g. for selecting one from configuration.
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
+pub enum PlannerBackend {
+    NuXmv(NuXmvConfig),
+    Explicit(PlanObjective),
+    /// Always finds the shortest plan.
+    Symbolic,
+}
+
+impl Default for PlannerBackend {
+    fn default() -> Self {
+        PlannerBackend::NuXmv(NuXmvConfig::default())
+    }
+}
+
+impl PlannerBackend {
+    pub fn create(&self) -> Box<dyn Planner> {
+        match self {
+            PlannerBackend::NuXmv(config) => Box::new(NuXmvPlanner::new(config.clone())),
+            PlannerBackend::Explicit(objective) => Box::new(ExplicitPlanner::new(*objective)),
+            PlannerBackend::Symbolic => Box::new(SymbolicPlanner::default()),
         }
-        store.cache.insert(key, result.clone());
-        println!(
-            "Added new state/goal pair to plan store. Current plan count {}",
-            store.cache.len()
-        );
     }
+}
 
-    result
+/// The specification used for planning. Each goal must be reached at
+/// some point, and the invariant of a goal, if any, must hold until
+/// the goal is reached.
+pub fn goal_spec(goals: &[(Predicate, Option<Predicate>)]) -> Spec {
+    let goals: Vec<_> = goals
+        .iter()
+        .map(|(goal, inv)| match inv {
+            Some(inv) => Spec::U(
+                Box::new(Spec::Pred(inv.clone())),
+                Box::new(Spec::Pred(goal.clone())),
+            ),
+            None => Spec::F(Box::new(Spec::Pred(goal.clone()))),
+        })
+        .collect();
+    Spec::And(goals)
 }
 
-pub fn plan(
+mod error;
+pub use error::*;
+
+mod cost;
+pub use cost::*;
+
+mod cache;
+pub use cache::*;
+
+mod partial_order;
+pub use partial_order::*;
+
+mod validate;
+pub use validate::*;
+
+mod nuxmv;
+pub use nuxmv::*;
+
+mod explicit;
+pub use explicit::*;
+
+mod symbolic;
+pub use symbolic::*;
+
+/// Plan using nuXmv, reusing earlier results from the cache.
+pub fn plan_with_cache(
     model: &TransitionSystemModel, goals: &[(Predicate, Option<Predicate>)], state: &SPState,
-    max_steps: u32,
-) -> Result<PlanningResult, String> {
-    // if we have an invariant for our goal, express it as inv U (inv
-    // & goal) e.g. we make sure that the invariant also holds in the
-    // post state. consider for example the two robots that cannot be
-    // at the table at the same time. reaching a goal that they should
-    // both at the table shouldn't make us ignore the
-    // invariants. instead we don't want a plan to be found.
-    let goals: Vec<_> = goals
+    max_steps: u32, cache: &PlanCache,
+) -> Result<PlanningResult, PlanningError> {
+    let mut planner = NuXmvPlanner::default();
+    let key = PlanCache::key(model, goals, state, &[], max_steps, &planner);
+    if let Some(plan) = cache.get(&key) {
+        return Ok(plan);
+    }
+
+    let result = planner.plan(model, goals, state, max_steps);
+    match &result {
+        Ok(result) if result.plan_found => cache.insert(&key, result)?,
+        _ => {}
+    }
+    result
+}
+
+/// If we have an invariant for our goal, express it as inv U (inv
+/// & goal) e.g. we make sure that the invariant also holds in the
+/// post state. consider for example the two robots that cannot be
+/// at the table at the same time. reaching a goal that they should
+/// both at the table shouldn't make us ignore the
+/// invariants. instead we don't want a plan to be found.
+pub(crate) fn goals_with_invariants(
+    goals: &[(Predicate, Option<Predicate>)],
+) -> Vec<(Predicate, Option<Predicate>)> {
+    goals
         .iter()
         .map(|(g, i)| {
             if let Some(invar) = i {
@@ -131,21 +322,13 @@ pub fn plan(
                 (g.clone(), i.clone())
             }
         })
-        .collect();
-
-    let result = NuXmvPlanner::plan(model, &goals, state, max_steps);
-
-    if let Ok(result) = &result {
-        if result.plan_found {
-            println!(
-                "plan_result: {} {}",
-                result.plan_length,
-                result.time_to_solve.as_millis()
-            );
-            println!("we have a plan of length {}", result.plan_length);
-            println!("nuxmv time: {}ms", result.time_to_solve.as_millis());
-        }
-    }
+        .collect()
+}
 
-    result
+pub fn plan(
+    model: &TransitionSystemModel, goals: &[(Predicate, Option<Predicate>)], state: &SPState,
+    max_steps: u32,
+) -> Result<PlanningResult, PlanningError> {
+    let goals = goals_with_invariants(goals);
+    NuXmvPlanner::default().plan(model, &goals, state, max_steps)
 }
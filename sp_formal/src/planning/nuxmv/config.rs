@@ -0,0 +1,103 @@
+use super::find_binary;
+use crate::planning::{PlanObjective, PlanningError};
+use serde::{Deserialize, Serialize};
+use std::path::PathBuf;
+use std::time::Duration;
+
+/// Environment variable that, when set, overrides the binary to use.
+pub const NUXMV_ENV: &str = "SP_NUXMV";
+
+/// The algorithm nuXmv uses to search for plans.
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Serialize, Deserialize, Default)]
+pub enum NuXmvEngine {
+    /// Incremental bounded model checking. Finds the shortest plan.
+    #[default]
+    BmcIncremental,
+    /// Simple bounded model checking, incremental. Finds the shortest plan.
+    Sbmc,
+    /// IC3 for LTL. Good at proving that no plan exists, but plans are
+    /// not necessarily the shortest.
+    Ic3,
+    /// Plain BDD based LTL checking. The step bound is not used and
+    /// plans are not necessarily the shortest.
+    Bdd,
+}
+
+impl NuXmvEngine {
+    /// The command script that searches for a plan with at most `max_steps` steps.
+    pub fn command(&self, max_steps: u32) -> String {
+        let check = match self {
+            NuXmvEngine::BmcIncremental => {
+                format!("go_bmc\ncheck_ltlspec_bmc_inc -k {max_steps}")
+            }
+            NuXmvEngine::Sbmc => format!("go_bmc\ncheck_ltlspec_sbmc_inc -k {max_steps}"),
+            NuXmvEngine::Ic3 => format!("go\ncheck_ltlspec_ic3 -k {max_steps}"),
+            NuXmvEngine::Bdd => "go\ncheck_ltlspec".to_string(),
+        };
+        format!("{check}\nshow_traces -v\nquit\n")
+    }
+}
+
+/// How to invoke nuXmv.
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize, Default)]
+pub struct NuXmvConfig {
+    /// The binary to run. If not set, the `SP_NUXMV` environment
+    /// variable is used, and otherwise the binary is searched for on
+    /// the path.
+    pub binary: Option<PathBuf>,
+    /// Extra command line arguments, passed before the model file.
+    pub args: Vec<String>,
+    pub engine: NuXmvEngine,
+    /// Kill the process if it has not finished in time.
+    pub timeout: Option<Duration>,
+    /// Other objectives than `Steps` are optimized by repeatedly
+    /// asking for a cheaper plan until none can be found.
+    #[serde(default)]
+    pub objective: PlanObjective,
+}
+
+impl NuXmvConfig {
+    pub fn with_engine(engine: NuXmvEngine) -> Self {
+        NuXmvConfig {
+            engine,
+            ..NuXmvConfig::default()
+        }
+    }
+
+    /// The binary to run, see `binary` of the config.
+    pub fn binary(&self) -> Result<PathBuf, PlanningError> {
+        if let Some(path) = &self.binary {
+            return Ok(path.clone());
+        }
+        match std::env::var_os(NUXMV_ENV) {
+            Some(path) => Ok(path.into()),
+            None => find_binary::find_nuxmv().map(PathBuf::from),
+        }
+    }
+}
+
+#[cfg(test)]
+mod config_tests {
+    use super::*;
+
+    #[test]
+    fn engine_commands() {
+        let bmc = NuXmvEngine::default().command(10);
+        assert!(bmc.starts_with("go_bmc\ncheck_ltlspec_bmc_inc -k 10\n"));
+        assert!(bmc.ends_with("show_traces -v\nquit\n"));
+        assert!(NuXmvEngine::Sbmc
+            .command(3)
+            .contains("check_ltlspec_sbmc_inc -k 3"));
+        assert!(NuXmvEngine::Ic3
+            .command(3)
+            .contains("check_ltlspec_ic3 -k 3"));
+        assert!(!NuXmvEngine::Bdd.command(3).contains("-k"));
+
+        let config = NuXmvConfig {
+            binary: Some("/opt/nuxmv/bin/nuXmv".into()),
+            ..NuXmvConfig::with_engine(NuXmvEngine::Bdd)
+        };
+        // the configured binary is used even if the variable is set.
+        assert_eq!(config.binary(), Ok("/opt/nuxmv/bin/nuXmv".into()));
+    }
+}
@@ -1,11 +1,12 @@
+use crate::planning::PlanningError;
 use which;
 
-pub fn find_nuxmv() -> &'static str {
+pub fn find_nuxmv() -> Result<&'static str, PlanningError> {
     let supported = &["nuXmv", "nuxmv", "nusmv", "NuSMV"];
     for f in supported.iter() {
         if which::which(f).is_ok() {
-            return f;
+            return Ok(f);
         }
     }
-    panic!("nu[X|x|s]mv not found. check your path!");
+    Err(PlanningError::BinaryNotFound)
 }
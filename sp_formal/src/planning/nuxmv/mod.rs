@@ -9,13 +9,39 @@ use std::time::Duration;
 
 mod find_binary;
 
+mod config;
+pub use config::*;
+
 mod nuxmv_async;
 use nuxmv_async::*;
 
+mod model_checking;
+pub use model_checking::*;
+
 fn indent(n: u32) -> String {
     (0..n).map(|_| " ").collect::<Vec<&str>>().concat()
 }
 
+/// A part of the model that has no encoding in nuXmv.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UnsupportedConstruct(pub String);
+
+impl fmt::Display for UnsupportedConstruct {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "can not encode for nuXmv: {}", self.0)
+    }
+}
+
+impl std::error::Error for UnsupportedConstruct {}
+
+impl From<UnsupportedConstruct> for PlanningError {
+    fn from(e: UnsupportedConstruct) -> Self {
+        PlanningError::Unsupported(e.0)
+    }
+}
+
+type Encoded = Result<String, UnsupportedConstruct>;
+
 struct NuXMVPath<'a>(&'a SPPath);
 impl fmt::Display for NuXMVPath<'_> {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
@@ -23,114 +49,393 @@ impl fmt::Display for NuXMVPath<'_> {
     }
 }
 
-struct NuXMVValue<'a>(&'a SPValue);
-impl fmt::Display for NuXMVValue<'_> {
-    fn fmt(&self, fmtr: &mut fmt::Formatter<'_>) -> fmt::Result {
-        match &self.0 {
-            SPValue::Bool(b) if *b => write!(fmtr, "TRUE"),
-            SPValue::Bool(_b) => write!(fmtr, "FALSE"),
-            SPValue::Float32(f) => write!(fmtr, "{f}"),
-            SPValue::Int32(i) => write!(fmtr, "{i}"),
-            SPValue::String(s) => write!(fmtr, "{s}"),
-            SPValue::Time(t) => write!(fmtr, "time: {t:?}"),
-            SPValue::Path(d) => write!(fmtr, "{d:?}"),
-            SPValue::Array(_at, a) => write!(fmtr, "{a:?}"),
-            SPValue::Unknown => write!(fmtr, "SPUNKNOWN"),
+fn nuxmv_value(v: &SPValue) -> Encoded {
+    match v {
+        SPValue::Bool(b) if *b => Ok("TRUE".into()),
+        SPValue::Bool(_b) => Ok("FALSE".into()),
+        SPValue::Float32(f) => Ok(format!("{f}")),
+        SPValue::Int32(i) => Ok(format!("{i}")),
+        SPValue::String(s) => Ok(s.clone()),
+        SPValue::Path(d) => Ok(format!("{d:?}")),
+        SPValue::Unknown => Ok("SPUNKNOWN".into()),
+        SPValue::Time(_) | SPValue::Array(..) => {
+            Err(UnsupportedConstruct(format!("the value {v}")))
         }
     }
 }
 
-struct NuXMVPredicate<'a>(&'a Predicate);
+fn nuxmv_predicate_value(pv: &PredicateValue) -> Encoded {
+    match pv {
+        PredicateValue::SPValue(v) => nuxmv_value(v),
+        PredicateValue::SPPath(p, _) => Ok(format!("{}", NuXMVPath(p))),
+    }
+}
 
-impl fmt::Display for NuXMVPredicate<'_> {
-    fn fmt(&self, fmtr: &mut fmt::Formatter<'_>) -> fmt::Result {
-        let s: String = match &self.0 {
-            Predicate::AND(x) => {
-                let children: Vec<_> = x.iter().map(|p| format!("{}", NuXMVPredicate(p))).collect();
-                format!("( {} )", children.join("&"))
-            }
-            Predicate::OR(x) => {
-                let children: Vec<_> = x.iter().map(|p| format!("{}", NuXMVPredicate(p))).collect();
-                format!("( {} )", children.join("|"))
-            }
-            Predicate::NOT(p) => format!("!({})", NuXMVPredicate(p)),
-            Predicate::TRUE => "TRUE".into(),
-            Predicate::FALSE => "FALSE".into(),
-            Predicate::EQ(x, y) => {
-                let xx = match x {
-                    PredicateValue::SPValue(v) => format!("{}", NuXMVValue(v)),
-                    PredicateValue::SPPath(p, _) => format!("{}", NuXMVPath(p)),
-                };
-                let yy = match y {
-                    PredicateValue::SPValue(v) => format!("{}", NuXMVValue(v)),
-                    PredicateValue::SPPath(p, _) => format!("{}", NuXMVPath(p)),
-                };
+/// Time predicates are abstracted as booleans that can change freely
+/// between each step, this is the name of such a variable.
+fn timer_name(p: &Predicate) -> Encoded {
+    let (kind, t, d) = match p {
+        Predicate::TON(t, d) => ("ton", t, d),
+        Predicate::TOFF(t, d) => ("toff", t, d),
+        _ => return Err(UnsupportedConstruct(format!("{p} is not a timer"))),
+    };
+    let t = match t {
+        PredicateValue::SPPath(t, _) => t,
+        _ => return Err(UnsupportedConstruct(format!("the timer {p} needs a path"))),
+    };
+    let d: String = d
+        .to_string()
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+        .collect();
+    Ok(format!(
+        "{}",
+        NuXMVPath(&t.add_child(&format!("{kind}_{d}")))
+    ))
+}
 
-                format!("( {xx} = {yy} )")
+fn timers(p: &Predicate, out: &mut Vec<String>) -> Result<(), UnsupportedConstruct> {
+    match p {
+        Predicate::AND(x) | Predicate::OR(x) | Predicate::XOR(x) => {
+            x.iter().try_for_each(|p| timers(p, out))
+        }
+        Predicate::NOT(p) => timers(p, out),
+        Predicate::TON(..) | Predicate::TOFF(..) => {
+            let name = timer_name(p)?;
+            if !out.contains(&name) {
+                out.push(name);
             }
-            Predicate::NEQ(x, y) => {
-                let xx = match x {
-                    PredicateValue::SPValue(v) => format!("{}", NuXMVValue(v)),
-                    PredicateValue::SPPath(p, _) => format!("{}", NuXMVPath(p)),
-                };
-                let yy = match y {
-                    PredicateValue::SPValue(v) => format!("{}", NuXMVValue(v)),
-                    PredicateValue::SPPath(p, _) => format!("{}", NuXMVPath(p)),
-                };
+            Ok(())
+        }
+        _ => Ok(()),
+    }
+}
 
-                format!("( {xx} != {yy} )")
+fn nuxmv_predicate(p: &Predicate) -> Encoded {
+    let join = |x: &[Predicate], op: &str| -> Encoded {
+        let children = x
+            .iter()
+            .map(nuxmv_predicate)
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(format!("( {} )", children.join(op)))
+    };
+    let ordering = |x: &PredicateValue, op: &str, y: &PredicateValue| -> Encoded {
+        Ok(format!(
+            "( {} {op} {} )",
+            nuxmv_predicate_value(x)?,
+            nuxmv_predicate_value(y)?
+        ))
+    };
+    match p {
+        Predicate::AND(x) if x.is_empty() => Ok("TRUE".into()),
+        Predicate::OR(x) if x.is_empty() => Ok("FALSE".into()),
+        Predicate::AND(x) => join(x, "&"),
+        Predicate::OR(x) => join(x, "|"),
+        Predicate::XOR(x) => {
+            // exactly one of the children holds.
+            let children = x
+                .iter()
+                .map(nuxmv_predicate)
+                .collect::<Result<Vec<_>, _>>()?;
+            let alternatives: Vec<_> = (0..children.len())
+                .map(|i| {
+                    let c: Vec<_> = children
+                        .iter()
+                        .enumerate()
+                        .map(|(j, c)| if i == j { c.clone() } else { format!("!{c}") })
+                        .collect();
+                    format!("( {} )", c.join("&"))
+                })
+                .collect();
+            if alternatives.is_empty() {
+                Ok("FALSE".into())
+            } else {
+                Ok(format!("( {} )", alternatives.join("|")))
             }
-            x => {
-                panic!("We can not convert this predicate to nuXMV: {}", x);
+        }
+        Predicate::NOT(p) => Ok(format!("!({})", nuxmv_predicate(p)?)),
+        Predicate::TRUE => Ok("TRUE".into()),
+        Predicate::FALSE => Ok("FALSE".into()),
+        Predicate::EQ(x, y) => Ok(format!(
+            "( {} = {} )",
+            nuxmv_predicate_value(x)?,
+            nuxmv_predicate_value(y)?
+        )),
+        Predicate::NEQ(x, y) => Ok(format!(
+            "( {} != {} )",
+            nuxmv_predicate_value(x)?,
+            nuxmv_predicate_value(y)?
+        )),
+        Predicate::GT(x, y) => ordering(x, ">", y),
+        Predicate::LT(x, y) => ordering(x, "<", y),
+        Predicate::GEQ(x, y) => ordering(x, ">=", y),
+        Predicate::LEQ(x, y) => ordering(x, "<=", y),
+        Predicate::TON(..) | Predicate::TOFF(..) => timer_name(p),
+        Predicate::MEMBER(x, PredicateValue::SPValue(SPValue::Array(_, xs))) => {
+            let x = nuxmv_predicate_value(x)?;
+            let members = xs.iter().map(nuxmv_value).collect::<Result<Vec<_>, _>>()?;
+            let eqs: Vec<_> = members.iter().map(|m| format!("( {x} = {m} )")).collect();
+            if eqs.is_empty() {
+                Ok("FALSE".into())
+            } else {
+                Ok(format!("( {} )", eqs.join("|")))
             }
-        };
+        }
+        Predicate::MEMBER(..) => Err(UnsupportedConstruct(format!(
+            "{p}, membership can only be checked against constant arrays"
+        ))),
+    }
+}
+
+fn nuxmv_spec(spec: &Spec) -> Encoded {
+    let join = |x: &[Spec], op: &str| -> Encoded {
+        let children = x.iter().map(nuxmv_spec).collect::<Result<Vec<_>, _>>()?;
+        Ok(format!("( {} )", children.join(op)))
+    };
+    let s = nuxmv_spec;
+    Ok(match spec {
+        Spec::Pred(p) => nuxmv_predicate(p)?,
+        Spec::Not(x) => format!("!({})", s(x)?),
+        Spec::And(x) if x.is_empty() => "TRUE".into(),
+        Spec::Or(x) if x.is_empty() => "FALSE".into(),
+        Spec::And(x) => join(x, "&")?,
+        Spec::Or(x) => join(x, "|")?,
+        Spec::Implies(a, b) => format!("( {} -> {} )", s(a)?, s(b)?),
+        Spec::G(x) => format!("G ( {} )", s(x)?),
+        Spec::F(x) => format!("F ( {} )", s(x)?),
+        Spec::X(x) => format!("X ( {} )", s(x)?),
+        Spec::U(a, b) => format!("( {} U {} )", s(a)?, s(b)?),
+        Spec::AG(x) => format!("AG ( {} )", s(x)?),
+        Spec::AF(x) => format!("AF ( {} )", s(x)?),
+        Spec::AX(x) => format!("AX ( {} )", s(x)?),
+        Spec::AU(a, b) => format!("A [ {} U {} ]", s(a)?, s(b)?),
+        Spec::EG(x) => format!("EG ( {} )", s(x)?),
+        Spec::EF(x) => format!("EF ( {} )", s(x)?),
+        Spec::EX(x) => format!("EX ( {} )", s(x)?),
+        Spec::EU(a, b) => format!("E [ {} U {} ]", s(a)?, s(b)?),
+    })
+}
+
+/// Variables holding time stamps are not part of the encoding, the
+/// timers using them are abstracted instead.
+fn is_encoded(v: &Variable) -> bool {
+    v.value_type != SPValueType::Time
+}
 
-        write!(fmtr, "{}", &s)
+fn nuxmv_domain(v: &Variable) -> Encoded {
+    if v.value_type == SPValueType::Bool {
+        return Ok("{TRUE, FALSE}".into());
+    }
+    if let Some((min, max)) = v.range {
+        return Ok(format!("{min}..{max}"));
     }
+    let domain = v
+        .domain
+        .iter()
+        .map(nuxmv_value)
+        .collect::<Result<Vec<_>, _>>()?;
+    if domain.is_empty() {
+        return Err(UnsupportedConstruct(format!(
+            "{} has an empty domain",
+            v.path
+        )));
+    }
+    Ok(format!("{{{}}}", domain.join(",")))
 }
 
-fn action_to_string(a: &Action) -> Option<String> {
+/// Arithmetic expressions in actions.
+fn nuxmv_compute(c: &Compute) -> Encoded {
+    match c {
+        Compute::PredicateValue(pv) => nuxmv_predicate_value(pv),
+        Compute::Add(a, b) => Ok(format!("( {} + {} )", nuxmv_compute(a)?, nuxmv_compute(b)?)),
+        Compute::Sub(a, b) => Ok(format!("( {} - {} )", nuxmv_compute(a)?, nuxmv_compute(b)?)),
+        c => Err(UnsupportedConstruct(format!("{c} in arithmetic"))),
+    }
+}
+
+/// Encode the constraint on the next value of the variable set by
+/// an action. Also returns a condition that must hold for the action
+/// to be possible to take.
+fn nuxmv_action(
+    a: &Action, vars: &[Variable],
+) -> Result<(Option<String>, Option<String>), UnsupportedConstruct> {
+    let path = NuXMVPath(&a.var);
+    let var = vars.iter().find(|v| v.path == a.var);
     match &a.value {
-        Compute::PredicateValue(pv) => match pv {
-            PredicateValue::SPValue(spval) => Some(format!("{}", NuXMVValue(spval))),
-            PredicateValue::SPPath(path, _) => Some(format!("{}", NuXMVPath(path))),
+        Compute::PredicateValue(pv) => Ok((
+            Some(format!("next({path}) = {}", nuxmv_predicate_value(pv)?)),
+            None,
+        )),
+        Compute::Predicate(p) => Ok((
+            Some(format!("next({path}) = {}", nuxmv_predicate(p)?)),
+            None,
+        )),
+        Compute::Function(xs) => {
+            // the action fails if no case matches
+            let cases = xs
+                .iter()
+                .map(|(p, v)| {
+                    Ok(format!(
+                        "{} : {};",
+                        nuxmv_predicate(p)?,
+                        nuxmv_predicate_value(v)?
+                    ))
+                })
+                .collect::<Result<Vec<_>, UnsupportedConstruct>>()?;
+            let conds = xs
+                .iter()
+                .map(|(p, _)| nuxmv_predicate(p))
+                .collect::<Result<Vec<_>, _>>()?;
+            if conds.is_empty() {
+                return Ok((None, Some("FALSE".into())));
+            }
+            Ok((
+                Some(format!(
+                    "next({path}) = case {} TRUE : {path}; esac",
+                    cases.join(" ")
+                )),
+                Some(format!("( {} )", conds.join("|"))),
+            ))
+        }
+        Compute::Any => match var {
+            Some(v) => Ok((Some(format!("next({path}) in {}", nuxmv_domain(v)?)), None)),
+            None => Err(UnsupportedConstruct(format!("{a}, unknown variable"))),
+        },
+        Compute::Random(n) => {
+            let values: Vec<_> = (0..*n)
+                .map(|i| i.to_spvalue())
+                .filter(|i| {
+                    var.map(|v| v.domain.is_empty() || v.domain.contains(i))
+                        .unwrap_or(true)
+                })
+                .map(|i| i.to_string())
+                .collect();
+            if values.is_empty() {
+                return Err(UnsupportedConstruct(format!("{a}, no possible values")));
+            }
+            Ok((
+                Some(format!("next({path}) in {{{}}}", values.join(","))),
+                None,
+            ))
+        }
+        // out of range results make the transition impossible to take
+        Compute::Add(..) | Compute::Sub(..) => Ok((
+            Some(format!("next({path}) = {}", nuxmv_compute(&a.value)?)),
+            None,
+        )),
+        Compute::TimeStamp => match var {
+            Some(v) if !is_encoded(v) => Ok((None, None)),
+            _ => Err(UnsupportedConstruct(format!(
+                "{a}, time stamps need a time variable"
+            ))),
         },
-        Compute::Predicate(p) => Some(format!("{}", NuXMVPredicate(p))),
-        _ => None,
     }
 }
 
-fn spval_from_nuxvm(nuxmv_val: &str, spv_t: SPValueType) -> SPValue {
+fn spval_from_nuxvm(nuxmv_val: &str, spv_t: SPValueType) -> Result<SPValue, PlanningError> {
     // as we have more options than json we switch on the spval type
-    let tm = |msg: &str| format!("type mismatch! got {nuxmv_val}, expected {msg}!");
+    let tm = |msg: &str| {
+        PlanningError::TraceParse(format!("type mismatch! got {nuxmv_val}, expected {msg}!"))
+    };
     match spv_t {
-        SPValueType::Bool => {
-            if nuxmv_val == "TRUE" {
-                true.to_spvalue()
-            } else {
-                false.to_spvalue()
-            }
-        }
+        SPValueType::Bool => match nuxmv_val {
+            "TRUE" => Ok(true.to_spvalue()),
+            "FALSE" => Ok(false.to_spvalue()),
+            _ => Err(tm("bool")),
+        },
         SPValueType::Int32 => {
-            let intval: i32 = nuxmv_val
-                .parse()
-                .unwrap_or_else(|_| panic!("{}", &tm("int32")));
-            intval.to_spvalue()
+            let intval: i32 = nuxmv_val.parse().map_err(|_| tm("int32"))?;
+            Ok(intval.to_spvalue())
         }
         SPValueType::Float32 => {
-            let fval: f32 = nuxmv_val
-                .parse()
-                .unwrap_or_else(|_| panic!("{}", &tm("float32")));
-            fval.to_spvalue()
+            let fval: f32 = nuxmv_val.parse().map_err(|_| tm("float32"))?;
+            Ok(fval.to_spvalue())
         }
-        SPValueType::String => nuxmv_val.to_spvalue(),
-        // todo: check is_array
-        _ => unimplemented!("TODO"),
+        SPValueType::String => Ok(nuxmv_val.to_spvalue()),
+        t => Err(PlanningError::TraceParse(format!(
+            "can not read back values of type {t:?}"
+        ))),
     }
 }
 
-fn call_nuxmv(max_steps: u32, filename: &str) -> std::io::Result<(String, String)> {
-    let mut process = Command::new(find_binary::find_nuxmv())
+/// Messages nuXmv prints on stderr that are not errors.
+const BENIGN_STDERR: &[&str] = &["There are no traces currently available."];
+
+/// Turn whatever nuXmv printed on stderr into an error. Syntax errors
+/// are reported on the form `file <name>: line <n>: <message>`, in
+/// that case the offending line of the model is looked up.
+fn check_stderr(raw_error: &str, model_lines: &str) -> Result<(), PlanningError> {
+    let raw_error = raw_error.trim();
+    if raw_error.is_empty() || BENIGN_STDERR.contains(&raw_error) {
+        return Ok(());
+    }
+    let line_str = ": line ";
+    if let Some(idx) = raw_error.find(line_str) {
+        let rest = &raw_error[idx + line_str.len()..];
+        if let Some(idx) = rest.find(':') {
+            if let Ok(line) = rest[..idx].trim().parse::<usize>() {
+                let message = rest[idx + 1..].lines().next().unwrap_or("").trim();
+                let model_line = model_lines
+                    .lines()
+                    .nth(line.saturating_sub(1))
+                    .unwrap_or("");
+                return Err(PlanningError::Syntax {
+                    line,
+                    message: message.to_string(),
+                    model_line: model_line.to_string(),
+                });
+            }
+        }
+    }
+    Err(PlanningError::ProcessFailed(raw_error.to_string()))
+}
+
+/// Keep a copy of a planning request around for debugging.
+fn save_request(filename: &str, to: &str) {
+    if let Err(e) = std::fs::copy(filename, to) {
+        println!("could not save planning request to {to}: {e}");
+    }
+}
+
+fn process_output(result: std::process::Output) -> Result<(String, String), PlanningError> {
+    let decode =
+        |b: Vec<u8>| String::from_utf8(b).map_err(|e| PlanningError::TraceParse(e.to_string()));
+    let raw = decode(result.stdout)?;
+    let raw_error = decode(result.stderr)?;
+    if !result.status.success() {
+        return Err(PlanningError::ProcessFailed(format!(
+            "{}: {}",
+            result.status,
+            raw_error.trim()
+        )));
+    }
+    Ok((raw, raw_error))
+}
+
+fn call_nuxmv(
+    config: &NuXmvConfig, max_steps: u32, filename: &str, cancel: &CancellationToken,
+) -> Result<(String, String), PlanningError> {
+    let command = config.engine.command(max_steps);
+    run_nuxmv(config, filename, &command, cancel)
+}
+
+fn read_in_background<R: Read + Send + 'static>(r: Option<R>) -> std::thread::JoinHandle<Vec<u8>> {
+    std::thread::spawn(move || {
+        let mut buf = Vec::new();
+        if let Some(mut r) = r {
+            let _ = r.read_to_end(&mut buf);
+        }
+        buf
+    })
+}
+
+/// Run nuXmv on the model in `filename`. The process is killed if it
+/// times out or planning is cancelled.
+fn run_nuxmv(
+    config: &NuXmvConfig, filename: &str, command: &str, cancel: &CancellationToken,
+) -> Result<(String, String), PlanningError> {
+    let mut process = Command::new(config.binary()?)
+        .args(&config.args)
         .arg("-int")
         .arg(filename)
         .stdin(Stdio::piped())
@@ -138,27 +443,44 @@ fn call_nuxmv(max_steps: u32, filename: &str) -> std::io::Result<(String, String
         .stderr(Stdio::piped())
         .spawn()?;
 
-    let command = format!("go_bmc\ncheck_ltlspec_bmc_inc -k {max_steps}\nshow_traces -v\nquit\n");
-
     let mut stdin = process.stdin.take().unwrap();
     stdin.write_all(command.as_bytes())?;
+    drop(stdin);
 
-    let result = process.wait_with_output()?;
-
-    assert!(result.status.success());
-
-    let raw = String::from_utf8(result.stdout).expect("Check character encoding");
-    let raw_error = String::from_utf8(result.stderr).expect("Check character encoding");
+    // keep reading so that the process does not block on a full pipe.
+    let stdout = read_in_background(process.stdout.take());
+    let stderr = read_in_background(process.stderr.take());
+    let start = Instant::now();
+    let status = loop {
+        if let Some(status) = process.try_wait()? {
+            break status;
+        }
+        let error = match config.timeout {
+            _ if cancel.is_cancelled() => Some(PlanningError::Cancelled),
+            Some(timeout) if start.elapsed() > timeout => Some(PlanningError::Timeout(timeout)),
+            _ => None,
+        };
+        if let Some(error) = error {
+            let _ = process.kill();
+            let _ = process.wait();
+            return Err(error);
+        }
+        std::thread::sleep(Duration::from_millis(10));
+    };
 
-    Ok((raw, raw_error))
+    process_output(std::process::Output {
+        status,
+        stdout: stdout.join().unwrap_or_default(),
+        stderr: stderr.join().unwrap_or_default(),
+    })
 }
 
 fn postprocess_nuxmv_problem(
     model: &TransitionSystemModel, raw: &str,
-) -> Option<Vec<PlanningFrame>> {
+) -> Result<Option<Vec<PlanningFrame>>, PlanningError> {
     if !raw.contains("Trace Type: Counterexample") {
         // we didn't find a counter-example, which means we already fulfil the goal.
-        return None;
+        return Ok(None);
     }
 
     let lines = raw.lines();
@@ -175,7 +497,6 @@ fn postprocess_nuxmv_problem(
         if l.contains("  -- Loop starts here") {
             // when searching for infinite paths...
             // TODO: we should search back to the first ok state when getting this
-            println!("NOTE: INFINITE PATH");
         } else if l.contains("  -> State: ") {
             // ignore the difference between state and input.
         } else if l.contains("  -> Input: ") || l.contains("nuXmv >") || l.contains("NuSMV >") {
@@ -183,15 +504,24 @@ fn postprocess_nuxmv_problem(
             last = PlanningFrame::default();
         } else {
             let path_val: Vec<_> = l.split('=').map(|s| s.trim()).collect();
-            let path: SPPath = (*path_val.first().expect("no value!")).into();
+            let (path, val) = match path_val.as_slice() {
+                [path, val] => (SPPath::from(*path), *val),
+                _ => return Err(PlanningError::TraceParse(format!("no value! {l}"))),
+            };
             let sppath = path.clone();
-            let val = path_val.get(1).expect("no value!");
 
             if model.transitions.iter().any(|t| t.path == path) {
-                if val == &"TRUE" {
-                    assert!(last.transition == SPPath::default());
+                if val == "TRUE" {
+                    if last.transition != SPPath::default() {
+                        return Err(PlanningError::TraceParse(format!(
+                            "both {} and {} taken in the same step",
+                            last.transition, sppath
+                        )));
+                    }
                     last.transition = sppath.clone();
                 }
+            } else if path == SPPath::from(COST_VAR) {
+                // only used to bound the cost
             } else {
                 // get SP type from path
                 let spt = if model.state_predicates.iter().any(|p| p.path == path) {
@@ -202,379 +532,416 @@ fn postprocess_nuxmv_problem(
                     SPValueType::Bool // this is a spec
                 };
 
-                let spval = spval_from_nuxvm(val, spt);
+                let spval = spval_from_nuxvm(val, spt)?;
                 last.state.add_variable(sppath.clone(), spval);
             }
         }
     }
 
-    Some(trace)
+    Ok(Some(trace))
 }
 
-pub struct NuXmvPlanner {}
+/// Write the problem to a temporary file that is removed when the
+/// returned path is dropped.
+fn write_problem(lines: &str) -> Result<tempfile::TempPath, PlanningError> {
+    let mut f = tempfile::NamedTempFile::new()?;
+    write!(f, "{lines}")?;
+    Ok(f.into_temp_path())
+}
 
-pub fn plan_async(
-    model: &TransitionSystemModel, goals: &[(Predicate, Option<Predicate>)], state: &SPState,
-    max_steps: u32, cutoff: u32, lookout: f32, max_time: Duration,
+fn planning_result(
+    model: &TransitionSystemModel, plan: Option<Vec<PlanningFrame>>, state: &SPState,
+    duration: Duration,
 ) -> PlanningResult {
-    let lines = create_nuxmv_problem(model, goals, state);
+    let plan_found = plan.is_some();
+    let trace = plan.unwrap_or_else(|| {
+        vec![PlanningFrame {
+            transition: "".into(),
+            state: state.clone(),
+        }]
+    });
+    PlanningResult {
+        plan_found,
+        plan_length: trace.len() as u32 - 1, // hack :)
+        cost: plan_cost(model, &trace),
+        trace,
+        time_to_solve: duration,
+    }
+}
+
+/// Plans using nuXmv, invoked as described by the config.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct NuXmvPlanner {
+    pub config: NuXmvConfig,
+}
+
+impl NuXmvPlanner {
+    pub fn new(config: NuXmvConfig) -> Self {
+        NuXmvPlanner { config }
+    }
+}
+
+/// Parameters of the concurrent search in `plan_async`.
+#[derive(Debug, PartialEq, Clone, Copy, Serialize, Deserialize)]
+pub struct SearchHeuristic {
+    /// Bounds up to the cutoff are searched incrementally by one
+    /// process, the bounds above by one process each.
+    pub cutoff: u32,
+    /// How long to keep looking for shorter plans, relative to the
+    /// time it took to find the first one.
+    pub lookout: f32,
+    pub max_time: Duration,
+}
+
+/// Quickly find a plan, not necessarily the shortest, by searching
+/// several bounds concurrently. See `search_heuristic`.
+#[allow(clippy::too_many_arguments)]
+pub async fn plan_async(
+    model: &TransitionSystemModel, goals: &[(Predicate, Option<Predicate>)], state: &SPState,
+    max_steps: u32, config: &NuXmvConfig, heuristic: &SearchHeuristic, cancel: &CancellationToken,
+    progress: Option<&ProgressSender>,
+) -> Result<PlanningResult, PlanningError> {
+    let lines = create_nuxmv_problem(model, goals, state)?;
 
-    let mut f = tempfile::NamedTempFile::new().expect("Could not create tempfile");
-    write!(f, "{lines}").unwrap();
-    let tempfile = f.into_temp_path();
+    let tempfile = write_problem(&lines)?;
     let filename_last_plan = tempfile.as_os_str().to_str().expect("invalid path");
 
     let start = Instant::now();
-    let result =
-        block_on_search_heuristic(filename_last_plan, cutoff, max_steps, lookout, max_time);
+    let search = search_heuristic(
+        config.clone(),
+        filename_last_plan.to_owned(),
+        heuristic.cutoff,
+        max_steps,
+        heuristic.lookout,
+        heuristic.max_time,
+        progress,
+    );
+    let result = tokio::select! {
+        biased;
+        _ = cancel.cancelled() => return Err(PlanningError::Cancelled),
+        result = search => result?,
+    };
     let duration = start.elapsed();
 
-    let res = match result {
-        Ok((_, raw, raw_error)) => {
-            if !raw_error.is_empty()
-                && !raw_error.contains("There are no traces currently available.")
-            {
-                // just to more easily find syntax errors
-                panic!("{}", raw_error);
-            }
-            let plan = postprocess_nuxmv_problem(model, &raw);
-            let plan_found = plan.is_some();
-            let trace = plan.unwrap_or_else(|| {
-                vec![PlanningFrame {
-                    transition: "".into(),
-                    state: state.clone(),
-                }]
-            });
-
-            println!("async_result: {} {}", trace.len() - 1, duration.as_millis());
-
-            if !plan_found {
-                std::fs::copy(
+    let plan = match result {
+        Some((_, raw, raw_error)) => {
+            if let Err(e) = check_stderr(&raw_error, &lines) {
+                save_request(
                     filename_last_plan,
-                    "./last_failed_async_planning_request.bmc",
-                )
-                .expect("file copy failed");
-            }
-
-            PlanningResult {
-                plan_found,
-                plan_length: trace.len() as u32 - 1, // hack :)
-                trace,
-                time_to_solve: duration,
+                    "./failed_async_planning_request_syntax_problem.bmc",
+                );
+                return Err(e);
             }
+            postprocess_nuxmv_problem(model, &raw)?
         }
-        Err(_) => PlanningResult {
-            plan_found: false,
-            plan_length: 0,
-            trace: Vec::new(),
-            time_to_solve: duration,
-        },
+        None => None,
     };
 
-    res
-}
-
-// this version is a bit more interesting... we can only store optimal
-// plans, as otherwise we might end up in a situation where we have
-// stored two plans of different length for doing the same thing. the redundant moves
-// can then make us livelock (by moving away from the goal)
-use std::sync::{Arc, Mutex};
+    let res = planning_result(model, plan, state, duration);
+    if !res.plan_found {
+        save_request(
+            filename_last_plan,
+            "./last_failed_async_planning_request.bmc",
+        );
+    }
 
-#[derive(Clone, Debug, Default, Serialize, Deserialize)]
-pub struct AsyncPlanningStore {
-    // key must be string to serde::to_string
-    cache: HashMap<String, Option<PlanningResult>>, // none if being computed already
-    hits: i64,
-    lookups: i64,
+    Ok(res)
 }
 
-use std::collections::hash_map::DefaultHasher;
-use std::hash::{Hash, Hasher};
-
-fn calculate_hash<T: Hash>(t: &T) -> u64 {
-    let mut s = DefaultHasher::new();
-    t.hash(&mut s);
-    s.finish()
-}
+/// Return a quick plan from `plan_async` while the optimal plan is
+/// computed in a background task and added to the cache. The
+/// background task is also stopped by the token.
+#[allow(clippy::too_many_arguments)]
+pub async fn plan_async_with_cache(
+    model: &TransitionSystemModel, goals: &[(Predicate, Option<Predicate>)], state: &SPState,
+    disabled: &[SPPath], max_steps: u32, config: &NuXmvConfig, heuristic: &SearchHeuristic,
+    cache: &PlanCache, cancel: &CancellationToken, progress: Option<&ProgressSender>,
+) -> Result<PlanningResult, PlanningError> {
+    let mut planner = NuXmvPlanner::new(config.clone());
+    let key = PlanCache::key(model, goals, state, disabled, max_steps, &planner);
+    if let Some(plan) = cache.get(&key) {
+        return Ok(plan);
+    }
+    let goals = goals_with_invariants(goals);
+
+    // start computing the optimal plan, unless someone else already is.
+    if cache.claim(&key) {
+        let t_model = model.clone();
+        let t_goals = goals.clone();
+        let t_state = state.clone();
+        let t_cache = cache.clone();
+        let t_cancel = cancel.clone();
+        tokio::spawn(async move {
+            let result = planner
+                .plan_async(&t_model, &t_goals, &t_state, max_steps, &t_cancel, None)
+                .await;
+            match result {
+                // nobody is waiting for the result, if it can not be
+                // stored the next request simply plans again.
+                Ok(result) if result.plan_found => {
+                    let _res = t_cache.insert(&key, &result);
+                }
+                _ => t_cache.release(&key),
+            }
+        });
+    }
 
-fn filename_from_model(
-    model: &TransitionSystemModel,
-) -> Result<String, Box<dyn std::error::Error>> {
-    let mod_ser = serde_json::to_string(model)?;
-    let mod_ser_hash = calculate_hash(&mod_ser);
-    Ok(format!("store-{mod_ser_hash}.sz"))
+    // return best guess for now!
+    plan_async(
+        model, &goals, state, max_steps, config, heuristic, cancel, progress,
+    )
+    .await
 }
 
-fn load_store(
-    model: &TransitionSystemModel,
-) -> Result<AsyncPlanningStore, Box<dyn std::error::Error>> {
-    let path = filename_from_model(model)?;
-    let file = File::open(path)?;
-    let mut buffer = String::new();
-    let mut reader = snap::read::FrameDecoder::new(file);
-    reader.read_to_string(&mut buffer)?;
-    let s = serde_json::from_str(&buffer)?;
-
-    Ok(s)
-}
+impl Planner for NuXmvPlanner {
+    fn name(&self) -> &str {
+        "nuxmv"
+    }
 
-fn save_store(
-    model: &TransitionSystemModel, store: &AsyncPlanningStore,
-) -> Result<(), Box<dyn std::error::Error>> {
-    let path = filename_from_model(model)?;
-    let file = File::create(path)?;
-    let mut writer = snap::write::FrameEncoder::new(file);
-    let s = serde_json::to_string(store)?;
-    writer.write_all(s.as_bytes())?;
+    fn objective(&self) -> PlanObjective {
+        self.config.objective
+    }
 
-    Ok(())
-}
+    fn plan_cancellable(
+        &mut self, model: &TransitionSystemModel, goals: &[(Predicate, Option<Predicate>)],
+        state: &SPState, max_steps: u32, cancel: &CancellationToken,
+    ) -> Result<PlanningResult, PlanningError> {
+        let mut result = self.solve(model, goals, state, max_steps, cancel, None)?;
+        let objective = self.config.objective;
+        if objective == PlanObjective::Steps {
+            return Ok(result);
+        }
 
-impl AsyncPlanningStore {
-    pub fn load(model: &TransitionSystemModel) -> Self {
-        match load_store(model) {
-            Ok(store) => {
-                println!("Loaded cache store with {} plans.", store.cache.len());
-                store
+        // tighten the bound until no cheaper plan can be found.
+        let mut time_to_solve = result.time_to_solve;
+        while result.plan_found {
+            let best = objective.value(&result.cost, result.plan_length);
+            if best == 0 {
+                break;
             }
-            Err(err) => {
-                println!("Could not load planning store: {err}");
-                AsyncPlanningStore::default()
+            let cheaper = self.solve(model, goals, state, max_steps, cancel, Some(best))?;
+            time_to_solve += cheaper.time_to_solve;
+            if !cheaper.plan_found {
+                break;
             }
+            result = cheaper;
         }
+        result.time_to_solve = time_to_solve;
+        Ok(result)
     }
 
-    pub fn save(&self, model: &TransitionSystemModel) {
-        save_store(model, self).expect("failed to save store")
-    }
-}
-
-pub fn plan_async_with_cache(
-    model: &TransitionSystemModel, goals: &[(Predicate, Option<Predicate>)], state: &SPState,
-    disabled: &[SPPath], max_steps: u32, cutoff: u32, lookout: f32, max_time: Duration,
-    store: Arc<Mutex<AsyncPlanningStore>>,
-) -> PlanningResult {
-    let now = std::time::Instant::now();
-    // filter the state based on the ts model and serialize it to make it hashable
-    let paths: Vec<_> = model.vars.iter().map(|v| v.path.clone()).collect();
-    let filtered_state = state.filter_by_paths(&paths);
-    let state_str = filtered_state.to_string();
-
-    // serialize goals
-    let goal_str = goals
-        .iter()
-        .map(|(g, i)| {
-            let i = if let Some(i) = i {
-                i.to_string()
+    /// Like `plan_cancellable`, but the nuXmv process is killed if the
+    /// future is dropped.
+    fn plan_async<'a>(
+        &'a mut self, model: &'a TransitionSystemModel,
+        goals: &'a [(Predicate, Option<Predicate>)], state: &'a SPState, max_steps: u32,
+        cancel: &'a CancellationToken, progress: Option<&'a ProgressSender>,
+    ) -> PlanFuture<'a> {
+        let planner = &*self;
+        let solve = move |bound: Option<u32>| async move {
+            // the cheaper plans are bounded by the objective instead.
+            let searched = bound.unwrap_or(max_steps);
+            report_progress(progress, PlanningProgress::Searching { bound: searched });
+            let result = planner
+                .solve_async(model, goals, state, max_steps, cancel, bound)
+                .await?;
+            let p = if result.plan_found {
+                PlanningProgress::PlanFound {
+                    length: result.plan_length,
+                }
             } else {
-                "".to_string()
+                PlanningProgress::NoPlan { bound: searched }
             };
-            format!("{g}+{i}")
-        })
-        .collect::<Vec<_>>()
-        .join("");
-    // let key = PlannerRequestKey { goal: goal_str, state: state_str };
-    let disabled_str = disabled
-        .iter()
-        .map(|p| p.to_string())
-        .collect::<Vec<_>>()
-        .join(",");
-    let key = format!("{goal_str}=={state_str}=={disabled_str}");
-
-    {
-        let mut store = store.lock().unwrap();
-        store.lookups += 1;
-        if let Some(Some(plan)) = store.cache.get(&key) {
-            let plan = plan.clone();
-            store.hits += 1;
-            println!(
-                "Used cached async plan! Current plan count {}, hit% {}, lookup time {} ms",
-                store.cache.len(),
-                ((100 * store.hits) / store.lookups),
-                now.elapsed().as_millis()
-            );
-            return plan;
-        } else {
-            println!(
-                "Did not use cached async plan! Current plan count {}, hit% {}, lookup time {} ms",
-                store.cache.len(),
-                ((100 * store.hits) / store.lookups),
-                now.elapsed().as_millis()
-            );
-        }
-    }
+            report_progress(progress, p);
+            Ok::<_, PlanningError>(result)
+        };
 
-    // start computing the optimal plan in a thread.
-    let t_key = key;
-    let t_model = model.clone();
-    let t_goals = goals.to_vec();
-    let t_state = state.clone();
-    let t_store = store;
-    std::thread::spawn(move || {
-        {
-            let mut store = t_store.lock().unwrap();
-            if let Some(None) = store.cache.get(&t_key) {
-                // already being computed by someone else, bail
-                return;
-            } else {
-                // we are computing this one!
-                store.cache.insert(t_key.clone(), None);
+        Box::pin(async move {
+            let mut result = solve(None).await?;
+            let objective = planner.config.objective;
+            if objective == PlanObjective::Steps {
+                return Ok(result);
             }
-        }
-        let result = plan(&t_model, &t_goals, &t_state, max_steps);
-        if let Ok(result) = result {
-            let mut store = t_store.lock().unwrap();
-            store.cache.insert(t_key, Some(result.clone()));
-            println!("Added new state/goal pair to async plan store. Current async plan count {}. time to solve: {}ms", store.cache.len(), result.time_to_solve.as_millis());
-            store.save(&t_model);
-        }
-    });
 
-    // return best guess for now!
-    plan_async(model, goals, state, max_steps, cutoff, lookout, max_time)
+            // tighten the bound until no cheaper plan can be found.
+            let mut time_to_solve = result.time_to_solve;
+            while result.plan_found {
+                let best = objective.value(&result.cost, result.plan_length);
+                if best == 0 {
+                    break;
+                }
+                let cheaper = solve(Some(best)).await?;
+                time_to_solve += cheaper.time_to_solve;
+                if !cheaper.plan_found {
+                    break;
+                }
+                result = cheaper;
+            }
+            result.time_to_solve = time_to_solve;
+            Ok(result)
+        })
+    }
 }
 
-impl Planner for NuXmvPlanner {
-    fn plan(
-        model: &TransitionSystemModel, goals: &[(Predicate, Option<Predicate>)], state: &SPState,
-        max_steps: u32,
-    ) -> Result<PlanningResult, String> {
-        let lines = create_nuxmv_problem(model, goals, state);
-
-        let mut f = tempfile::NamedTempFile::new().expect("Could not create tempfile");
-        write!(f, "{lines}").unwrap();
-        let tempfile = f.into_temp_path();
-        let filename_last_plan = tempfile.as_os_str().to_str().expect("invalid path");
+impl NuXmvPlanner {
+    /// Write the problem to a temporary file. When `bound` is given,
+    /// only plans where the objective is below the bound are allowed.
+    fn problem(
+        &self, model: &TransitionSystemModel, goals: &[(Predicate, Option<Predicate>)],
+        state: &SPState, bound: Option<u32>,
+    ) -> Result<(String, tempfile::TempPath), PlanningError> {
+        let mut lines = create_nuxmv_problem(model, goals, state)?;
+        if let Some(bound) = bound {
+            add_cost_bound(&mut lines, &model.transitions, self.config.objective, bound);
+        }
+        let tempfile = write_problem(&lines)?;
+        Ok((lines, tempfile))
+    }
+
+    /// Search for a plan. When `bound` is given, only plans where the
+    /// objective is below the bound are allowed.
+    fn solve(
+        &self, model: &TransitionSystemModel, goals: &[(Predicate, Option<Predicate>)],
+        state: &SPState, max_steps: u32, cancel: &CancellationToken, bound: Option<u32>,
+    ) -> Result<PlanningResult, PlanningError> {
+        let problem = self.problem(model, goals, state, bound)?;
+        let filename = problem.1.as_os_str().to_str().expect("invalid path");
 
         let start = Instant::now();
-        let result = call_nuxmv(max_steps, filename_last_plan);
-        let duration = start.elapsed();
-
-        let res = match result {
-            Ok((raw, raw_error)) => {
-                if !raw_error.is_empty()
-                    && raw_error.trim() != "There are no traces currently available."
-                {
-                    // just to more easily find syntax errors
-                    // try to get the line number
-                    let mut error_msg = raw_error.clone();
-                    let line_str = ": line ";
-                    if let Some(idx) = raw_error.find(line_str) {
-                        let idx = idx + line_str.len();
-                        let new_error = &raw_error[idx..];
-                        if let Some(idx) = new_error.find(':') {
-                            let line = &new_error[..idx].trim();
-                            let line_nbr: usize = line.parse().unwrap();
-                            let error = &new_error[idx..].lines().next().unwrap().trim();
-                            let err_line = lines.lines().nth(line_nbr - 1).unwrap();
-                            error_msg = format!("No plan found, syntax problem at line {line_nbr}: {error}\n{err_line}. \n\n");
-                        }
-                    }
+        let output = call_nuxmv(&self.config, max_steps, filename, cancel)?;
+        self.result(model, state, &problem, output, start.elapsed(), bound)
+    }
 
-                    std::fs::copy(
-                        filename_last_plan,
-                        "./failed_planning_request_syntax_problem.bmc",
-                    )
-                    .expect("file copy failed");
+    async fn solve_async(
+        &self, model: &TransitionSystemModel, goals: &[(Predicate, Option<Predicate>)],
+        state: &SPState, max_steps: u32, cancel: &CancellationToken, bound: Option<u32>,
+    ) -> Result<PlanningResult, PlanningError> {
+        let problem = self.problem(model, goals, state, bound)?;
+        let filename = problem.1.as_os_str().to_str().expect("invalid path");
 
-                    return Err(error_msg);
-                }
-                let plan = postprocess_nuxmv_problem(model, &raw);
-                let plan_found = plan.is_some();
-                let trace = plan.unwrap_or_else(|| {
-                    vec![PlanningFrame {
-                        transition: "".into(),
-                        state: state.clone(),
-                    }]
-                });
+        let start = Instant::now();
+        let command = self.config.engine.command(max_steps);
+        let output = run_nuxmv_async(&self.config, filename, command, cancel).await?;
+        self.result(model, state, &problem, output, start.elapsed(), bound)
+    }
 
-                if !plan_found {
-                    std::fs::copy(filename_last_plan, "./last_failed_planning_request.bmc")
-                        .expect("file copy failed");
-                }
+    /// Parse the nuXmv output for the problem.
+    fn result(
+        &self, model: &TransitionSystemModel, state: &SPState,
+        (lines, tempfile): &(String, tempfile::TempPath), (raw, raw_error): (String, String),
+        duration: Duration, bound: Option<u32>,
+    ) -> Result<PlanningResult, PlanningError> {
+        let filename_last_plan = tempfile.as_os_str().to_str().expect("invalid path");
+        if let Err(e) = check_stderr(&raw_error, lines) {
+            // just to more easily find syntax errors
+            save_request(
+                filename_last_plan,
+                "./failed_planning_request_syntax_problem.bmc",
+            );
+            return Err(e);
+        }
 
-                PlanningResult {
-                    plan_found,
-                    plan_length: trace.len() as u32 - 1, // hack :)
-                    trace,
-                    time_to_solve: duration,
-                }
-            }
-            Err(e) => {
-                panic!("Something went wrong when invoking planner: {}", e);
-            }
-        };
+        let plan = postprocess_nuxmv_problem(model, &raw)?;
+        let res = planning_result(model, plan, state, duration);
+        if !res.plan_found && bound.is_none() {
+            save_request(filename_last_plan, "./last_failed_planning_request.bmc");
+        }
         Ok(res)
     }
 }
 
-fn create_offline_nuxmv_problem(model: &TransitionSystemModel, initial: &Predicate) -> String {
-    let mut lines = make_base_problem(model);
+fn create_offline_nuxmv_problem(
+    model: &TransitionSystemModel, initial: &Predicate,
+) -> Result<String, UnsupportedConstruct> {
+    let mut lines = make_base_problem(model, &[initial])?;
 
-    add_initial_states(&mut lines, initial);
+    add_initial_states(&mut lines, initial)?;
 
-    lines
+    Ok(lines)
 }
 
 pub fn create_nuxmv_problem(
     model: &TransitionSystemModel, goal_invs: &[(Predicate, Option<Predicate>)], state: &SPState,
-) -> String {
-    let mut lines = make_base_problem(model);
+) -> Result<String, PlanningError> {
+    let goal_preds: Vec<_> = goal_invs
+        .iter()
+        .flat_map(|(g, i)| std::iter::once(g).chain(i.iter()))
+        .collect();
+    let mut lines = make_base_problem(model, &goal_preds)?;
 
-    add_current_valuations(&mut lines, &model.vars, state);
+    add_current_valuations(&mut lines, &model.vars, state)?;
 
-    add_goals(&mut lines, goal_invs);
+    add_goals(&mut lines, goal_invs)?;
 
-    lines
+    Ok(lines)
 }
 
 fn create_nuxmv_problem_ctl(
-    model: &TransitionSystemModel, initial: &Predicate, ops: &[(String, Predicate, Predicate)],
-) -> String {
-    let mut lines = make_base_problem(model);
+    model: &TransitionSystemModel, initial: &Predicate, specs: &[(String, Spec)],
+) -> Result<String, UnsupportedConstruct> {
+    let mut preds = vec![initial];
+    preds.extend(specs.iter().flat_map(|(_, s)| s.predicates()));
+    let mut lines = make_base_problem(model, &preds)?;
 
-    add_initial_states(&mut lines, initial);
+    add_initial_states(&mut lines, initial)?;
 
-    add_ctl_specs(&mut lines, ops);
+    add_ctl_specs(&mut lines, specs)?;
 
-    lines
+    Ok(lines)
 }
 
-fn make_base_problem(model: &TransitionSystemModel) -> String {
+/// Encode the model. `extra` are the predicates used in the
+/// specifications, which may also contain timers.
+fn make_base_problem(
+    model: &TransitionSystemModel, extra: &[&Predicate],
+) -> Result<String, UnsupportedConstruct> {
     let mut lines = String::new();
 
     add_preamble(&mut lines, &model.name);
 
-    add_vars(&mut lines, &model.vars);
+    add_vars(&mut lines, &model.vars)?;
+
+    add_timers(&mut lines, model, extra)?;
 
     add_ivars(&mut lines, &model.transitions);
 
-    add_statepreds(&mut lines, &model.state_predicates);
+    add_statepreds(&mut lines, &model.state_predicates)?;
 
     let mut var_set: HashSet<SPPath> = HashSet::new();
-    var_set.extend(model.vars.iter().map(|v| &v.path).cloned());
+    var_set.extend(
+        model
+            .vars
+            .iter()
+            .filter(|v| is_encoded(v))
+            .map(|v| &v.path)
+            .cloned(),
+    );
 
-    add_transitions(&mut lines, &var_set, &model.transitions);
+    add_transitions(&mut lines, &var_set, &model.vars, &model.transitions)?;
 
-    add_global_invariants(&mut lines, &model.invariants);
+    add_global_invariants(&mut lines, &model.invariants)?;
 
-    lines
+    Ok(lines)
 }
 
 pub fn generate_offline_nuxvm_ctl(
-    model: &TransitionSystemModel, initial: &Predicate, ops: &[(String, Predicate, Predicate)],
-) {
-    let lines = create_nuxmv_problem_ctl(model, initial, ops);
+    model: &TransitionSystemModel, initial: &Predicate, specs: &[(String, Spec)],
+) -> Result<(), PlanningError> {
+    let lines = create_nuxmv_problem_ctl(model, initial, specs)?;
     let filename = &format!("./last_model_out_{}.bmc", model.name);
-    let mut f = File::create(filename).unwrap();
-    write!(f, "{lines}").unwrap();
+    let mut f = File::create(filename)?;
+    write!(f, "{lines}")?;
+    Ok(())
 }
 
-pub fn generate_offline_nuxvm(model: &TransitionSystemModel, initial: &Predicate) {
-    let lines = create_offline_nuxmv_problem(model, initial);
+pub fn generate_offline_nuxvm(
+    model: &TransitionSystemModel, initial: &Predicate,
+) -> Result<(), PlanningError> {
+    let lines = create_offline_nuxmv_problem(model, initial)?;
     let filename = &format!("./last_model_out_{}.bmc", model.name);
-    let mut f = File::create(filename).unwrap();
-    write!(f, "{lines}").unwrap();
+    let mut f = File::create(filename)?;
+    write!(f, "{lines}")?;
+    Ok(())
 }
 
 fn add_preamble(lines: &mut String, module_name: &str) {
@@ -582,24 +949,68 @@ fn add_preamble(lines: &mut String, module_name: &str) {
     lines.push_str("MODULE main\n\n");
 }
 
-fn add_vars(lines: &mut String, vars: &[Variable]) {
+fn add_vars(lines: &mut String, vars: &[Variable]) -> Result<(), UnsupportedConstruct> {
     lines.push_str("VAR\n\n");
-    for v in vars {
+    for v in vars.iter().filter(|v| is_encoded(v)) {
         let path = NuXMVPath(&v.path);
         if v.value_type == SPValueType::Bool {
             lines.push_str(&format!("{i}{v} : boolean;\n", i = indent(2), v = path));
         } else {
-            let domain: Vec<_> = v.domain.iter().map(|v| NuXMVValue(v).to_string()).collect();
-            let domain = domain.join(",");
             lines.push_str(&format!(
-                "{i}{v} : {{{d}}};\n",
+                "{i}{v} : {d};\n",
                 i = indent(2),
                 v = path,
-                d = domain
+                d = nuxmv_domain(v)?
             ));
         }
     }
     lines.push_str("\n\n");
+    Ok(())
+}
+
+fn add_timers(
+    lines: &mut String, model: &TransitionSystemModel, extra: &[&Predicate],
+) -> Result<(), UnsupportedConstruct> {
+    let mut names = Vec::new();
+    for t in &model.transitions {
+        timers(&t.guard, &mut names)?;
+        for a in &t.actions {
+            match &a.value {
+                Compute::Predicate(p) => timers(p, &mut names)?,
+                Compute::Function(xs) => xs.iter().try_for_each(|(p, _)| timers(p, &mut names))?,
+                _ => {}
+            }
+        }
+    }
+    for p in &model.state_predicates {
+        timers(&p.predicate, &mut names)?;
+    }
+    // the invariants end up in INVAR, where inputs are not allowed.
+    for p in &model.invariants {
+        let mut in_invariant = Vec::new();
+        timers(&p.predicate, &mut in_invariant)?;
+        if !in_invariant.is_empty() {
+            return Err(UnsupportedConstruct(format!(
+                "the invariant {} can not contain timers",
+                p.path
+            )));
+        }
+    }
+    for p in extra {
+        timers(p, &mut names)?;
+    }
+    if names.is_empty() {
+        return Ok(());
+    }
+
+    // not constrained by the transitions, so they are inputs that may
+    // change freely between each step.
+    lines.push_str("-- timers\nIVAR\n\n");
+    for n in names {
+        lines.push_str(&format!("{i}{n} : boolean;\n", i = indent(2)));
+    }
+    lines.push_str("\n\n");
+    Ok(())
 }
 
 fn add_ivars(lines: &mut String, transitions: &[Transition]) {
@@ -613,17 +1024,20 @@ fn add_ivars(lines: &mut String, transitions: &[Transition]) {
     lines.push_str("\n\n");
 }
 
-fn add_statepreds(lines: &mut String, predicates: &[NamedPredicate]) {
+fn add_statepreds(
+    lines: &mut String, predicates: &[NamedPredicate],
+) -> Result<(), UnsupportedConstruct> {
     // add DEFINES for specs and state predicates
     lines.push_str("DEFINE\n\n");
 
     for p in predicates {
         let path = NuXMVPath(&p.path);
-        let p = NuXMVPredicate(&p.predicate);
+        let p = nuxmv_predicate(&p.predicate)?;
         lines.push_str(&format!("{i}{v} := {p};\n", i = indent(2), v = path, p = p,));
     }
 
     lines.push_str("\n\n");
+    Ok(())
 }
 
 fn modified_by(t: &Transition) -> HashSet<SPPath> {
@@ -633,7 +1047,9 @@ fn modified_by(t: &Transition) -> HashSet<SPPath> {
     r
 }
 
-fn add_transitions(lines: &mut String, all_vars: &HashSet<SPPath>, transitions: &[Transition]) {
+fn add_transitions(
+    lines: &mut String, all_vars: &HashSet<SPPath>, vars: &[Variable], transitions: &[Transition],
+) -> Result<(), UnsupportedConstruct> {
     lines.push_str("TRANS\n\n");
 
     let mut trans = Vec::new();
@@ -649,25 +1065,21 @@ fn add_transitions(lines: &mut String, all_vars: &HashSet<SPPath>, transitions:
             })
             .collect();
 
-        let assign = |a: &Action| {
-            let path = NuXMVPath(&a.var);
-            let value =
-                action_to_string(a).unwrap_or_else(|| panic!("model too complicated {:?}", a));
-            format!("next({path}) = {value}")
-        };
-        let upd: Vec<_> = t
-            .actions
-            .iter()
-            .flat_map(|a| match a.value {
-                Compute::Any => None,
-                _ => Some(a.clone()),
-            })
-            .collect();
-        let mut updates: Vec<_> = upd.iter().map(assign).collect();
+        let mut guards = vec![nuxmv_predicate(&t.guard)?];
+        let mut updates = Vec::new();
+        for a in &t.actions {
+            let (update, guard) = nuxmv_action(a, vars)?;
+            updates.extend(update);
+            guards.extend(guard);
+        }
         updates.extend(keep);
 
-        let g = NuXMVPredicate(&t.guard);
-        let updates_s = updates.join(" & ");
+        let g = guards.join(" & ");
+        let updates_s = if updates.is_empty() {
+            "TRUE".to_string()
+        } else {
+            updates.join(" & ")
+        };
 
         // tracking variable
         let ivar = NuXMVPath(&t.path);
@@ -675,27 +1087,35 @@ fn add_transitions(lines: &mut String, all_vars: &HashSet<SPPath>, transitions:
         trans.push(format!("{ivar} & {g} & {updates_s}"));
     }
 
-    let trans_s = trans.join(" |\n\n");
+    let trans_s = if trans.is_empty() {
+        "FALSE".to_string()
+    } else {
+        trans.join(" |\n\n")
+    };
     lines.push_str(&trans_s);
 
     lines.push_str("\n\n");
+    Ok(())
 }
 
-fn add_initial_states(lines: &mut String, initial: &Predicate) {
+fn add_initial_states(lines: &mut String, initial: &Predicate) -> Result<(), UnsupportedConstruct> {
     lines.push_str("INIT\n\n");
-    let ip = NuXMVPredicate(initial);
+    let ip = nuxmv_predicate(initial)?;
     lines.push_str(&format!("{i}{e}\n;\n", i = indent(2), e = ip));
     lines.push_str("\n\n");
+    Ok(())
 }
 
-fn add_global_invariants(lines: &mut String, invariants: &[NamedPredicate]) {
+fn add_global_invariants(
+    lines: &mut String, invariants: &[NamedPredicate],
+) -> Result<(), UnsupportedConstruct> {
     lines.push_str("INVAR\n\n");
     let mut global = Vec::new();
     for i in invariants {
         global.push(format!(
             "-- spec: {}\n{}\n",
             i.path,
-            NuXMVPredicate(&i.predicate)
+            nuxmv_predicate(&i.predicate)?
         ));
     }
     let invars = if global.is_empty() {
@@ -707,15 +1127,23 @@ fn add_global_invariants(lines: &mut String, invariants: &[NamedPredicate]) {
 
     lines.push_str(&invars);
     lines.push_str("\n\n");
+    Ok(())
 }
 
-fn add_current_valuations(lines: &mut String, vars: &[Variable], state: &SPState) {
+fn add_current_valuations(
+    lines: &mut String, vars: &[Variable], state: &SPState,
+) -> Result<(), PlanningError> {
     lines.push_str("ASSIGN\n\n");
 
-    for v in vars {
-        let value = state.sp_value_from_path(&v.path).expect("all variables need a valuation!");
+    for v in vars.iter().filter(|v| is_encoded(v)) {
+        let value = state.sp_value_from_path(&v.path).ok_or_else(|| {
+            PlanningError::InvalidProblem(format!(
+                "all variables need a valuation! missing {}",
+                v.path
+            ))
+        })?;
         let path = NuXMVPath(&v.path);
-        let value = NuXMVValue(value);
+        let value = nuxmv_value(value)?;
         lines.push_str(&format!(
             "{i}init({v}) := {spv};\n",
             i = indent(2),
@@ -725,52 +1153,281 @@ fn add_current_valuations(lines: &mut String, vars: &[Variable], state: &SPState
     }
 
     lines.push_str("\n\n");
+    Ok(())
 }
 
-fn add_goals(lines: &mut String, goal_invs: &[(Predicate, Option<Predicate>)]) {
-    let goal_str: Vec<String> = goal_invs
+fn add_goals(
+    lines: &mut String, goal_invs: &[(Predicate, Option<Predicate>)],
+) -> Result<(), UnsupportedConstruct> {
+    // we ask for a counterexample to the goals never being reached.
+    let spec = Spec::Not(Box::new(goal_spec(goal_invs)));
+    lines.push_str(&format!("LTLSPEC {};", nuxmv_spec(&spec)?));
+    Ok(())
+}
+
+/// Variable accumulating the objective when looking for cheaper plans.
+const COST_VAR: &str = "_plan_cost";
+
+/// Track the objective in a variable that can not reach `bound`. This
+/// makes the transitions that would reach it impossible to take.
+fn add_cost_bound(
+    lines: &mut String, transitions: &[Transition], objective: PlanObjective, bound: u32,
+) {
+    lines.push_str("\n\n-- cost bound\nVAR\n\n");
+    lines.push_str(&format!(
+        "{i}{COST_VAR} : 0..{};\n\n",
+        bound - 1,
+        i = indent(2)
+    ));
+    lines.push_str(&format!("INIT\n\n{i}{COST_VAR} = 0\n;\n\n", i = indent(2)));
+    let cases: Vec<_> = transitions
         .iter()
-        .map(|(goal, inv)| {
-            if let Some(inv) = inv {
-                // invariant until goal
-                format!(
-                    "({inv} U {goal})",
-                    goal = &NuXMVPredicate(goal),
-                    inv = &NuXMVPredicate(inv)
-                )
-            } else {
-                // no invariant, simple "exists" goal.
-                format!("F ( {} )", &NuXMVPredicate(goal))
-            }
-        })
+        .map(|t| format!("{} : {};", NuXMVPath(&t.path), objective.weight(t)))
         .collect();
-    let goals = if goal_str.is_empty() {
-        // TODO: check this before doing everything else....
-        "TRUE".to_string()
-    } else {
-        goal_str.join("&")
-    };
-
-    // TODO: clean this up!
-    lines.push_str(&format!("LTLSPEC ! ( {goals} );"));
+    lines.push_str(&format!(
+        "TRANS\n\n{i}next({COST_VAR}) = {COST_VAR} + case {} TRUE : 0; esac\n;\n",
+        cases.join(" "),
+        i = indent(2)
+    ));
 }
 
-fn add_ctl_specs(lines: &mut String, operations: &[(String, Predicate, Predicate)]) {
-    let mut checked = Vec::new();
-    for (op_name, pre, goal) in operations {
-        match checked.iter().find(|(_, p, g)| p == pre && g == goal) {
-            Some((name, _, _)) => {
-                lines.push_str(&format!("-- {op_name} goal already checked by {name}\n\n"))
+fn add_ctl_specs(lines: &mut String, specs: &[(String, Spec)]) -> Result<(), UnsupportedConstruct> {
+    let mut checked: Vec<(&String, &Spec)> = Vec::new();
+    for (name, spec) in specs {
+        match checked.iter().find(|(_, s)| s == &spec) {
+            Some((other, _)) => {
+                lines.push_str(&format!("-- {name} already checked by {other}\n\n"))
             }
             None => {
-                lines.push_str(&format!("-- {op_name}\n"));
-                lines.push_str(&format!(
-                    "CTLSPEC AG ( {} -> EF ( {} ));\n\n",
-                    NuXMVPredicate(pre),
-                    NuXMVPredicate(goal)
-                ));
-                checked.push((op_name.clone(), pre.clone(), goal.clone()));
+                lines.push_str(&format!("-- {name}\n"));
+                lines.push_str(&format!("CTLSPEC {};\n\n", nuxmv_spec(spec)?));
+                checked.push((name, spec));
             }
         }
     }
+    Ok(())
+}
+
+#[cfg(test)]
+mod nuxmv_encoding_tests {
+    use super::*;
+
+    #[test]
+    fn encode_all_constructs() {
+        let mut tsm = TransitionSystemModel::default();
+        let x = Variable::new(
+            "x".into(),
+            SPValueType::Int32,
+            vec![0.to_spvalue(), 1.to_spvalue(), 2.to_spvalue()],
+        );
+        let y = Variable::new_boolean("y".into());
+        let t = Variable::new("t".into(), SPValueType::Time, vec![]);
+        tsm.vars.extend(vec![x.clone(), y.clone(), t.clone()]);
+        let (x, y, t) = (x.path, y.path, t.path);
+
+        let member = Predicate::MEMBER(
+            PredicateValue::SPPath(x.clone(), None),
+            PredicateValue::SPValue(SPValue::Array(
+                SPValueType::Int32,
+                vec![1.to_spvalue(), 2.to_spvalue()],
+            )),
+        );
+        let ton = Predicate::TON(
+            PredicateValue::SPPath(t.clone(), None),
+            PredicateValue::SPValue(100.to_spvalue()),
+        );
+        let function = Action::new(
+            x.clone(),
+            Compute::Function(vec![
+                (p!(y), PredicateValue::SPValue(2.to_spvalue())),
+                (p!(!y), PredicateValue::SPValue(1.to_spvalue())),
+            ]),
+        );
+        tsm.transitions.push(Transition::new(
+            "a".into(),
+            Predicate::XOR(vec![p!(y), member]),
+            vec![function, Action::new(t.clone(), Compute::TimeStamp)],
+        ));
+        tsm.transitions.push(Transition::new(
+            "b".into(),
+            ton,
+            vec![
+                Action::new(x.clone(), Compute::Any),
+                Action::new(y.clone(), Compute::Any),
+            ],
+        ));
+        tsm.transitions.push(Transition::new(
+            "c".into(),
+            p!(y),
+            vec![Action::new(x.clone(), Compute::Random(5))],
+        ));
+
+        let state = state!(x => 0, y => false);
+        let lines = create_nuxmv_problem(&tsm, &[(p!(x == 2), None)], &state).unwrap();
+        assert!(!lines.contains(" t :"));
+        assert!(lines.contains("-- timers\nIVAR\n\n  t.ton_100 : boolean;"));
+        assert!(lines.contains("( ( y = TRUE )&!( ( x = 1 )|( x = 2 ) ) )"));
+        assert!(
+            lines.contains("next(x) = case ( y = TRUE ) : 2; !(( y = TRUE )) : 1; TRUE : x; esac")
+        );
+        assert!(lines.contains("b & t.ton_100 & next(x) in {0,1,2} & next(y) in {TRUE, FALSE}"));
+        assert!(lines.contains("next(x) in {0,1,2}"));
+
+        // membership in a variable is not supported.
+        let member = Predicate::MEMBER(
+            PredicateValue::SPPath(x.clone(), None),
+            PredicateValue::SPPath(y.clone(), None),
+        );
+        tsm.invariants
+            .push(NamedPredicate::new("member".into(), member));
+        assert!(create_nuxmv_problem(&tsm, &[(p!(x == 2), None)], &state).is_err());
+        tsm.invariants.clear();
+
+        // timers are inputs, which can not be used in the invariants.
+        let ton = Predicate::TON(
+            PredicateValue::SPPath(t, None),
+            PredicateValue::SPValue(100.to_spvalue()),
+        );
+        tsm.invariants.push(NamedPredicate::new("slow".into(), ton));
+        assert!(create_nuxmv_problem(&tsm, &[], &state).is_err());
+        tsm.invariants.clear();
+
+        // time stamps can only be assigned to time variables.
+        tsm.transitions.push(Transition::new(
+            "d".into(),
+            p!(y),
+            vec![Action::new(x, Compute::TimeStamp)],
+        ));
+        assert!(create_nuxmv_problem(&tsm, &[], &state).is_err());
+    }
+
+    #[test]
+    fn encode_arithmetic() {
+        let mut tsm = TransitionSystemModel::default();
+        let x = Variable::new_range("x".into(), -2, 5);
+        tsm.vars.push(x.clone());
+        let x = x.path;
+        tsm.transitions.push(Transition::new(
+            "inc".into(),
+            p!([x < 5] && [x >= 0]),
+            vec![a!(x += 1)],
+        ));
+        tsm.transitions.push(Transition::new(
+            "dec".into(),
+            p!([x > [-2]] && [x <= 5]),
+            vec![a!(x = x - 1)],
+        ));
+        tsm.transitions
+            .push(Transition::new("any".into(), Predicate::TRUE, vec![a!(x?)]));
+
+        let state = state!(x => 0);
+        let lines = create_nuxmv_problem(&tsm, &[(p!(x == 4), None)], &state).unwrap();
+        assert!(lines.contains("x : -2..5;"));
+        assert!(lines.contains("( ( x < 5 )&( x >= 0 ) )"));
+        assert!(lines.contains("( ( x > -2 )&( x <= 5 ) )"));
+        assert!(lines.contains("next(x) = ( x + 1 )"));
+        assert!(lines.contains("next(x) = ( x - 1 )"));
+        assert!(lines.contains("next(x) in -2..5"));
+
+        tsm.transitions.push(Transition::new(
+            "bad".into(),
+            Predicate::TRUE,
+            vec![Action::new(
+                x.clone(),
+                Compute::sum(
+                    Compute::Any,
+                    Compute::PredicateValue(1.to_predicate_value()),
+                ),
+            )],
+        ));
+        assert!(create_nuxmv_problem(&tsm, &[], &state).is_err());
+    }
+
+    #[test]
+    fn encode_cost_bound() {
+        let mut tsm = TransitionSystemModel::default();
+        let x = Variable::new_boolean("x".into());
+        tsm.vars.push(x.clone());
+        let x = x.path;
+        let cost = Cost {
+            duration: 7,
+            ..Cost::default()
+        };
+        tsm.transitions
+            .push(Transition::new("a".into(), p!(!x), vec![a!(x)]).with_cost(cost));
+        tsm.transitions
+            .push(Transition::new("b".into(), p!(x), vec![a!(!x)]));
+
+        let mut lines = String::new();
+        add_cost_bound(&mut lines, &tsm.transitions, PlanObjective::TotalDuration, 10);
+        assert!(lines.contains("_plan_cost : 0..9;"));
+        assert!(lines.contains("_plan_cost = 0"));
+        assert!(lines.contains("next(_plan_cost) = _plan_cost + case a : 7; b : 0; TRUE : 0; esac"));
+
+        // the cost variable is not part of the plan.
+        let raw = "Trace Type: Counterexample\n  -> State: 1.1 <-\n    x = FALSE\n    \
+                   _plan_cost = 0\n  -> Input: 1.2 <-\n    a = TRUE\n    b = FALSE\n  \
+                   -> State: 1.2 <-\n    x = TRUE\n    _plan_cost = 7\nnuXmv > ";
+        let trace = postprocess_nuxmv_problem(&tsm, raw).unwrap().unwrap();
+        assert_eq!(trace.len(), 2);
+        assert_eq!(trace[1].transition, SPPath::from("a"));
+        assert_eq!(
+            trace[1].state.sp_value_from_path(&x),
+            Some(&true.to_spvalue())
+        );
+        assert_eq!(
+            trace[1].state.sp_value_from_path(&SPPath::from(COST_VAR)),
+            None
+        );
+        assert_eq!(plan_cost(&tsm, &trace), cost);
+    }
+
+    #[test]
+    fn nuxmv_errors() {
+        let model = "MODULE main\n\nVAR\n  x : {1,2};\n";
+        let stderr = "file /tmp/x.bmc: line 4: syntax error\nexiting\n";
+        assert_eq!(
+            check_stderr(stderr, model),
+            Err(PlanningError::Syntax {
+                line: 4,
+                message: "syntax error".into(),
+                model_line: "  x : {1,2};".into(),
+            })
+        );
+        assert_eq!(
+            check_stderr("There are no traces currently available.\n", model),
+            Ok(())
+        );
+        assert!(matches!(
+            check_stderr("segmentation fault", model),
+            Err(PlanningError::ProcessFailed(_))
+        ));
+
+        assert_eq!(
+            spval_from_nuxvm("3", SPValueType::Int32),
+            Ok(3.to_spvalue())
+        );
+        assert!(matches!(
+            spval_from_nuxvm("three", SPValueType::Int32),
+            Err(PlanningError::TraceParse(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn concurrent_search_is_bmc_only() {
+        let config = NuXmvConfig {
+            binary: Some("/nonexistent/nuXmv".into()),
+            ..NuXmvConfig::with_engine(NuXmvEngine::Ic3)
+        };
+        let search = search_heuristic(
+            config,
+            "model.bmc".into(),
+            5,
+            10,
+            2.0,
+            Duration::from_secs(1),
+            None,
+        );
+        assert!(matches!(search.await, Err(PlanningError::Unsupported(_))));
+    }
 }
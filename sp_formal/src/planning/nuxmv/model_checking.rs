@@ -0,0 +1,333 @@
+use super::*;
+use std::collections::HashMap;
+
+/// A named temporal logic property to check against a model.
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
+pub struct Property {
+    pub name: String,
+    pub spec: Spec,
+}
+
+impl Property {
+    pub fn new(name: &str, spec: Spec) -> Self {
+        Property {
+            name: name.to_string(),
+            spec,
+        }
+    }
+
+    /// The predicate must hold in all reachable states.
+    pub fn invariant(name: &str, predicate: &Predicate) -> Self {
+        Property::new(name, Spec::AG(Box::new(Spec::Pred(predicate.clone()))))
+    }
+
+    /// From all reachable states some transition must be enabled.
+    pub fn deadlock_free(name: &str) -> Self {
+        Property::new(
+            name,
+            Spec::AG(Box::new(Spec::EX(Box::new(Spec::Pred(Predicate::TRUE))))),
+        )
+    }
+
+    // nuXmv identifiers can not contain all characters allowed in our
+    // names. the index keeps e.g. "a-b" and "a_b" apart.
+    fn identifier(&self, index: usize) -> String {
+        let id: String = self
+            .name
+            .chars()
+            .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+            .collect();
+        format!("prop_{index}_{id}")
+    }
+
+    fn check_command(&self) -> Result<(&'static str, &'static str), PlanningError> {
+        if self.spec.invariant().is_some() {
+            Ok(("INVARSPEC", "check_invar"))
+        } else if self.spec.is_ctl() {
+            Ok(("CTLSPEC", "check_ctlspec"))
+        } else if self.spec.is_ltl() {
+            Ok(("LTLSPEC", "check_ltlspec"))
+        } else {
+            Err(PlanningError::Unsupported(format!(
+                "{} mixes LTL and CTL operators: {}",
+                self.name, self.spec
+            )))
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
+pub struct PropertyResult {
+    pub name: String,
+    pub holds: bool,
+    /// The counterexample if the property does not hold.
+    pub counterexample: Option<Vec<PlanningFrame>>,
+    /// For infinite counterexamples, the frame where the loop starts.
+    pub loop_start: Option<usize>,
+}
+
+fn add_properties(lines: &mut String, properties: &[Property]) -> Result<(), PlanningError> {
+    for (i, p) in properties.iter().enumerate() {
+        let id = p.identifier(i);
+        let spec = match p.check_command()? {
+            ("INVARSPEC", _) => {
+                let invariant = p.spec.invariant().expect("checked above");
+                format!("INVARSPEC NAME {id} := {};", nuxmv_predicate(&invariant)?)
+            }
+            (kind, _) => format!("{kind} NAME {id} := {};", nuxmv_spec(&p.spec)?),
+        };
+        lines.push_str(&format!("-- {}\n{spec}\n\n", p.name));
+    }
+    Ok(())
+}
+
+/// Check a number of properties against the model, starting from all
+/// states satisfying `initial`. Returns one result per property, in
+/// the same order. nuXmv is run as given by `config`, except for the
+/// engine which depends on the kind of property.
+pub fn check_properties(
+    model: &TransitionSystemModel, initial: &Predicate, properties: &[Property],
+    config: &NuXmvConfig,
+) -> Result<Vec<PropertyResult>, PlanningError> {
+    let preds: Vec<_> = properties
+        .iter()
+        .flat_map(|p| p.spec.predicates())
+        .chain([initial])
+        .collect();
+    let mut lines = make_base_problem(model, &preds)?;
+    add_initial_states(&mut lines, initial)?;
+    add_properties(&mut lines, properties)?;
+
+    let tempfile = write_problem(&lines)?;
+    let filename = tempfile.as_os_str().to_str().expect("invalid path");
+
+    let mut command = "go\n".to_string();
+    for (i, p) in properties.iter().enumerate() {
+        let (_, check) = p.check_command()?;
+        command.push_str(&format!("{check} -P {}\n", p.identifier(i)));
+    }
+    command.push_str("quit\n");
+
+    let (raw, raw_error) = run_nuxmv(config, filename, &command, &CancellationToken::new())?;
+    if raw_error.contains("error") || raw_error.contains("Error") {
+        check_stderr(&raw_error, &lines)?;
+    }
+
+    parse_property_results(model, properties, &raw)
+}
+
+/// Split the output into one part per checked specification.
+fn parse_property_results(
+    model: &TransitionSystemModel, properties: &[Property], raw: &str,
+) -> Result<Vec<PropertyResult>, PlanningError> {
+    let mut chunks: Vec<Vec<&str>> = Vec::new();
+    for l in raw.lines() {
+        if l.starts_with("-- specification") || l.starts_with("-- invariant") {
+            chunks.push(vec![l]);
+        } else if let Some(chunk) = chunks.last_mut() {
+            chunk.push(l);
+        }
+    }
+
+    if chunks.len() != properties.len() {
+        return Err(PlanningError::TraceParse(format!(
+            "expected {} results from nuXmv, got {}",
+            properties.len(),
+            chunks.len()
+        )));
+    }
+
+    properties
+        .iter()
+        .zip(chunks.iter())
+        .map(|(p, chunk)| {
+            let holds = chunk[0].trim_end().ends_with("is true");
+            let (counterexample, loop_start) = if holds {
+                (None, None)
+            } else {
+                let (trace, loop_start) = parse_counterexample(model, &chunk[1..])?;
+                (Some(trace), loop_start)
+            };
+            Ok(PropertyResult {
+                name: p.name.clone(),
+                holds,
+                counterexample,
+                loop_start,
+            })
+        })
+        .collect()
+}
+
+/// Parse a counterexample trace. nuXmv only prints the values that
+/// changed since the previous state, so we carry all values forward.
+fn parse_counterexample(
+    model: &TransitionSystemModel, lines: &[&str],
+) -> Result<(Vec<PlanningFrame>, Option<usize>), PlanningError> {
+    let mut trace: Vec<PlanningFrame> = Vec::new();
+    let mut loop_start = None;
+    let mut inputs: HashMap<SPPath, bool> = HashMap::new();
+    let mut in_input = false;
+
+    for l in lines {
+        if l.contains("-- Loop starts here") {
+            loop_start = Some(trace.len());
+        } else if l.contains("-> State: ") {
+            in_input = false;
+            let state = trace.last().map(|f| f.state.clone()).unwrap_or_default();
+            let transition = if trace.is_empty() {
+                SPPath::default()
+            } else {
+                model
+                    .transitions
+                    .iter()
+                    .find(|t| inputs.get(&t.path) == Some(&true))
+                    .map(|t| t.path.clone())
+                    .unwrap_or_default()
+            };
+            trace.push(PlanningFrame { state, transition });
+        } else if l.contains("-> Input: ") {
+            in_input = true;
+        } else if let Some((path, val)) = l.split_once('=') {
+            let (path, val): (SPPath, _) = (path.trim().into(), val.trim());
+            if in_input {
+                inputs.insert(path, val == "TRUE");
+            } else if let Some(frame) = trace.last_mut() {
+                let spt = if model.state_predicates.iter().any(|p| p.path == path) {
+                    SPValueType::Bool
+                } else if let Some(v) = model.vars.iter().find(|v| v.path == path) {
+                    v.value_type
+                } else {
+                    // property names etc.
+                    continue;
+                };
+                frame.state.add_variable(path, spval_from_nuxvm(val, spt)?);
+            }
+        }
+    }
+
+    Ok((trace, loop_start))
+}
+
+#[cfg(test)]
+mod model_checking_tests {
+    use super::*;
+
+    #[test]
+    fn parse_ctl_counterexample() {
+        let mut tsm = TransitionSystemModel::default();
+        let x = Variable::new_boolean("x".into());
+        let y = Variable::new_boolean("y".into());
+        tsm.vars.push(x.clone());
+        tsm.vars.push(y.clone());
+        tsm.transitions
+            .push(Transition::new("set_x".into(), p!(!x), vec![a!(x)]));
+        tsm.transitions
+            .push(Transition::new("set_y".into(), p!(!y), vec![a!(y)]));
+
+        let properties = [
+            Property::invariant("never_x", &p!(!x)),
+            Property::deadlock_free("no_deadlock"),
+            Property::new("y_reachable", Spec::from_string("EF {p:y}").unwrap()),
+        ];
+
+        let raw = "\
+-- specification AG EX TRUE  is false
+-- as demonstrated by the following execution sequence
+Trace Description: CTL Counterexample
+Trace Type: Counterexample
+  -> State: 1.1 <-
+    x = FALSE
+    y = FALSE
+  -> Input: 1.2 <-
+    set_x = TRUE
+    set_y = FALSE
+  -> State: 1.2 <-
+    x = TRUE
+  -> Input: 1.3 <-
+    set_x = FALSE
+    set_y = TRUE
+  -> State: 1.3 <-
+    y = TRUE
+-- invariant !x  is false
+-- as demonstrated by the following execution sequence
+Trace Description: AG alpha Counterexample
+Trace Type: Counterexample
+  -- Loop starts here
+  -> State: 2.1 <-
+    x = FALSE
+    y = TRUE
+  -> Input: 2.2 <-
+    set_x = TRUE
+    set_y = FALSE
+  -> State: 2.2 <-
+    x = TRUE
+-- specification EF y  is true
+nuXmv > ";
+
+        // results are in order of the checks, not necessarily the order of specs
+        let ordered = [
+            properties[1].clone(),
+            properties[0].clone(),
+            properties[2].clone(),
+        ];
+        let results = parse_property_results(&tsm, &ordered, raw).unwrap();
+        assert_eq!(results.len(), 3);
+
+        assert!(!results[0].holds);
+        let trace = results[0].counterexample.as_ref().unwrap();
+        let trans: Vec<_> = trace.iter().map(|f| f.transition.to_string()).collect();
+        assert_eq!(trans, vec!["", "set_x", "set_y"]);
+        // unchanged values are carried forward
+        assert_eq!(
+            trace[2].state.sp_value_from_path(&x.path),
+            Some(&true.to_spvalue())
+        );
+        assert_eq!(
+            trace[2].state.sp_value_from_path(&y.path),
+            Some(&true.to_spvalue())
+        );
+        assert_eq!(results[0].loop_start, None);
+
+        assert!(!results[1].holds);
+        assert_eq!(results[1].name, "never_x");
+        assert_eq!(results[1].loop_start, Some(0));
+
+        assert!(results[2].holds);
+        assert!(results[2].counterexample.is_none());
+
+        assert!(parse_property_results(&tsm, &properties[..2], raw).is_err());
+
+        let mut lines = String::new();
+        add_properties(&mut lines, &properties).unwrap();
+        assert!(lines.contains("INVARSPEC NAME prop_0_never_x := !(( x = TRUE ));"));
+        assert!(lines.contains("CTLSPEC NAME prop_1_no_deadlock := AG ( EX ( TRUE ) );"));
+        assert!(lines.contains("CTLSPEC NAME prop_2_y_reachable := EF ( ( y = TRUE ) );"));
+
+        // names that only differ in characters nuXmv does not allow
+        let similar = [
+            Property::invariant("never-x", &p!(!x)),
+            Property::invariant("never_x", &p!(!x)),
+        ];
+        let mut similar_lines = String::new();
+        add_properties(&mut similar_lines, &similar).unwrap();
+        assert!(similar_lines.contains("NAME prop_0_never_x :="));
+        assert!(similar_lines.contains("NAME prop_1_never_x :="));
+
+        let mixed = Property::new("mixed", Spec::from_string("AG F {p:x}").unwrap());
+        assert!(add_properties(&mut lines, &[mixed]).is_err());
+    }
+
+    #[test]
+    fn check_with_the_given_binary() {
+        let mut tsm = TransitionSystemModel::default();
+        let x = Variable::new_boolean("x".into());
+        tsm.vars.push(x.clone());
+        let config = NuXmvConfig {
+            binary: Some("/nonexistent/nuXmv".into()),
+            ..NuXmvConfig::default()
+        };
+        let properties = [Property::invariant("never_x", &p!(!x))];
+        let result = check_properties(&tsm, &p!(!x), &properties, &config);
+        assert!(matches!(result, Err(PlanningError::Io(_))), "{:?}", result);
+    }
+}
@@ -1,6 +1,10 @@
-use super::find_binary;
+use super::{NuXmvConfig, NuXmvEngine};
+use crate::planning::{
+    report_progress, CancellationToken, PlanningError, PlanningProgress, ProgressSender,
+};
 use futures::future::select_all;
 use std::future::Future;
+use std::path::PathBuf;
 use std::pin::Pin;
 use std::process::Stdio;
 use std::task::{Context, Poll};
@@ -13,11 +17,11 @@ pub type NuxmvOutput = (u32, String, String);
 
 pub struct WrappedWorkTask {
     max_steps: u32,
-    inner: Pin<Box<dyn Future<Output = std::io::Result<NuxmvOutput>>>>,
+    inner: Pin<Box<dyn Future<Output = Result<NuxmvOutput, PlanningError>> + Send>>,
 }
 
 impl Future for WrappedWorkTask {
-    type Output = std::io::Result<NuxmvOutput>;
+    type Output = Result<NuxmvOutput, PlanningError>;
     fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
         self.inner.as_mut().poll(cx)
     }
@@ -25,16 +29,18 @@ impl Future for WrappedWorkTask {
 
 impl WrappedWorkTask {
     fn from(
-        max_steps: u32, inner: Pin<Box<dyn Future<Output = std::io::Result<NuxmvOutput>>>>,
+        max_steps: u32,
+        inner: Pin<Box<dyn Future<Output = Result<NuxmvOutput, PlanningError>> + Send>>,
     ) -> Self {
         Self { max_steps, inner }
     }
 }
 
 async fn call_nuxmv_async(
-    filename: String, command: String, max_len: u32,
-) -> std::io::Result<NuxmvOutput> {
-    let mut process = Command::new(find_binary::find_nuxmv())
+    binary: PathBuf, args: Vec<String>, filename: String, command: String, max_len: u32,
+) -> Result<NuxmvOutput, PlanningError> {
+    let mut process = Command::new(binary)
+        .args(args)
         .arg("-int")
         .arg(filename)
         .stdin(Stdio::piped())
@@ -47,15 +53,38 @@ async fn call_nuxmv_async(
     stdin.write_all(command.as_bytes()).await?;
 
     let result = process.wait_with_output().await?;
-
-    assert!(result.status.success());
-
-    let raw = String::from_utf8(result.stdout).expect("Check character encoding");
-    let raw_error = String::from_utf8(result.stderr).expect("Check character encoding");
+    let (raw, raw_error) = super::process_output(result)?;
 
     Ok((max_len, raw, raw_error))
 }
 
+/// Run nuXmv on the model in `filename`. The process is killed if it
+/// times out, planning is cancelled, or the future is dropped.
+pub(super) async fn run_nuxmv_async(
+    config: &NuXmvConfig, filename: &str, command: String, cancel: &CancellationToken,
+) -> Result<(String, String), PlanningError> {
+    let call = call_nuxmv_async(
+        config.binary()?,
+        config.args.clone(),
+        filename.to_owned(),
+        command,
+        0,
+    );
+    let call = async {
+        match config.timeout {
+            Some(t) => timeout(t, call)
+                .await
+                .map_err(|_| PlanningError::Timeout(t))?,
+            None => call.await,
+        }
+    };
+    tokio::select! {
+        biased;
+        _ = cancel.cancelled() => Err(PlanningError::Cancelled),
+        result = call => result.map(|(_, raw, raw_error)| (raw, raw_error)),
+    }
+}
+
 /// Look for plans concurrently.
 /// We start one process that searches incrementally from step 1 up to `cutoff`.
 /// At the sime time, we start multiple processes for plan lengths of cutoff+1 to max_steps.
@@ -63,14 +92,38 @@ async fn call_nuxmv_async(
 /// for plans with either to few or too many step. Let the remaining processes run for the
 /// time it has taken to find the first guess times the `lookout`-factor. When this time is up,
 /// return the solution with the shortest plan length.
-async fn search_heuristic(
-    filename: String, cutoff: u32, max_steps: u32, lookout: f32, max_time: Duration,
-) -> Option<NuxmvOutput> {
+///
+/// Returns `None` if no plan exists within `max_steps`, and a timeout
+/// error if no process finished within `max_time`, or the timeout of
+/// the config if that is shorter. All processes are killed when the
+/// future is dropped.
+///
+/// The search is built on single bound BMC problems, so only the
+/// `BmcIncremental` engine is supported.
+pub(super) async fn search_heuristic(
+    config: NuXmvConfig, filename: String, cutoff: u32, max_steps: u32, lookout: f32,
+    max_time: Duration, progress: Option<&ProgressSender>,
+) -> Result<Option<NuxmvOutput>, PlanningError> {
+    if config.engine != NuXmvEngine::BmcIncremental {
+        return Err(PlanningError::Unsupported(format!(
+            "the concurrent search can not use the {:?} engine",
+            config.engine
+        )));
+    }
+    let max_time = config.timeout.map_or(max_time, |t| t.min(max_time));
+    let binary = config.binary()?;
+    report_progress(progress, PlanningProgress::Searching { bound: max_steps });
     let mut tasks = Vec::new();
 
     // steps 1..cutoff, normal incremental solver
     let command = format!("go_bmc\ncheck_ltlspec_bmc_inc -k {cutoff}\nshow_traces -v\nquit\n",);
-    let fut = Box::pin(call_nuxmv_async(filename.clone(), command, cutoff));
+    let fut = Box::pin(call_nuxmv_async(
+        binary.clone(),
+        config.args.clone(),
+        filename.clone(),
+        command,
+        cutoff,
+    ));
     tasks.push(Box::pin(timeout(
         max_time,
         WrappedWorkTask::from(cutoff, fut),
@@ -81,35 +134,40 @@ async fn search_heuristic(
     for i in (cutoff + 1)..max_steps {
         // only look at every third lengths
         let command = format!("go_bmc\ncheck_ltlspec_bmc_onepb -k {i}\nshow_traces -v\nquit\n",);
-        let fut = Box::pin(call_nuxmv_async(filename.clone(), command, i));
+        let fut = Box::pin(call_nuxmv_async(
+            binary.clone(),
+            config.args.clone(),
+            filename.clone(),
+            command,
+            i,
+        ));
         tasks.push(Box::pin(timeout(max_time, WrappedWorkTask::from(i, fut))));
     }
 
     let now = std::time::Instant::now();
+    let mut any_finished = false;
     let mut one = select_all(tasks);
     loop {
         let (x, _, mut remaining) = one.await;
 
         let (c, r, e) = match x {
-            Result::Ok(Result::Ok((c, r, e))) => (c, r, e),
-            Result::Ok(_) => {
-                panic!("Something went wrong when invoking planner. Check your PATH.");
-            }
+            Result::Ok(res) => res?,
             _ => {
                 if remaining.is_empty() {
-                    return None;
+                    return if any_finished {
+                        Ok(None)
+                    } else {
+                        Err(PlanningError::Timeout(max_time))
+                    };
                 }
                 one = select_all(remaining);
                 continue;
             }
         };
+        any_finished = true;
 
         if !r.contains("Trace Type: Counterexample") {
-            println!(
-                "no plan found for bound {} after {}ms",
-                c,
-                now.elapsed().as_millis()
-            );
+            report_progress(progress, PlanningProgress::NoPlan { bound: c });
             // stop looking for shorter plans, except for the cutoff
             // point. the reason for keeping the cutoff point is to
             // handle the case the only valid plan is short. then it
@@ -118,18 +176,13 @@ async fn search_heuristic(
             // shorter task is discarded.
             remaining.retain(|f| f.get_ref().max_steps > c || f.get_ref().max_steps == cutoff);
             if remaining.is_empty() {
-                return None;
+                return Ok(None);
             }
             one = select_all(remaining);
         } else {
-            println!(
-                "first guess found after after {}ms (with plan length: {})",
-                now.elapsed().as_millis(),
-                c
-            );
+            report_progress(progress, PlanningProgress::PlanFound { length: c });
 
             let dur = now.elapsed().mul_f32(lookout);
-            println!("setting timeout to {}", dur.as_millis());
             let remaining: Vec<_> = remaining
                 .into_iter()
                 .filter(|f| f.get_ref().max_steps < c) // stop looking for longer plans
@@ -137,7 +190,7 @@ async fn search_heuristic(
 
             if remaining.is_empty() {
                 // no more tasks to run!
-                return Some((c, r, e));
+                return Ok(Some((c, r, e)));
             }
 
             let mut solutions = vec![(c, r, e)];
@@ -147,11 +200,13 @@ async fn search_heuristic(
                 // outer timeout, io error, inner (global) timeout
                 if let Result::Ok(Result::Ok(Result::Ok((c, r, e)))) = x {
                     if r.contains("Trace Type: Counterexample") {
+                        report_progress(progress, PlanningProgress::PlanFound { length: c });
                         // save solution
                         solutions.push((c, r, e));
                         // stop looking for longer plans
                         remaining.retain(|f| f.get_ref().get_ref().max_steps < c);
                     } else {
+                        report_progress(progress, PlanningProgress::NoPlan { bound: c });
                         // stop looking for shorter plans
                         remaining.retain(|f| f.get_ref().get_ref().max_steps > c);
                     }
@@ -161,18 +216,7 @@ async fn search_heuristic(
                 }
                 one = select_all(remaining);
             }
-            return solutions.into_iter().min_by(|x, y| x.0.cmp(&y.0));
+            return Ok(solutions.into_iter().min_by(|x, y| x.0.cmp(&y.0)));
         }
     }
 }
-
-pub fn block_on_search_heuristic(
-    filename: &str, cutoff: u32, max_steps: u32, lookout: f32, max_time: Duration,
-) -> Result<NuxmvOutput, Box<dyn std::error::Error>> {
-    //let runtime = tokio::runtime::Runtime::new()?;
-    let fut = search_heuristic(filename.to_owned(), cutoff, max_steps, lookout, max_time);
-
-    // let result = runtime.block_on(fut);
-    let result = futures::executor::block_on(fut);
-    result.ok_or("No result found".into())
-}
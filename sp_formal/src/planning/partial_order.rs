@@ -0,0 +1,249 @@
+use crate::TransitionSystemModel;
+use serde::{Deserialize, Serialize};
+use sp_domain::*;
+use std::collections::HashSet;
+use std::fmt;
+
+use super::PlanningFrame;
+
+/// A step of a `PartialOrderPlan`.
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
+pub struct PlanStep {
+    pub transition: SPPath,
+    /// Indices of the steps that must be done before this step is taken.
+    pub predecessors: Vec<usize>,
+    pub done: bool,
+    /// The step could not be taken, the steps after it never will be.
+    #[serde(default)]
+    pub failed: bool,
+}
+
+/// A plan where each step only waits for the steps it depends on, so
+/// that independent steps, e.g. of different resources, can be taken
+/// in parallel. Only the controllable transitions of the plan are
+/// included, the others happen by themselves.
+#[derive(Debug, PartialEq, Clone, Default, Serialize, Deserialize)]
+pub struct PartialOrderPlan {
+    pub steps: Vec<PlanStep>,
+}
+
+/// Variables read and modified by a transition. State predicates are
+/// replaced by the variables they depend on.
+fn reads_writes(
+    model: &TransitionSystemModel, t: &Transition,
+) -> (HashSet<SPPath>, HashSet<SPPath>) {
+    let mut reads: Vec<SPPath> = t.guard.support();
+    reads.extend(t.actions.iter().flat_map(|a| a.value.support()));
+
+    let mut expanded = HashSet::new();
+    while let Some(path) = reads.pop() {
+        if !expanded.insert(path.clone()) {
+            continue;
+        }
+        if let Some(sp) = model.state_predicates.iter().find(|p| p.path == path) {
+            reads.extend(sp.predicate.support());
+        }
+    }
+    (expanded, t.modifies())
+}
+
+impl PartialOrderPlan {
+    /// Relax the order of a sequential plan. A step depends on an
+    /// earlier step if one of them modifies a variable that the other
+    /// reads or modifies.
+    pub fn from_trace(model: &TransitionSystemModel, trace: &[PlanningFrame]) -> Self {
+        let transitions: Vec<&Transition> = trace
+            .iter()
+            .flat_map(|f| model.transitions.iter().find(|t| t.path == f.transition))
+            .collect();
+        let rw: Vec<_> = transitions.iter().map(|t| reads_writes(model, t)).collect();
+
+        let depends: Vec<Vec<usize>> = (0..rw.len())
+            .map(|j| {
+                let (reads_j, writes_j) = &rw[j];
+                (0..j)
+                    .filter(|i| {
+                        let (reads_i, writes_i) = &rw[*i];
+                        !writes_i.is_disjoint(reads_j)
+                            || !writes_i.is_disjoint(writes_j)
+                            || !reads_i.is_disjoint(writes_j)
+                    })
+                    .collect()
+            })
+            .collect();
+
+        // keep the controllable steps, depending on each other through
+        // any uncontrollable steps in between.
+        let controllable: Vec<bool> = transitions
+            .iter()
+            .map(|t| !model.uncontrollable.contains(&t.path))
+            .collect();
+        let mut index = vec![0; transitions.len()];
+        let mut steps = Vec::new();
+        for j in (0..transitions.len()).filter(|j| controllable[*j]) {
+            let mut predecessors = HashSet::new();
+            let mut visited = HashSet::new();
+            let mut stack = depends[j].clone();
+            while let Some(i) = stack.pop() {
+                if !visited.insert(i) {
+                    continue;
+                }
+                if controllable[i] {
+                    predecessors.insert(index[i]);
+                } else {
+                    stack.extend(depends[i].iter().cloned());
+                }
+            }
+            let mut predecessors: Vec<_> = predecessors.into_iter().collect();
+            predecessors.sort_unstable();
+            index[j] = steps.len();
+            steps.push(PlanStep {
+                transition: transitions[j].path.clone(),
+                predecessors,
+                done: false,
+                failed: false,
+            });
+        }
+
+        PartialOrderPlan { steps }
+    }
+
+    /// The steps that are neither done nor failed but whose predecessors are done.
+    pub fn ready(&self) -> Vec<usize> {
+        (0..self.steps.len())
+            .filter(|i| {
+                !self.steps[*i].done
+                    && !self.steps[*i].failed
+                    && self.steps[*i]
+                        .predecessors
+                        .iter()
+                        .all(|p| self.steps[*p].done)
+            })
+            .collect()
+    }
+
+    pub fn set_done(&mut self, step: usize) {
+        self.steps[step].done = true;
+    }
+
+    pub fn set_failed(&mut self, step: usize) {
+        self.steps[step].failed = true;
+    }
+
+    pub fn is_done(&self) -> bool {
+        self.steps.iter().all(|s| s.done)
+    }
+
+    pub fn is_failed(&self) -> bool {
+        self.steps.iter().any(|s| s.failed)
+    }
+
+    /// Whether the plan has the same steps, regardless of progress.
+    pub fn same_steps(&self, other: &PartialOrderPlan) -> bool {
+        self.steps.len() == other.steps.len()
+            && self
+                .steps
+                .iter()
+                .zip(&other.steps)
+                .all(|(a, b)| a.transition == b.transition && a.predecessors == b.predecessors)
+    }
+}
+
+impl fmt::Display for PartialOrderPlan {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let done = self.steps.iter().filter(|s| s.done).count();
+        write!(f, "plan {}/{}:", done, self.steps.len())?;
+        for s in &self.steps {
+            let mark = if s.failed {
+                "!"
+            } else if s.done {
+                "x"
+            } else {
+                " "
+            };
+            write!(f, " [{}] {}", mark, s.transition)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod partial_order_tests {
+    use super::*;
+
+    #[test]
+    fn independent_resources_in_parallel() {
+        let mut tsm = TransitionSystemModel::default();
+        let r1 = SPPath::from("r1");
+        let r2 = SPPath::from("r2");
+        let done = SPPath::from("done");
+        let both = SPPath::from("both");
+        tsm.state_predicates
+            .push(NamedPredicate::new(both.clone(), p!([r1] && [r2])));
+        tsm.transitions
+            .push(Transition::new("r1_start".into(), p!(!r1), vec![a!(r1)]));
+        tsm.transitions
+            .push(Transition::new("r2_start".into(), p!(!r2), vec![a!(r2)]));
+        tsm.transitions
+            .push(Transition::new("finish".into(), p!(both), vec![a!(done)]));
+        tsm.transitions
+            .push(Transition::new("report".into(), p!(done), vec![]));
+        tsm.uncontrollable.push("finish".into());
+
+        let trace: Vec<_> = ["", "r1_start", "r2_start", "finish", "report"]
+            .iter()
+            .map(|t| PlanningFrame {
+                transition: SPPath::from(*t),
+                state: SPState::new(),
+            })
+            .collect();
+        let mut plan = PartialOrderPlan::from_trace(&tsm, &trace);
+        let steps: Vec<_> = plan
+            .steps
+            .iter()
+            .map(|s| (s.transition.to_string(), s.predecessors.clone()))
+            .collect();
+        // the uncontrollable finish is left out, but report still
+        // depends on both starts through it.
+        assert_eq!(
+            steps,
+            vec![
+                ("r1_start".to_string(), vec![]),
+                ("r2_start".to_string(), vec![]),
+                ("report".to_string(), vec![0, 1]),
+            ]
+        );
+
+        assert_eq!(plan.ready(), vec![0, 1]);
+        plan.set_done(1);
+        assert_eq!(plan.ready(), vec![0]);
+        plan.set_done(0);
+        assert_eq!(plan.ready(), vec![2]);
+        plan.set_done(2);
+        assert!(plan.is_done());
+        assert_eq!(
+            plan.to_string(),
+            "plan 3/3: [x] r1_start [x] r2_start [x] report"
+        );
+    }
+
+    #[test]
+    fn failed_steps_block_their_successors() {
+        let step = |transition: &str, predecessors: Vec<usize>| PlanStep {
+            transition: transition.into(),
+            predecessors,
+            done: false,
+            failed: false,
+        };
+        let mut plan = PartialOrderPlan {
+            steps: vec![step("a", vec![]), step("b", vec![0]), step("c", vec![])],
+        };
+        plan.set_failed(0);
+        assert!(plan.is_failed());
+        assert_eq!(plan.ready(), vec![2]);
+        plan.set_done(2);
+        assert!(plan.ready().is_empty());
+        assert!(!plan.is_done());
+        assert_eq!(plan.to_string(), "plan 1/3: [!] a [ ] b [x] c");
+    }
+}
@@ -0,0 +1,456 @@
+//! A small reduced ordered binary decision diagram package.
+//!
+//! Nodes are never garbage collected, a manager is meant to live for
+//! the duration of a single planning or reachability query.
+
+use std::collections::{HashMap, HashSet};
+
+/// A reference to a node in a `BddManager`.
+pub type Bdd = u32;
+
+pub const FALSE: Bdd = 0;
+pub const TRUE: Bdd = 1;
+
+const TERMINAL: u32 = u32::MAX;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+enum Op {
+    And,
+    Or,
+    Xor,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+struct Node {
+    var: u32,
+    low: Bdd,
+    high: Bdd,
+}
+
+/// Holds all nodes. Variables are ordered by their index, lower
+/// indices are closer to the root.
+#[derive(Debug, Clone)]
+pub struct BddManager {
+    nodes: Vec<Node>,
+    unique: HashMap<Node, Bdd>,
+    apply_cache: HashMap<(Op, Bdd, Bdd), Bdd>,
+    not_cache: HashMap<Bdd, Bdd>,
+}
+
+impl Default for BddManager {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl BddManager {
+    pub fn new() -> Self {
+        let terminal = |b| Node {
+            var: TERMINAL,
+            low: b,
+            high: b,
+        };
+        BddManager {
+            nodes: vec![terminal(FALSE), terminal(TRUE)],
+            unique: HashMap::new(),
+            apply_cache: HashMap::new(),
+            not_cache: HashMap::new(),
+        }
+    }
+
+    pub fn node_count(&self) -> usize {
+        self.nodes.len()
+    }
+
+    fn var_of(&self, f: Bdd) -> u32 {
+        self.nodes[f as usize].var
+    }
+
+    fn low(&self, f: Bdd) -> Bdd {
+        self.nodes[f as usize].low
+    }
+
+    fn high(&self, f: Bdd) -> Bdd {
+        self.nodes[f as usize].high
+    }
+
+    fn mk(&mut self, var: u32, low: Bdd, high: Bdd) -> Bdd {
+        if low == high {
+            return low;
+        }
+        let node = Node { var, low, high };
+        if let Some(f) = self.unique.get(&node) {
+            return *f;
+        }
+        self.nodes.push(node);
+        let f = (self.nodes.len() - 1) as Bdd;
+        self.unique.insert(node, f);
+        f
+    }
+
+    /// The function that is true when `var` is true.
+    pub fn var(&mut self, var: u32) -> Bdd {
+        self.mk(var, FALSE, TRUE)
+    }
+
+    /// The function that is true when `var` is false.
+    pub fn nvar(&mut self, var: u32) -> Bdd {
+        self.mk(var, TRUE, FALSE)
+    }
+
+    pub fn not(&mut self, f: Bdd) -> Bdd {
+        if f == FALSE {
+            return TRUE;
+        }
+        if f == TRUE {
+            return FALSE;
+        }
+        if let Some(r) = self.not_cache.get(&f) {
+            return *r;
+        }
+        let (var, low, high) = (self.var_of(f), self.low(f), self.high(f));
+        let low = self.not(low);
+        let high = self.not(high);
+        let r = self.mk(var, low, high);
+        self.not_cache.insert(f, r);
+        r
+    }
+
+    pub fn and(&mut self, a: Bdd, b: Bdd) -> Bdd {
+        self.apply(Op::And, a, b)
+    }
+
+    pub fn or(&mut self, a: Bdd, b: Bdd) -> Bdd {
+        self.apply(Op::Or, a, b)
+    }
+
+    pub fn xor(&mut self, a: Bdd, b: Bdd) -> Bdd {
+        self.apply(Op::Xor, a, b)
+    }
+
+    pub fn iff(&mut self, a: Bdd, b: Bdd) -> Bdd {
+        let x = self.xor(a, b);
+        self.not(x)
+    }
+
+    pub fn implies(&mut self, a: Bdd, b: Bdd) -> Bdd {
+        let na = self.not(a);
+        self.or(na, b)
+    }
+
+    pub fn and_all(&mut self, fs: &[Bdd]) -> Bdd {
+        fs.iter().fold(TRUE, |acc, f| self.and(acc, *f))
+    }
+
+    pub fn or_all(&mut self, fs: &[Bdd]) -> Bdd {
+        fs.iter().fold(FALSE, |acc, f| self.or(acc, *f))
+    }
+
+    fn apply(&mut self, op: Op, a: Bdd, b: Bdd) -> Bdd {
+        match op {
+            Op::And => {
+                if a == FALSE || b == FALSE {
+                    return FALSE;
+                }
+                if a == TRUE || a == b {
+                    return b;
+                }
+                if b == TRUE {
+                    return a;
+                }
+            }
+            Op::Or => {
+                if a == TRUE || b == TRUE {
+                    return TRUE;
+                }
+                if a == FALSE || a == b {
+                    return b;
+                }
+                if b == FALSE {
+                    return a;
+                }
+            }
+            Op::Xor => {
+                if a == b {
+                    return FALSE;
+                }
+                if a == FALSE {
+                    return b;
+                }
+                if b == FALSE {
+                    return a;
+                }
+                if a == TRUE {
+                    return self.not(b);
+                }
+                if b == TRUE {
+                    return self.not(a);
+                }
+            }
+        }
+
+        // all operations are commutative
+        let (a, b) = if a < b { (a, b) } else { (b, a) };
+        if let Some(r) = self.apply_cache.get(&(op, a, b)) {
+            return *r;
+        }
+
+        let (va, vb) = (self.var_of(a), self.var_of(b));
+        let var = va.min(vb);
+        let (a0, a1) = if va == var {
+            (self.low(a), self.high(a))
+        } else {
+            (a, a)
+        };
+        let (b0, b1) = if vb == var {
+            (self.low(b), self.high(b))
+        } else {
+            (b, b)
+        };
+        let low = self.apply(op, a0, b0);
+        let high = self.apply(op, a1, b1);
+        let r = self.mk(var, low, high);
+        self.apply_cache.insert((op, a, b), r);
+        r
+    }
+
+    /// Existentially quantify away `vars`.
+    pub fn exists(&mut self, f: Bdd, vars: &HashSet<u32>) -> Bdd {
+        let mut cache = HashMap::new();
+        self.exists_rec(f, vars, &mut cache)
+    }
+
+    fn exists_rec(&mut self, f: Bdd, vars: &HashSet<u32>, cache: &mut HashMap<Bdd, Bdd>) -> Bdd {
+        if f == FALSE || f == TRUE {
+            return f;
+        }
+        if let Some(r) = cache.get(&f) {
+            return *r;
+        }
+        let (var, low, high) = (self.var_of(f), self.low(f), self.high(f));
+        let low = self.exists_rec(low, vars, cache);
+        let r = if vars.contains(&var) {
+            if low == TRUE {
+                TRUE
+            } else {
+                let high = self.exists_rec(high, vars, cache);
+                self.or(low, high)
+            }
+        } else {
+            let high = self.exists_rec(high, vars, cache);
+            self.mk(var, low, high)
+        };
+        cache.insert(f, r);
+        r
+    }
+
+    /// Compute `exists vars. a & b` without building the conjunction first.
+    pub fn and_exists(&mut self, a: Bdd, b: Bdd, vars: &HashSet<u32>) -> Bdd {
+        let mut cache = HashMap::new();
+        self.and_exists_rec(a, b, vars, &mut cache)
+    }
+
+    fn and_exists_rec(
+        &mut self, a: Bdd, b: Bdd, vars: &HashSet<u32>, cache: &mut HashMap<(Bdd, Bdd), Bdd>,
+    ) -> Bdd {
+        if a == FALSE || b == FALSE {
+            return FALSE;
+        }
+        if a == TRUE && b == TRUE {
+            return TRUE;
+        }
+        if a == TRUE || a == b {
+            return self.exists(b, vars);
+        }
+        if b == TRUE {
+            return self.exists(a, vars);
+        }
+        let (a, b) = if a < b { (a, b) } else { (b, a) };
+        if let Some(r) = cache.get(&(a, b)) {
+            return *r;
+        }
+
+        let (va, vb) = (self.var_of(a), self.var_of(b));
+        let var = va.min(vb);
+        let (a0, a1) = if va == var {
+            (self.low(a), self.high(a))
+        } else {
+            (a, a)
+        };
+        let (b0, b1) = if vb == var {
+            (self.low(b), self.high(b))
+        } else {
+            (b, b)
+        };
+
+        let low = self.and_exists_rec(a0, b0, vars, cache);
+        let r = if vars.contains(&var) {
+            if low == TRUE {
+                TRUE
+            } else {
+                let high = self.and_exists_rec(a1, b1, vars, cache);
+                self.or(low, high)
+            }
+        } else {
+            let high = self.and_exists_rec(a1, b1, vars, cache);
+            self.mk(var, low, high)
+        };
+        cache.insert((a, b), r);
+        r
+    }
+
+    /// Rename variables according to `map`. The mapping must preserve
+    /// the relative order of the variables in the support of `f`.
+    pub fn rename(&mut self, f: Bdd, map: &HashMap<u32, u32>) -> Bdd {
+        let mut cache = HashMap::new();
+        self.rename_rec(f, map, &mut cache)
+    }
+
+    fn rename_rec(
+        &mut self, f: Bdd, map: &HashMap<u32, u32>, cache: &mut HashMap<Bdd, Bdd>,
+    ) -> Bdd {
+        if f == FALSE || f == TRUE {
+            return f;
+        }
+        if let Some(r) = cache.get(&f) {
+            return *r;
+        }
+        let (var, low, high) = (self.var_of(f), self.low(f), self.high(f));
+        let low = self.rename_rec(low, map, cache);
+        let high = self.rename_rec(high, map, cache);
+        let var = *map.get(&var).unwrap_or(&var);
+        let r = self.mk(var, low, high);
+        cache.insert(f, r);
+        r
+    }
+
+    /// The conjunction of the given literals.
+    pub fn cube(&mut self, literals: &[(u32, bool)]) -> Bdd {
+        let mut sorted = literals.to_vec();
+        sorted.sort();
+        sorted.iter().rev().fold(TRUE, |acc, (var, value)| {
+            if *value {
+                self.mk(*var, FALSE, acc)
+            } else {
+                self.mk(*var, acc, FALSE)
+            }
+        })
+    }
+
+    /// Pick one satisfying assignment. Variables not on the chosen path
+    /// are left out of the result, they can take on any value.
+    pub fn pick_cube(&self, f: Bdd) -> Option<Vec<(u32, bool)>> {
+        if f == FALSE {
+            return None;
+        }
+        let mut cube = Vec::new();
+        let mut current = f;
+        while current != TRUE {
+            if self.low(current) != FALSE {
+                cube.push((self.var_of(current), false));
+                current = self.low(current);
+            } else {
+                cube.push((self.var_of(current), true));
+                current = self.high(current);
+            }
+        }
+        Some(cube)
+    }
+
+    /// All paths to the true terminal, i.e. a disjoint cover of `f`.
+    pub fn cubes(&self, f: Bdd) -> Vec<Vec<(u32, bool)>> {
+        fn rec(
+            mgr: &BddManager, f: Bdd, path: &mut Vec<(u32, bool)>, out: &mut Vec<Vec<(u32, bool)>>,
+        ) {
+            if f == FALSE {
+                return;
+            }
+            if f == TRUE {
+                out.push(path.clone());
+                return;
+            }
+            let var = mgr.var_of(f);
+            path.push((var, false));
+            rec(mgr, mgr.low(f), path, out);
+            path.pop();
+            path.push((var, true));
+            rec(mgr, mgr.high(f), path, out);
+            path.pop();
+        }
+        let mut out = Vec::new();
+        rec(self, f, &mut Vec::new(), &mut out);
+        out
+    }
+
+    /// Count the satisfying assignments over `vars`, which need to
+    /// include the support of `f`.
+    pub fn sat_count(&self, f: Bdd, vars: &[u32]) -> f64 {
+        let mut vars = vars.to_vec();
+        vars.sort_unstable();
+        let level = |var: u32| {
+            if var == TERMINAL {
+                vars.len()
+            } else {
+                vars.iter()
+                    .position(|v| *v == var)
+                    .expect("variable not in set")
+            }
+        };
+
+        fn rec(
+            mgr: &BddManager, f: Bdd, level: &dyn Fn(u32) -> usize, cache: &mut HashMap<Bdd, f64>,
+        ) -> f64 {
+            if f == FALSE {
+                return 0.0;
+            }
+            if f == TRUE {
+                return 1.0;
+            }
+            if let Some(c) = cache.get(&f) {
+                return *c;
+            }
+            let l = level(mgr.var_of(f));
+            let (low, high) = (mgr.low(f), mgr.high(f));
+            let cl =
+                rec(mgr, low, level, cache) * 2f64.powi((level(mgr.var_of(low)) - l - 1) as i32);
+            let ch =
+                rec(mgr, high, level, cache) * 2f64.powi((level(mgr.var_of(high)) - l - 1) as i32);
+            cache.insert(f, cl + ch);
+            cl + ch
+        }
+
+        let mut cache = HashMap::new();
+        rec(self, f, &level, &mut cache) * 2f64.powi(level(self.var_of(f)) as i32)
+    }
+}
+
+#[cfg(test)]
+mod bdd_tests {
+    use super::*;
+
+    #[test]
+    fn bdd_basics() {
+        let mut mgr = BddManager::new();
+        let a = mgr.var(0);
+        let b = mgr.var(2);
+        let ab = mgr.and(a, b);
+        let na = mgr.not(a);
+        assert_eq!(mgr.and(ab, na), FALSE);
+        assert_eq!(mgr.or(a, na), TRUE);
+        assert_eq!(mgr.sat_count(ab, &[0, 2]), 1.0);
+        assert_eq!(mgr.sat_count(a, &[0, 2]), 2.0);
+
+        let vars: HashSet<u32> = [0].iter().cloned().collect();
+        assert_eq!(mgr.exists(ab, &vars), b);
+        assert_eq!(mgr.and_exists(a, b, &vars), b);
+
+        let map: HashMap<u32, u32> = [(2, 3)].iter().cloned().collect();
+        let b3 = mgr.var(3);
+        assert_eq!(mgr.rename(b, &map), b3);
+
+        let cube = mgr.cube(&[(0, true), (2, false)]);
+        assert_eq!(mgr.pick_cube(cube), Some(vec![(0, true), (2, false)]));
+        let a_or_b = mgr.or(a, b);
+        assert_eq!(mgr.cubes(a_or_b).len(), 2);
+    }
+}
@@ -0,0 +1,830 @@
+use crate::planning::*;
+use std::collections::{HashMap, HashSet};
+use std::time::Instant;
+
+pub mod bdd;
+use bdd::*;
+
+mod supervisor;
+pub use supervisor::*;
+
+// Upper limit on the number of valuations to enumerate when encoding
+// a single predicate or action.
+const MAX_ENUMERATION: usize = 1 << 16;
+
+/// The bits encoding a single model variable. The current state bits
+/// are on even indices and the next state bits directly after.
+#[derive(Debug, Clone)]
+struct EncodedVariable {
+    path: SPPath,
+    domain: Vec<SPValue>,
+    bits: Vec<u32>,
+}
+
+fn next_bit(bit: u32) -> u32 {
+    bit + 1
+}
+
+/// A transition system model encoded as binary decision diagrams.
+///
+/// Variables are encoded in binary over the indices of their domains.
+/// Each transition gets its own relation over current and next state
+/// bits. Global invariants are applied to every state visited.
+#[derive(Debug, Clone)]
+pub struct SymbolicModel {
+    mgr: BddManager,
+    vars: Vec<EncodedVariable>,
+    state_predicates: Vec<NamedPredicate>,
+    state_predicate_bdds: HashMap<SPPath, Bdd>,
+    transitions: Vec<(SPPath, Bdd)>,
+    invariant: Bdd,
+    current_bits: HashSet<u32>,
+    next_bits: HashSet<u32>,
+    current_to_next: HashMap<u32, u32>,
+    next_to_current: HashMap<u32, u32>,
+    next_free_bit: u32,
+}
+
+impl SymbolicModel {
+    /// Encode a model. If a state is given, its values are added to
+    /// the variable domains in case they are missing.
+    pub fn new(
+        model: &TransitionSystemModel, state: Option<&SPState>,
+    ) -> Result<Self, PlanningError> {
+        let mut sm = SymbolicModel {
+            mgr: BddManager::new(),
+            vars: Vec::new(),
+            state_predicates: model.state_predicates.clone(),
+            state_predicate_bdds: HashMap::new(),
+            transitions: Vec::new(),
+            invariant: TRUE,
+            current_bits: HashSet::new(),
+            next_bits: HashSet::new(),
+            current_to_next: HashMap::new(),
+            next_to_current: HashMap::new(),
+            next_free_bit: 0,
+        };
+
+        for v in &model.vars {
+            let mut domain = if v.value_type == SPValueType::Bool {
+                vec![false.to_spvalue(), true.to_spvalue()]
+            } else {
+                v.domain.clone()
+            };
+            if let Some(value) = state.and_then(|s| s.sp_value_from_path(&v.path)) {
+                if !domain.contains(value) {
+                    domain.push(value.clone());
+                }
+            }
+            if domain.is_empty() {
+                return Err(PlanningError::Unsupported(format!(
+                    "variable {} has an empty domain",
+                    v.path
+                )));
+            }
+            let n_bits = (usize::BITS - (domain.len() - 1).leading_zeros()).max(1);
+            let bits = (0..n_bits).map(|_| sm.alloc_bit()).collect();
+            sm.vars.push(EncodedVariable {
+                path: v.path.clone(),
+                domain,
+                bits,
+            });
+        }
+
+        let mut invariant = TRUE;
+        for i in 0..sm.vars.len() {
+            let valid = sm.valid(i, false);
+            invariant = sm.mgr.and(invariant, valid);
+        }
+        for i in &model.invariants {
+            let p = sm.predicate(&i.predicate)?;
+            invariant = sm.mgr.and(invariant, p);
+        }
+        sm.invariant = invariant;
+
+        for t in &model.transitions {
+            let relation = sm.transition(t)?;
+            sm.transitions.push((t.path.clone(), relation));
+        }
+
+        Ok(sm)
+    }
+
+    fn alloc_bit(&mut self) -> u32 {
+        let bit = self.next_free_bit;
+        self.next_free_bit += 2;
+        self.current_bits.insert(bit);
+        self.next_bits.insert(next_bit(bit));
+        self.current_to_next.insert(bit, next_bit(bit));
+        self.next_to_current.insert(next_bit(bit), bit);
+        bit
+    }
+
+    fn var_index(&self, path: &SPPath) -> Option<usize> {
+        self.vars.iter().position(|v| &v.path == path)
+    }
+
+    /// The valuation where variable `var` takes on the value at `idx` in its domain.
+    fn value_bdd(&mut self, var: usize, idx: usize, next: bool) -> Bdd {
+        let literals: Vec<_> = self.vars[var]
+            .bits
+            .iter()
+            .enumerate()
+            .map(|(i, b)| {
+                let b = if next { next_bit(*b) } else { *b };
+                (b, (idx >> i) & 1 == 1)
+            })
+            .collect();
+        self.mgr.cube(&literals)
+    }
+
+    /// Only allow bit patterns that map to a value in the domain.
+    fn valid(&mut self, var: usize, next: bool) -> Bdd {
+        let values: Vec<_> = (0..self.vars[var].domain.len())
+            .map(|idx| self.value_bdd(var, idx, next))
+            .collect();
+        self.mgr.or_all(&values)
+    }
+
+    fn unchanged(&mut self, var: usize) -> Bdd {
+        let bits = self.vars[var].bits.clone();
+        bits.iter().fold(TRUE, |acc, b| {
+            let (c, n) = (self.mgr.var(*b), self.mgr.var(next_bit(*b)));
+            let eq = self.mgr.iff(c, n);
+            self.mgr.and(acc, eq)
+        })
+    }
+
+    pub fn to_next(&mut self, f: Bdd) -> Bdd {
+        self.mgr.rename(f, &self.current_to_next.clone())
+    }
+
+    pub fn to_current(&mut self, f: Bdd) -> Bdd {
+        self.mgr.rename(f, &self.next_to_current.clone())
+    }
+
+    /// Call `f` for every valuation of the variables in `support`.
+    /// Returns the disjunction of the cubes of the valuations for
+    /// which `f` returns some bdd, conjoined with that bdd.
+    fn enumerate<F>(&mut self, support: &[usize], mut f: F) -> Result<Bdd, PlanningError>
+    where
+        F: FnMut(&mut Self, &SPState) -> Result<Bdd, PlanningError>,
+    {
+        let sizes: Vec<_> = support.iter().map(|v| self.vars[*v].domain.len()).collect();
+        let total = sizes.iter().try_fold(1usize, |acc, s| acc.checked_mul(*s));
+        match total {
+            Some(total) if total <= MAX_ENUMERATION => {}
+            _ => {
+                return Err(PlanningError::Unsupported(
+                    "too many valuations to enumerate".to_string(),
+                ))
+            }
+        }
+
+        let mut result = FALSE;
+        let mut idx = vec![0; support.len()];
+        loop {
+            let values: Vec<_> = support
+                .iter()
+                .zip(idx.iter())
+                .map(|(v, i)| (self.vars[*v].path.clone(), self.vars[*v].domain[*i].clone()))
+                .collect();
+            let state = SPState::new_from_values(&values);
+            let r = f(self, &state)?;
+            if r != FALSE {
+                let mut cube = r;
+                for (v, i) in support.iter().zip(idx.iter()) {
+                    let value = self.value_bdd(*v, *i, false);
+                    cube = self.mgr.and(cube, value);
+                }
+                result = self.mgr.or(result, cube);
+            }
+
+            // next valuation
+            let mut pos = 0;
+            loop {
+                if pos == idx.len() {
+                    return Ok(result);
+                }
+                idx[pos] += 1;
+                if idx[pos] < sizes[pos] {
+                    break;
+                }
+                idx[pos] = 0;
+                pos += 1;
+            }
+        }
+    }
+
+    fn support_vars(&self, support: &[SPPath]) -> Result<Vec<usize>, PlanningError> {
+        support
+            .iter()
+            .map(|p| {
+                self.var_index(p)
+                    .ok_or_else(|| PlanningError::InvalidProblem(format!("unknown variable {p}")))
+            })
+            .collect()
+    }
+
+    fn state_predicate(&mut self, path: &SPPath) -> Result<Option<Bdd>, PlanningError> {
+        if let Some(b) = self.state_predicate_bdds.get(path) {
+            return Ok(Some(*b));
+        }
+        let p = match self.state_predicates.iter().find(|p| &p.path == path) {
+            Some(p) => p.predicate.clone(),
+            None => return Ok(None),
+        };
+        let b = self.predicate(&p)?;
+        self.state_predicate_bdds.insert(path.clone(), b);
+        Ok(Some(b))
+    }
+
+    /// Encode a predicate over the current state variables.
+    pub fn predicate(&mut self, p: &Predicate) -> Result<Bdd, PlanningError> {
+        match p {
+            Predicate::AND(ps) => ps.iter().try_fold(TRUE, |acc, p| {
+                let p = self.predicate(p)?;
+                Ok(self.mgr.and(acc, p))
+            }),
+            Predicate::OR(ps) => ps.iter().try_fold(FALSE, |acc, p| {
+                let p = self.predicate(p)?;
+                Ok(self.mgr.or(acc, p))
+            }),
+            Predicate::XOR(ps) => {
+                // exactly one
+                let ps = ps
+                    .iter()
+                    .map(|p| self.predicate(p))
+                    .collect::<Result<Vec<_>, _>>()?;
+                let mut result = FALSE;
+                for (i, p) in ps.iter().enumerate() {
+                    let mut one = *p;
+                    for (j, q) in ps.iter().enumerate() {
+                        if i != j {
+                            let nq = self.mgr.not(*q);
+                            one = self.mgr.and(one, nq);
+                        }
+                    }
+                    result = self.mgr.or(result, one);
+                }
+                Ok(result)
+            }
+            Predicate::NOT(p) => {
+                let p = self.predicate(p)?;
+                Ok(self.mgr.not(p))
+            }
+            Predicate::TRUE => Ok(TRUE),
+            Predicate::FALSE => Ok(FALSE),
+            Predicate::TON(_, _) | Predicate::TOFF(_, _) => Err(PlanningError::Unsupported(
+                format!("time predicates can not be encoded symbolically: {p}"),
+            )),
+            _ => self.atom(p),
+        }
+    }
+
+    fn atom(&mut self, p: &Predicate) -> Result<Bdd, PlanningError> {
+        let support = p.support();
+
+        // references to state predicates must be compared with a boolean
+        for path in &support {
+            if let Some(sp) = self.state_predicate(path)? {
+                let (value, negated) = match p {
+                    Predicate::EQ(PredicateValue::SPPath(x, _), PredicateValue::SPValue(v))
+                    | Predicate::EQ(PredicateValue::SPValue(v), PredicateValue::SPPath(x, _))
+                        if x == path =>
+                    {
+                        (v, false)
+                    }
+                    Predicate::NEQ(PredicateValue::SPPath(x, _), PredicateValue::SPValue(v))
+                    | Predicate::NEQ(PredicateValue::SPValue(v), PredicateValue::SPPath(x, _))
+                        if x == path =>
+                    {
+                        (v, true)
+                    }
+                    _ => {
+                        return Err(PlanningError::Unsupported(format!(
+                            "state predicates can only be compared to booleans: {p}"
+                        )))
+                    }
+                };
+                return match value {
+                    SPValue::Bool(b) if *b != negated => Ok(sp),
+                    SPValue::Bool(_) => Ok(self.mgr.not(sp)),
+                    _ => Err(PlanningError::Unsupported(format!(
+                        "state predicates can only be compared to booleans: {p}"
+                    ))),
+                };
+            }
+        }
+
+        let support = self.support_vars(&support)?;
+        self.enumerate(&support, |_, state| {
+            Ok(if p.eval(state) { TRUE } else { FALSE })
+        })
+    }
+
+    fn action(&mut self, a: &Action) -> Result<Bdd, PlanningError> {
+        let var = self
+            .var_index(&a.var)
+            .ok_or_else(|| PlanningError::InvalidProblem(format!("unknown variable {}", a.var)))?;
+        match &a.value {
+            Compute::Any => Ok(self.valid(var, true)),
+            Compute::Random(n) => {
+                let values: Vec<_> = self.vars[var]
+                    .domain
+                    .iter()
+                    .enumerate()
+                    .filter(|(_, v)| matches!(v, SPValue::Int32(x) if *x >= 0 && x < n))
+                    .map(|(idx, _)| idx)
+                    .collect();
+                let values: Vec<_> = values
+                    .into_iter()
+                    .map(|idx| self.value_bdd(var, idx, true))
+                    .collect();
+                Ok(self.mgr.or_all(&values))
+            }
+            Compute::TimeStamp => Err(PlanningError::Unsupported(format!(
+                "time stamps can not be encoded symbolically: {a}"
+            ))),
+            c => {
+                let support = self.support_vars(&c.support())?;
+                let target = self.vars[var].clone();
+                self.enumerate(&support, |sm, state| {
+                    let mut state = state.clone();
+                    if state.sp_value_from_path(&target.path).is_none() {
+                        state.add_variable(target.path.clone(), target.domain[0].clone());
+                    }
+                    if a.next(&mut state).is_err() {
+                        return Ok(FALSE);
+                    }
+                    let value = state
+                        .state_value_from_path(&target.path)
+                        .and_then(|v| v.next_value().clone());
+                    match value.and_then(|v| target.domain.iter().position(|d| d == &v)) {
+                        Some(idx) => Ok(sm.value_bdd(var, idx, true)),
+                        None => Ok(FALSE),
+                    }
+                })
+            }
+        }
+    }
+
+    fn transition(&mut self, t: &Transition) -> Result<Bdd, PlanningError> {
+        let mut relation = self.predicate(&t.guard)?;
+        for a in &t.actions {
+            let a = self.action(a)?;
+            relation = self.mgr.and(relation, a);
+        }
+        let modified = t.modifies();
+        for i in 0..self.vars.len() {
+            if !modified.contains(&self.vars[i].path) {
+                let unchanged = self.unchanged(i);
+                relation = self.mgr.and(relation, unchanged);
+            }
+        }
+        Ok(relation)
+    }
+
+    /// Encode a concrete state. Only the model variables are used.
+    pub fn state(&mut self, state: &SPState) -> Result<Bdd, PlanningError> {
+        let mut result = TRUE;
+        for i in 0..self.vars.len() {
+            let v = &self.vars[i];
+            let value = state.sp_value_from_path(&v.path).ok_or_else(|| {
+                PlanningError::InvalidProblem(format!(
+                    "all variables need a valuation! missing {}",
+                    v.path
+                ))
+            })?;
+            let idx = v.domain.iter().position(|d| d == value).ok_or_else(|| {
+                PlanningError::InvalidProblem(format!(
+                    "value {} of {} not in domain",
+                    value, v.path
+                ))
+            })?;
+            let value = self.value_bdd(i, idx, false);
+            result = self.mgr.and(result, value);
+        }
+        Ok(result)
+    }
+
+    /// Decode a satisfying assignment of `f` into a state, including
+    /// the values of the state predicates.
+    fn pick_state(&self, f: Bdd) -> Option<(SPState, Vec<(u32, bool)>)> {
+        let cube = self.mgr.pick_cube(f)?;
+        let bit = |b: &u32| cube.iter().any(|(v, value)| v == b && *value);
+        let mut literals = Vec::new();
+        let values: Vec<_> = self
+            .vars
+            .iter()
+            .map(|v| {
+                let idx =
+                    v.bits
+                        .iter()
+                        .enumerate()
+                        .fold(0, |acc, (i, b)| if bit(b) { acc | (1 << i) } else { acc });
+                literals.extend(v.bits.iter().map(|b| (*b, bit(b))));
+                let value = v.domain.get(idx).cloned().unwrap_or(SPValue::Unknown);
+                (v.path.clone(), value)
+            })
+            .collect();
+        // also keep any extra bits (e.g. goal monitors)
+        let extra: Vec<_> = cube
+            .iter()
+            .filter(|(b, _)| self.current_bits.contains(b) && !literals.iter().any(|(l, _)| l == b))
+            .cloned()
+            .collect();
+        literals.extend(extra);
+        let mut state = SPState::new_from_values(&values);
+        let preds: Vec<_> = self
+            .state_predicates
+            .iter()
+            .map(|p| (p.path.clone(), p.predicate.eval(&state).to_spvalue()))
+            .collect();
+        state.add_variables(preds);
+        Some((state, literals))
+    }
+
+    /// All states reachable in one step from `states`.
+    pub fn image(&mut self, states: Bdd) -> Bdd {
+        let current_bits = self.current_bits.clone();
+        let mut result = FALSE;
+        for i in 0..self.transitions.len() {
+            let t = self.transitions[i].1;
+            let next = self.mgr.and_exists(states, t, &current_bits);
+            result = self.mgr.or(result, next);
+        }
+        let result = self.to_current(result);
+        self.mgr.and(result, self.invariant)
+    }
+
+    /// All states that can reach `states` in one step.
+    pub fn preimage(&mut self, states: Bdd) -> Bdd {
+        let next_bits = self.next_bits.clone();
+        let next = self.to_next(states);
+        let mut result = FALSE;
+        for i in 0..self.transitions.len() {
+            let t = self.transitions[i].1;
+            let pre = self.mgr.and_exists(next, t, &next_bits);
+            result = self.mgr.or(result, pre);
+        }
+        self.mgr.and(result, self.invariant)
+    }
+
+    /// The set of states reachable from `from` (including `from`).
+    pub fn forward_reachable(&mut self, from: Bdd) -> Bdd {
+        let mut reached = self.mgr.and(from, self.invariant);
+        let mut frontier = reached;
+        while frontier != FALSE {
+            let image = self.image(frontier);
+            let not_reached = self.mgr.not(reached);
+            frontier = self.mgr.and(image, not_reached);
+            reached = self.mgr.or(reached, frontier);
+        }
+        reached
+    }
+
+    /// The set of states from which `to` can be reached (including `to`).
+    pub fn backward_reachable(&mut self, to: Bdd) -> Bdd {
+        let mut reached = self.mgr.and(to, self.invariant);
+        let mut frontier = reached;
+        while frontier != FALSE {
+            let pre = self.preimage(frontier);
+            let not_reached = self.mgr.not(reached);
+            frontier = self.mgr.and(pre, not_reached);
+            reached = self.mgr.or(reached, frontier);
+        }
+        reached
+    }
+
+    /// Number of states in the set.
+    pub fn count_states(&self, states: Bdd) -> f64 {
+        let mut bits: Vec<_> = self.vars.iter().flat_map(|v| v.bits.clone()).collect();
+        bits.sort_unstable();
+        self.mgr.sat_count(states, &bits)
+    }
+
+    /// Convert a set of states back into a predicate over the model
+    /// variables. The set is written either as a disjunction of its
+    /// cubes or as the negation of its complement, whichever is smaller.
+    pub fn to_predicate(&mut self, states: Bdd) -> Predicate {
+        if states == TRUE {
+            return Predicate::TRUE;
+        } else if states == FALSE {
+            return Predicate::FALSE;
+        }
+        let complement = self.mgr.not(states);
+        let pos = self.mgr.cubes(states);
+        let neg = self.mgr.cubes(complement);
+        let (cubes, negate) = if neg.len() < pos.len() {
+            (neg, true)
+        } else {
+            (pos, false)
+        };
+
+        let mut disjuncts = Vec::new();
+        for cube in cubes {
+            let mut conjuncts = Vec::new();
+            for v in &self.vars {
+                let allowed: Vec<_> = (0..v.domain.len())
+                    .filter(|idx| {
+                        v.bits.iter().enumerate().all(|(i, b)| {
+                            cube.iter()
+                                .find(|(l, _)| l == b)
+                                .map(|(_, value)| *value == ((idx >> i) & 1 == 1))
+                                .unwrap_or(true)
+                        })
+                    })
+                    .collect();
+                if allowed.len() == v.domain.len() {
+                    continue;
+                }
+                let mut eqs: Vec<_> = allowed
+                    .iter()
+                    .map(|idx| {
+                        Predicate::EQ(
+                            PredicateValue::SPPath(v.path.clone(), None),
+                            PredicateValue::SPValue(v.domain[*idx].clone()),
+                        )
+                    })
+                    .collect();
+                conjuncts.push(match eqs.len() {
+                    0 => Predicate::FALSE,
+                    1 => eqs.remove(0),
+                    _ => Predicate::OR(eqs),
+                });
+            }
+            disjuncts.push(match conjuncts.len() {
+                0 => Predicate::TRUE,
+                1 => conjuncts.remove(0),
+                _ => Predicate::AND(conjuncts),
+            });
+        }
+        let p = match disjuncts.len() {
+            1 => disjuncts.remove(0),
+            _ => Predicate::OR(disjuncts),
+        };
+        if negate {
+            Predicate::NOT(Box::new(p))
+        } else {
+            p
+        }
+    }
+
+    pub fn is_empty(&self, states: Bdd) -> bool {
+        states == FALSE
+    }
+
+    pub fn and(&mut self, a: Bdd, b: Bdd) -> Bdd {
+        self.mgr.and(a, b)
+    }
+}
+
+/// Check if a state satisfying `goal` can be reached at all from
+/// `state`, without any bound on the number of steps.
+pub fn is_goal_reachable(
+    model: &TransitionSystemModel, goal: &Predicate, state: &SPState,
+) -> Result<bool, PlanningError> {
+    let mut sm = SymbolicModel::new(model, Some(state))?;
+    let goal = sm.predicate(goal)?;
+    let initial = sm.state(state)?;
+    let mut reached = sm.mgr.and(initial, sm.invariant);
+    let mut frontier = reached;
+    while frontier != FALSE {
+        if sm.mgr.and(frontier, goal) != FALSE {
+            return Ok(true);
+        }
+        let image = sm.image(frontier);
+        let not_reached = sm.mgr.not(reached);
+        frontier = sm.mgr.and(image, not_reached);
+        reached = sm.mgr.or(reached, frontier);
+    }
+    Ok(false)
+}
+
+/// A planner that searches the state space symbolically. Produces
+/// the same (shortest) plans as the nuXmv bmc planner, but without
+/// the external dependency.
+#[derive(Debug, Clone, Default)]
+pub struct SymbolicPlanner {}
+
+struct GoalMonitors {
+    initial: Bdd,
+    alive: Bdd,
+    target: Bdd,
+}
+
+impl SymbolicModel {
+    /// Add one bit per goal that tracks if the goal has been reached.
+    /// Mirrors the `F goal` and `invar U goal` encoding for nuXmv.
+    fn add_goal_monitors(
+        &mut self, goals: &[(Predicate, Option<Predicate>)],
+    ) -> Result<GoalMonitors, PlanningError> {
+        let mut monitors = GoalMonitors {
+            initial: TRUE,
+            alive: TRUE,
+            target: TRUE,
+        };
+        for (goal, invar) in goals {
+            let goal = self.predicate(goal)?;
+            let goal_next = self.to_next(goal);
+            let bit = self.alloc_bit();
+            let (m, m_next) = (self.mgr.var(bit), self.mgr.var(next_bit(bit)));
+
+            let reached = self.mgr.or(m, goal_next);
+            let update = self.mgr.iff(m_next, reached);
+            for t in self.transitions.iter_mut() {
+                t.1 = self.mgr.and(t.1, update);
+            }
+
+            let initial = self.mgr.iff(m, goal);
+            monitors.initial = self.mgr.and(monitors.initial, initial);
+            if let Some(invar) = invar {
+                let invar = self.predicate(invar)?;
+                let alive = self.mgr.or(m, invar);
+                monitors.alive = self.mgr.and(monitors.alive, alive);
+            }
+            monitors.target = self.mgr.and(monitors.target, m);
+        }
+        Ok(monitors)
+    }
+}
+
+impl Planner for SymbolicPlanner {
+    fn name(&self) -> &str {
+        "symbolic"
+    }
+
+    fn plan_cancellable(
+        &mut self, model: &TransitionSystemModel, goals: &[(Predicate, Option<Predicate>)],
+        state: &SPState, max_steps: u32, cancel: &CancellationToken,
+    ) -> Result<PlanningResult, PlanningError> {
+        let start = Instant::now();
+        let mut sm = SymbolicModel::new(model, Some(state))?;
+        let monitors = sm.add_goal_monitors(goals)?;
+        sm.invariant = sm.mgr.and(sm.invariant, monitors.alive);
+
+        let initial = sm.state(state)?;
+        let initial = sm.mgr.and(initial, monitors.initial);
+        let initial = sm.mgr.and(initial, sm.invariant);
+
+        let mut layers = vec![initial];
+        let mut reached = initial;
+        let mut found = None;
+        for step in 0..=max_steps {
+            cancel.check()?;
+            let frontier = layers[step as usize];
+            let at_goal = sm.mgr.and(frontier, monitors.target);
+            if at_goal != FALSE {
+                found = Some(at_goal);
+                break;
+            }
+            if frontier == FALSE || step == max_steps {
+                break;
+            }
+            let image = sm.image(frontier);
+            let not_reached = sm.mgr.not(reached);
+            let next = sm.mgr.and(image, not_reached);
+            reached = sm.mgr.or(reached, next);
+            layers.push(next);
+        }
+
+        let found = match found {
+            Some(found) => found,
+            None => {
+                return Ok(PlanningResult {
+                    plan_found: false,
+                    plan_length: 0,
+                    trace: vec![PlanningFrame {
+                        transition: "".into(),
+                        state: state.clone(),
+                    }],
+                    time_to_solve: start.elapsed(),
+                    cost: Cost::default(),
+                })
+            }
+        };
+
+        // walk backwards through the layers to extract a concrete plan.
+        let next_bits = sm.next_bits.clone();
+        let (mut current_state, mut literals) = sm.pick_state(found).expect("non-empty");
+        let mut trace = Vec::new();
+        for layer in layers[..layers.len() - 1].iter().rev() {
+            let target = sm.mgr.cube(&literals);
+            let target = sm.to_next(target);
+            let mut step = None;
+            for i in 0..sm.transitions.len() {
+                let (path, t) = sm.transitions[i].clone();
+                let pre = sm.mgr.and_exists(target, t, &next_bits);
+                let pre = sm.mgr.and(pre, *layer);
+                if pre != FALSE {
+                    step = Some((path, pre));
+                    break;
+                }
+            }
+            let (path, pre) = step.ok_or_else(|| {
+                PlanningError::TraceParse("could not extract plan from layers".to_string())
+            })?;
+            trace.push(PlanningFrame {
+                state: current_state,
+                transition: path,
+            });
+            let (s, l) = sm.pick_state(pre).expect("non-empty");
+            current_state = s;
+            literals = l;
+        }
+        trace.push(PlanningFrame {
+            state: current_state,
+            transition: SPPath::default(),
+        });
+        trace.reverse();
+
+        Ok(PlanningResult {
+            plan_found: true,
+            plan_length: trace.len() as u32 - 1,
+            cost: plan_cost(model, &trace),
+            trace,
+            time_to_solve: start.elapsed(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod symbolic_tests {
+    use super::*;
+
+    fn counter_model() -> TransitionSystemModel {
+        let mut tsm = TransitionSystemModel::default();
+        let mut x = Variable::new(
+            "x".into(),
+            SPValueType::String,
+            vec!["a".to_spvalue(), "b".to_spvalue(), "c".to_spvalue()],
+        );
+        x.initial_state = "a".to_spvalue();
+        let y = Variable::new_boolean("y".into());
+        tsm.vars.push(x.clone());
+        tsm.vars.push(y.clone());
+        tsm.state_predicates
+            .push(NamedPredicate::new("at_c".into(), p!(x == "c")));
+        let at_c = SPPath::from("at_c");
+        tsm.transitions.push(Transition::new(
+            "a_to_b".into(),
+            p!(x == "a"),
+            vec![a!(x = "b")],
+        ));
+        tsm.transitions.push(Transition::new(
+            "b_to_c".into(),
+            p!(x == "b"),
+            vec![a!(x = "c")],
+        ));
+        tsm.transitions
+            .push(Transition::new("set_y".into(), p!(at_c), vec![a!(y)]));
+        tsm
+    }
+
+    #[test]
+    fn symbolic_plan_and_reachability() {
+        let tsm = counter_model();
+        let x = SPPath::from("x");
+        let y = SPPath::from("y");
+        let state = state!(x => "a", y => false);
+
+        let result = SymbolicPlanner::default()
+            .plan(&tsm, &[(p!(y), None)], &state, 5)
+            .unwrap();
+        assert!(result.plan_found);
+        let trans: Vec<_> = result
+            .trace
+            .iter()
+            .map(|f| f.transition.to_string())
+            .collect();
+        assert_eq!(trans, vec!["", "a_to_b", "b_to_c", "set_y"]);
+        assert_eq!(
+            result.trace[3].state.sp_value_from_path(&y),
+            Some(&true.to_spvalue())
+        );
+
+        let result = SymbolicPlanner::default()
+            .plan(&tsm, &[(p!(y), None)], &state, 2)
+            .unwrap();
+        assert!(!result.plan_found);
+
+        let goals = [(p!(y), Some(p!(x != "c")))];
+        let result = SymbolicPlanner::default()
+            .plan(&tsm, &goals, &state, 5)
+            .unwrap();
+        assert!(!result.plan_found);
+
+        assert!(is_goal_reachable(&tsm, &p!(y), &state).unwrap());
+        let state = state!(x => "c", y => false);
+        assert!(!is_goal_reachable(&tsm, &p!(x == "a"), &state).unwrap());
+
+        let mut sm = SymbolicModel::new(&tsm, None).unwrap();
+        let initial = sm.predicate(&p!([x == "a"] && [!y])).unwrap();
+        let reachable = sm.forward_reachable(initial);
+        assert_eq!(sm.count_states(reachable), 4.0);
+        let goal = sm.predicate(&p!(y)).unwrap();
+        let coreachable = sm.backward_reachable(goal);
+        assert_eq!(sm.count_states(coreachable), 6.0);
+    }
+}
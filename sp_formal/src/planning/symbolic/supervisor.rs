@@ -0,0 +1,190 @@
+use super::*;
+
+/// The result of supervisor synthesis. Contains the additional guard
+/// needed for each controlled transition. Transitions that do not
+/// need to be restricted are not included.
+#[derive(Debug, PartialEq, Clone, Default)]
+pub struct Supervisor {
+    /// The states the supervisor can keep the system within, i.e. the
+    /// states that are safe and from which a marked state is reachable.
+    pub safe_states: Predicate,
+    pub guards: Vec<(SPPath, Predicate)>,
+}
+
+impl Supervisor {
+    /// Is it possible to control the system from this state?
+    pub fn is_safe(&self, state: &SPState) -> bool {
+        self.safe_states.eval(state)
+    }
+
+    /// Strengthen the guard of `t` if the supervisor restricts it.
+    pub fn strengthen(&self, t: &mut Transition) {
+        if let Some((_, extra)) = self.guards.iter().find(|(p, _)| p == &t.path) {
+            t.guard = Predicate::AND(vec![t.guard.clone(), extra.clone()]);
+        }
+    }
+
+    /// Write back the strengthened guards into the model.
+    pub fn apply(&self, model: &mut TransitionSystemModel) {
+        let uncontrollable = &model.uncontrollable;
+        model
+            .transitions
+            .iter_mut()
+            .filter(|t| !uncontrollable.contains(&t.path))
+            .for_each(|t| self.strengthen(t));
+    }
+}
+
+impl SymbolicModel {
+    /// The states with some successor in `states` using transition `t`.
+    /// Not restricted to the allowed states.
+    fn transition_preimage(&mut self, t: Bdd, states: Bdd) -> Bdd {
+        let next_bits = self.next_bits.clone();
+        let next = self.to_next(states);
+        self.mgr.and_exists(next, t, &next_bits)
+    }
+}
+
+/// Compute a maximally permissive, nonblocking and controllable
+/// supervisor for the model. Transitions listed in
+/// `model.uncontrollable` can not be disabled, and all states violating
+/// the invariants are forbidden. From every state the supervisor allows,
+/// a state satisfying `marked` must remain reachable.
+pub fn synthesize_supervisor(
+    model: &TransitionSystemModel, marked: &Predicate,
+) -> Result<Supervisor, PlanningError> {
+    let mut sm = SymbolicModel::new(model, None)?;
+    let marked = sm.predicate(marked)?;
+
+    let (uncontrollable, controlled): (Vec<_>, Vec<_>) = sm
+        .transitions
+        .clone()
+        .into_iter()
+        .partition(|(p, _)| model.uncontrollable.contains(p));
+
+    let mut good = sm.invariant;
+    loop {
+        // nonblocking: keep the states that can reach a marked state
+        // while staying within the good states.
+        let mut coreachable = sm.mgr.and(good, marked);
+        let mut frontier = coreachable;
+        while frontier != FALSE {
+            let mut pre = FALSE;
+            for (_, t) in &sm.transitions.clone() {
+                let p = sm.transition_preimage(*t, frontier);
+                pre = sm.mgr.or(pre, p);
+            }
+            let pre = sm.mgr.and(pre, good);
+            let not_coreachable = sm.mgr.not(coreachable);
+            frontier = sm.mgr.and(pre, not_coreachable);
+            coreachable = sm.mgr.or(coreachable, frontier);
+        }
+
+        // controllability: remove the states from which the bad
+        // states can be reached using uncontrollable transitions.
+        let mut bad = sm.mgr.not(coreachable);
+        loop {
+            let mut pre = FALSE;
+            for (_, t) in &uncontrollable {
+                let p = sm.transition_preimage(*t, bad);
+                pre = sm.mgr.or(pre, p);
+            }
+            let new_bad = sm.mgr.or(bad, pre);
+            if new_bad == bad {
+                break;
+            }
+            bad = new_bad;
+        }
+
+        let not_bad = sm.mgr.not(bad);
+        let new_good = sm.mgr.and(coreachable, not_bad);
+        if new_good == good {
+            break;
+        }
+        good = new_good;
+    }
+
+    let bad = sm.mgr.not(good);
+    let mut guards = Vec::new();
+    for (path, t) in controlled {
+        // only allow the transition if it can not lead to a bad state.
+        let to_bad = sm.transition_preimage(t, bad);
+        let enabled = sm.mgr.exists(t, &sm.next_bits.clone());
+        let not_enabled = sm.mgr.not(enabled);
+        let extra = sm.mgr.not(to_bad);
+        // no need to care about the states where t is already disabled.
+        let extra = sm.mgr.or(extra, not_enabled);
+        if extra != TRUE {
+            guards.push((path, sm.to_predicate(extra)));
+        }
+    }
+
+    Ok(Supervisor {
+        safe_states: sm.to_predicate(good),
+        guards,
+    })
+}
+
+#[cfg(test)]
+mod supervisor_tests {
+    use super::*;
+
+    #[test]
+    fn supervisor_blocks_uncontrollable_bad_states() {
+        // a buffer that can hold two items. the machine adding to the buffer
+        // is controlled but it finishes on its own (uncontrollable).
+        let mut tsm = TransitionSystemModel::default();
+        let mut m = Variable::new(
+            "m".into(),
+            SPValueType::String,
+            vec!["idle".to_spvalue(), "working".to_spvalue()],
+        );
+        m.initial_state = "idle".to_spvalue();
+        let b = Variable::new(
+            "b".into(),
+            SPValueType::Int32,
+            vec![0.to_spvalue(), 1.to_spvalue(), 2.to_spvalue()],
+        );
+        tsm.vars.push(m.clone());
+        tsm.vars.push(b.clone());
+        tsm.transitions.push(Transition::new(
+            "start".into(),
+            p!(m == "idle"),
+            vec![a!(m = "working")],
+        ));
+        tsm.transitions.push(Transition::new(
+            "finish_0".into(),
+            p!([m == "working"] && [b == 0]),
+            vec![a!(m = "idle"), a!(b = 1)],
+        ));
+        tsm.transitions.push(Transition::new(
+            "finish_1".into(),
+            p!([m == "working"] && [b == 1]),
+            vec![a!(m = "idle"), a!(b = 2)],
+        ));
+        tsm.transitions
+            .push(Transition::new("take".into(), p!(b != 0), vec![a!(b = 0)]));
+        tsm.uncontrollable.push("finish_0".into());
+        tsm.uncontrollable.push("finish_1".into());
+
+        // never more than one item in the buffer.
+        tsm.invariants
+            .push(NamedPredicate::new("max_one".into(), p!(b != 2)));
+
+        let sup = synthesize_supervisor(&tsm, &p!([m == "idle"] && [b == 0])).unwrap();
+        let (m, b) = (m.path, b.path);
+        assert!(sup.is_safe(&state!(m => "idle", b => 1)));
+        assert!(!sup.is_safe(&state!(m => "working", b => 1)));
+
+        // only start should be restricted.
+        assert_eq!(sup.guards.len(), 1);
+        assert_eq!(sup.guards[0].0, SPPath::from("start"));
+
+        sup.apply(&mut tsm);
+        let start = &tsm.transitions[0];
+        assert!(start.eval(&state!(m => "idle", b => 0)));
+        assert!(!start.eval(&state!(m => "idle", b => 1)));
+        let take = &tsm.transitions[3];
+        assert_eq!(take.guard, p!(b != 0));
+    }
+}
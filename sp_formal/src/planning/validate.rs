@@ -0,0 +1,271 @@
+use crate::TransitionSystemModel;
+use serde::{Deserialize, Serialize};
+use sp_domain::*;
+use std::fmt;
+
+use super::explicit::update_state_predicates;
+use super::PlanningResult;
+
+/// Where and how a plan stops following the model.
+#[derive(Debug, PartialEq, Serialize, Deserialize, Clone)]
+pub enum PlanDivergence {
+    /// The plan takes a transition that is not in the model.
+    UnknownTransition { step: usize, transition: SPPath },
+    /// The guard of the transition is false in the replayed state.
+    GuardFalse { step: usize, transition: SPPath },
+    /// The actions of the transition could not be applied, or they
+    /// moved a variable outside of its range.
+    ActionFailed {
+        step: usize,
+        transition: SPPath,
+        message: String,
+    },
+    /// The replayed state differs from the state the planner predicted.
+    StateMismatch {
+        step: usize,
+        transition: SPPath,
+        path: SPPath,
+        predicted: SPValue,
+        actual: Option<SPValue>,
+    },
+}
+
+impl fmt::Display for PlanDivergence {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PlanDivergence::UnknownTransition { step, transition } => {
+                write!(f, "step {step}: {transition} is not in the model")
+            }
+            PlanDivergence::GuardFalse { step, transition } => {
+                write!(f, "step {step}: the guard of {transition} is false")
+            }
+            PlanDivergence::ActionFailed {
+                step,
+                transition,
+                message,
+            } => write!(
+                f,
+                "step {step}: the actions of {transition} failed: {message}"
+            ),
+            PlanDivergence::StateMismatch {
+                step,
+                transition,
+                path,
+                predicted,
+                actual,
+            } => {
+                let actual = actual
+                    .as_ref()
+                    .map(|v| v.to_string())
+                    .unwrap_or_else(|| "nothing".to_string());
+                write!(
+                    f,
+                    "step {step}: after {transition} the planner predicted {path} = {predicted}, but it is {actual}"
+                )
+            }
+        }
+    }
+}
+
+impl std::error::Error for PlanDivergence {}
+
+/// Replay a plan from `state` using the rust semantics of the model,
+/// i.e. `Transition::eval` and `Transition::next`. After each step the
+/// variables and state predicates are compared with the state the
+/// planner predicted. Values chosen by `Compute::Any` are taken from
+/// the predicted state.
+///
+/// Returns the replayed state of each frame of the trace, starting
+/// with `state`, or the first step where the plan diverges.
+pub fn validate_plan(
+    model: &TransitionSystemModel, state: &SPState, plan: &PlanningResult,
+) -> Result<Vec<SPState>, PlanDivergence> {
+    let mut current = state.clone();
+    update_state_predicates(model, &mut current);
+
+    let mut states = Vec::with_capacity(plan.trace.len());
+    for (step, frame) in plan.trace.iter().enumerate() {
+        // the first frame, and frames where the planner stutters, have no transition.
+        if frame.transition != SPPath::default() {
+            let t = model
+                .transitions
+                .iter()
+                .find(|t| t.path == frame.transition)
+                .ok_or_else(|| PlanDivergence::UnknownTransition {
+                    step,
+                    transition: frame.transition.clone(),
+                })?;
+            current =
+                replay_step(model, t, &current, &frame.state).map_err(|e| e.at(step, &t.path))?;
+        }
+
+        let mismatch = model
+            .vars
+            .iter()
+            .map(|v| &v.path)
+            .chain(model.state_predicates.iter().map(|p| &p.path))
+            .find_map(|path| {
+                let predicted = frame.state.sp_value_from_path(path)?;
+                let actual = current.sp_value_from_path(path);
+                if actual != Some(predicted) {
+                    Some(PlanDivergence::StateMismatch {
+                        step,
+                        transition: frame.transition.clone(),
+                        path: path.clone(),
+                        predicted: predicted.clone(),
+                        actual: actual.cloned(),
+                    })
+                } else {
+                    None
+                }
+            });
+        if let Some(mismatch) = mismatch {
+            return Err(mismatch);
+        }
+
+        states.push(current.clone());
+    }
+
+    Ok(states)
+}
+
+enum StepError {
+    Guard,
+    Action(String),
+}
+
+impl StepError {
+    fn at(self, step: usize, transition: &SPPath) -> PlanDivergence {
+        let transition = transition.clone();
+        match self {
+            StepError::Guard => PlanDivergence::GuardFalse { step, transition },
+            StepError::Action(message) => PlanDivergence::ActionFailed {
+                step,
+                transition,
+                message,
+            },
+        }
+    }
+}
+
+fn replay_step(
+    model: &TransitionSystemModel, t: &Transition, state: &SPState, predicted: &SPState,
+) -> Result<SPState, StepError> {
+    if !t.eval(state) {
+        return Err(StepError::Guard);
+    }
+
+    let mut next = state.clone();
+    t.next(&mut next)
+        .map_err(|e| StepError::Action(format!("{e:?}")))?;
+    for a in t.actions.iter().filter(|a| a.value == Compute::Any) {
+        if let Some(value) = predicted.sp_value_from_path(&a.var) {
+            next.next_from_path(&a.var, value.clone())
+                .map_err(|e| StepError::Action(format!("{e:?}")))?;
+        }
+    }
+    for a in &t.actions {
+        let var = model.vars.iter().find(|v| v.path == a.var);
+        let value = next
+            .state_value_from_path(&a.var)
+            .and_then(|v| v.next_value().as_ref());
+        if let (Some(var), Some(value)) = (var, value) {
+            if !var.in_range(value) {
+                return Err(StepError::Action(format!(
+                    "{} = {value} is out of range",
+                    a.var
+                )));
+            }
+        }
+    }
+
+    next.take_transition();
+    update_state_predicates(model, &mut next);
+    Ok(next)
+}
+
+#[cfg(test)]
+mod validate_tests {
+    use super::*;
+    use crate::planning::*;
+
+    fn model() -> (TransitionSystemModel, SPState) {
+        let x = SPPath::from("x");
+        let done = SPPath::from("done");
+        let at_two = SPPath::from("at_two");
+        let mut tsm = TransitionSystemModel::default();
+        tsm.vars.push(Variable::new_range(x.clone(), 0, 3));
+        tsm.vars.push(Variable::new_boolean(done.clone()));
+        tsm.state_predicates
+            .push(NamedPredicate::new(at_two.clone(), p!(x == 2)));
+        tsm.transitions
+            .push(Transition::new("inc".into(), p!(!done), vec![a!(x += 1)]));
+        tsm.transitions
+            .push(Transition::new("finish".into(), p!(at_two), vec![a!(done)]));
+        (tsm, state!(x => 0, done => false))
+    }
+
+    #[test]
+    fn replays_found_plans() {
+        let (tsm, state) = model();
+        let done = SPPath::from("done");
+        let goal = p!(done);
+        let result = ExplicitPlanner::default()
+            .plan(&tsm, &[(goal, None)], &state, 10)
+            .unwrap();
+        assert!(result.plan_found);
+
+        let states = validate_plan(&tsm, &state, &result).unwrap();
+        assert_eq!(states.len(), result.trace.len());
+        let last = states.last().unwrap();
+        assert_eq!(last.sp_value_from_path(&"x".into()), Some(&2.to_spvalue()));
+        assert_eq!(
+            last.sp_value_from_path(&"done".into()),
+            Some(&true.to_spvalue())
+        );
+    }
+
+    #[test]
+    fn reports_the_first_divergence() {
+        let (tsm, state) = model();
+        let done = SPPath::from("done");
+        let goal = p!(done);
+        let result = ExplicitPlanner::default()
+            .plan(&tsm, &[(goal, None)], &state, 10)
+            .unwrap();
+
+        // finish taken too early
+        let mut early = result.clone();
+        early.trace.remove(2);
+        assert_eq!(
+            validate_plan(&tsm, &state, &early),
+            Err(PlanDivergence::GuardFalse {
+                step: 2,
+                transition: "finish".into()
+            })
+        );
+
+        // the planner predicted a different value
+        let mut wrong = result.clone();
+        wrong.trace[1]
+            .state
+            .add_variable("x".into(), 3.to_spvalue());
+        assert_eq!(
+            validate_plan(&tsm, &state, &wrong),
+            Err(PlanDivergence::StateMismatch {
+                step: 1,
+                transition: "inc".into(),
+                path: "x".into(),
+                predicted: 3.to_spvalue(),
+                actual: Some(1.to_spvalue()),
+            })
+        );
+
+        let mut unknown = result;
+        unknown.trace[1].transition = "jump".into();
+        assert!(matches!(
+            validate_plan(&tsm, &state, &unknown),
+            Err(PlanDivergence::UnknownTransition { step: 1, .. })
+        ));
+    }
+}
@@ -12,6 +12,10 @@ pub struct TransitionSystemModel {
     pub transitions: Vec<Transition>,
     pub invariants: Vec<NamedPredicate>,
 
+    /// Transitions that can not be disabled by a supervisor.
+    #[serde(default)]
+    pub uncontrollable: Vec<SPPath>,
+
     /// TS model currently "compiled" against this state id.
     pub state_id: Uuid,
 }
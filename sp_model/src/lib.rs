@@ -4,6 +4,12 @@ use serde::{Deserialize, Serialize};
 
 // For derive macro.
 pub use sp_model_derive::Resource;
+
+mod validate;
+pub use validate::*;
+
+mod model_file;
+pub use model_file::*;
 pub trait Resource {
     fn new(name: &str) -> Self;
     fn get_variables(&self) -> Vec<Variable>;
@@ -115,6 +121,60 @@ pub struct ModelTransition {
     pub transitions: Vec<(Transition, TransitionType)>
 }
 
+impl ModelTransition {
+    /// Set the cost of taking this transition when planning.
+    pub fn with_cost(mut self, cost: Cost) -> Self {
+        for (t, _) in &mut self.transitions {
+            t.cost = Some(cost);
+        }
+        self
+    }
+
+    /// Whether the planner decides when to take this transition.
+    pub fn is_controlled(&self) -> bool {
+        self.transitions.iter().any(|(_, tt)| tt == &TransitionType::Controlled)
+    }
+
+    /// The parts executed by the runner merged into one transition,
+    /// so that they execute atomically: it is enabled when all parts
+    /// are and it takes all their actions. Effects only happen in the
+    /// environment and are left out. The merged transition is named
+    /// after the formal part, which is what plans refer to.
+    pub fn runner_transition(&self) -> Option<Transition> {
+        let parts: Vec<&Transition> = self.transitions.iter()
+            .filter(|(_, tt)| tt != &TransitionType::Effect)
+            .map(|(t, _)| t)
+            .collect();
+        let path = self.transitions.iter()
+            .find(|(_, tt)| tt == &TransitionType::Controlled || tt == &TransitionType::Auto)
+            .map(|(t, _)| &t.path)
+            .or_else(|| parts.first().map(|t| &t.path))?;
+        let guard = if parts.len() == 1 {
+            parts[0].guard.clone()
+        } else {
+            Predicate::AND(parts.iter().map(|t| t.guard.clone()).collect())
+        };
+        let actions = parts.iter().flat_map(|t| t.actions.clone()).collect();
+        Some(Transition::new(path.clone(), guard, actions))
+    }
+}
+
+/// The transitions executed by the runner, as (controlled, uncontrolled).
+pub fn get_runner_transitions(mts: &[ModelTransition]) -> (Vec<Transition>, Vec<Transition>) {
+    let mut controlled = vec![];
+    let mut uncontrolled = vec![];
+    for mt in mts {
+        if let Some(t) = mt.runner_transition() {
+            if mt.is_controlled() {
+                controlled.push(t);
+            } else {
+                uncontrolled.push(t);
+            }
+        }
+    }
+    (controlled, uncontrolled)
+}
+
 pub fn get_formal_transitions(mts: &[ModelTransition]) -> Vec<Transition> {
     let mut trans = vec![];
     for mt in mts {
@@ -127,11 +187,284 @@ pub fn get_formal_transitions(mts: &[ModelTransition]) -> Vec<Transition> {
     trans
 }
 
+pub fn get_uncontrollable_transitions(mts: &[ModelTransition]) -> Vec<SPPath> {
+    let mut trans = vec![];
+    for mt in mts {
+        for (t, tt) in &mt.transitions {
+            if tt == &TransitionType::Auto || tt == &TransitionType::Effect {
+                trans.push(t.path.clone());
+            }
+        }
+    }
+    trans
+}
+
+
+/// The values of an operation variable in each state of the operation.
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
+pub struct OperationStates {
+    pub init: String,
+    pub executing: String,
+    pub finished: String,
+    pub error: String,
+    pub timeout: String,
+}
+
+impl Default for OperationStates {
+    fn default() -> Self {
+        OperationStates {
+            init: "i".into(),
+            executing: "e".into(),
+            finished: "f".into(),
+            error: "error".into(),
+            timeout: "timeout".into(),
+        }
+    }
+}
+
+/// An operation abstracts away implementation details from the planner.
+/// The planner only sees it start, when `pre` holds, and finish, when
+/// `goal` has been reached. The runner parts are executed together
+/// with these, e.g. to send commands to the resources.
+///
+/// A finished operation can be reset to init by the planner. While
+/// executing, the operation times out after `max_duration`, and the
+/// runner may also set it to error, e.g. when the planner can not
+/// reach its goal. Failed operations are reset automatically at most
+/// `max_retries` times in a row.
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
+pub struct Operation {
+    pub path: SPPath,
+    pub pre: Predicate,
+    #[serde(default)]
+    pub pre_actions: Vec<Action>,
+    #[serde(default)]
+    pub runner_pre: Predicate,
+    #[serde(default)]
+    pub runner_pre_actions: Vec<Action>,
+    /// What the planner should reach while the operation is executing.
+    pub goal: Predicate,
+    #[serde(default)]
+    pub goal_actions: Vec<Action>,
+    #[serde(default)]
+    pub runner_post: Predicate,
+    #[serde(default)]
+    pub runner_post_actions: Vec<Action>,
+    #[serde(default)]
+    pub states: OperationStates,
+    /// Time out after executing this many milliseconds.
+    #[serde(default)]
+    pub max_duration: Option<u32>,
+    #[serde(default)]
+    pub max_retries: u32,
+}
+
+impl Operation {
+    pub fn new(path: SPPath, pre: Predicate, goal: Predicate) -> Self {
+        Operation {
+            path,
+            pre,
+            pre_actions: vec![],
+            runner_pre: Predicate::TRUE,
+            runner_pre_actions: vec![],
+            goal,
+            goal_actions: vec![],
+            runner_post: Predicate::TRUE,
+            runner_post_actions: vec![],
+            states: OperationStates::default(),
+            max_duration: None,
+            max_retries: 0,
+        }
+    }
+
+    pub fn with_max_duration(mut self, millis: u32) -> Self {
+        self.max_duration = Some(millis);
+        self
+    }
+
+    pub fn with_retries(mut self, retries: u32) -> Self {
+        self.max_retries = retries;
+        self
+    }
+
+    /// When the operation started executing, only used with a max duration.
+    pub fn started(&self) -> SPPath {
+        self.path.add_child("started")
+    }
+
+    /// How many times in a row the operation has been retried.
+    pub fn retries(&self) -> SPPath {
+        self.path.add_child("retries")
+    }
+
+    fn is(&self, state: &SPState, value: &str) -> bool {
+        state.sp_value_from_path(&self.path) == Some(&value.to_spvalue())
+    }
+
+    pub fn is_executing(&self, state: &SPState) -> bool {
+        self.is(state, &self.states.executing)
+    }
+
+    pub fn is_finished(&self, state: &SPState) -> bool {
+        self.is(state, &self.states.finished)
+    }
+
+    pub fn is_error(&self, state: &SPState) -> bool {
+        self.is(state, &self.states.error)
+    }
+
+    pub fn is_timeout(&self, state: &SPState) -> bool {
+        self.is(state, &self.states.timeout)
+    }
+
+    fn in_state(&self, value: &str) -> Predicate {
+        Predicate::EQ(
+            PredicateValue::SPPath(self.path.clone(), None),
+            PredicateValue::SPValue(value.to_spvalue()),
+        )
+    }
+
+    fn set_state(&self, value: &str) -> Action {
+        Action::new(
+            self.path.clone(),
+            Compute::PredicateValue(PredicateValue::SPValue(value.to_spvalue())),
+        )
+    }
+
+    /// The operation variable and the variables used for timeouts and retries.
+    pub fn variables(&self) -> Vec<Variable> {
+        let s = &self.states;
+        let values = [&s.init, &s.executing, &s.finished, &s.error, &s.timeout];
+        let mut var = Variable::new(
+            self.path.clone(),
+            SPValueType::String,
+            values.iter().map(|v| v.to_spvalue()).collect(),
+        );
+        var.initial_state = s.init.to_spvalue();
+
+        let mut vars = vec![var];
+        if self.max_duration.is_some() {
+            vars.push(Variable::new(self.started(), SPValueType::Time, vec![]));
+        }
+        if self.max_retries > 0 {
+            let mut retries = Variable::new_range(self.retries(), 0, self.max_retries as i32);
+            retries.initial_state = 0.to_spvalue();
+            vars.push(retries);
+        }
+        vars
+    }
+
+    /// The transitions of the operation. The formal parts are what
+    /// the planner sees of the operation, timeouts are only detected
+    /// by the runner.
+    pub fn transitions(&self) -> Vec<ModelTransition> {
+        let s = &self.states;
+        let path = &self.path;
+
+        let mut start_actions = self.pre_actions.clone();
+        start_actions.push(self.set_state(&s.executing));
+        let mut runner_start_actions = self.runner_pre_actions.clone();
+        if self.max_duration.is_some() {
+            runner_start_actions.push(Action::new(self.started(), Compute::TimeStamp));
+        }
+
+        let mut finish_actions = self.goal_actions.clone();
+        finish_actions.push(self.set_state(&s.finished));
+        if self.max_retries > 0 {
+            let retries = self.retries();
+            finish_actions.push(a!(retries = 0));
+        }
+
+        let mut trans = vec![
+            ModelTransition {
+                transitions: vec![
+                    (Transition::new(path.add_child("formal_start"),
+                                     Predicate::AND(vec![self.in_state(&s.init), self.pre.clone()]),
+                                     start_actions),
+                     TransitionType::Controlled),
+                    (Transition::new(path.add_child("runner_start"),
+                                     self.runner_pre.clone(), runner_start_actions),
+                     TransitionType::Runner),
+                ]
+            },
+            ModelTransition {
+                transitions: vec![
+                    (Transition::new(path.add_child("formal_finish"),
+                                     Predicate::AND(vec![self.in_state(&s.executing), self.goal.clone()]),
+                                     finish_actions),
+                     TransitionType::Auto),
+                    (Transition::new(path.add_child("runner_finish"),
+                                     self.runner_post.clone(), self.runner_post_actions.clone()),
+                     TransitionType::Runner),
+                ]
+            },
+            ModelTransition {
+                transitions: vec![
+                    (Transition::new(path.add_child("reset"),
+                                     self.in_state(&s.finished),
+                                     vec![self.set_state(&s.init)]),
+                     TransitionType::Controlled),
+                ]
+            },
+        ];
+
+        if let Some(max_duration) = self.max_duration {
+            let timer = Predicate::TON(
+                PredicateValue::SPPath(self.started(), None),
+                PredicateValue::SPValue((max_duration as i32).to_spvalue()),
+            );
+            trans.push(ModelTransition {
+                transitions: vec![
+                    (Transition::new(path.add_child("timeout"),
+                                     Predicate::AND(vec![self.in_state(&s.executing), timer]),
+                                     vec![self.set_state(&s.timeout)]),
+                     TransitionType::Runner),
+                ]
+            });
+        }
+
+        let failed = Predicate::OR(vec![self.in_state(&s.error), self.in_state(&s.timeout)]);
+        let mut recover_guard = failed.clone();
+        let mut recover_actions = vec![self.set_state(&s.init)];
+        if self.max_retries > 0 {
+            let retries = self.retries();
+            let retries_left = Predicate::LT(
+                PredicateValue::SPPath(retries.clone(), None),
+                PredicateValue::SPValue((self.max_retries as i32).to_spvalue()),
+            );
+            trans.push(ModelTransition {
+                transitions: vec![
+                    (Transition::new(path.add_child("retry"),
+                                     Predicate::AND(vec![failed, retries_left.clone()]),
+                                     vec![self.set_state(&s.init), a!(retries += 1)]),
+                     TransitionType::Auto),
+                ]
+            });
+            recover_guard = Predicate::AND(vec![recover_guard, Predicate::NOT(Box::new(retries_left))]);
+            recover_actions.push(a!(retries = 0));
+        }
+
+        // when there is nothing left to retry, the operation can only
+        // leave error or timeout when asked to.
+        trans.push(ModelTransition {
+            transitions: vec![
+                (Transition::new(path.add_child("recover"), recover_guard, recover_actions),
+                 TransitionType::Controlled),
+            ]
+        });
+
+        trans
+    }
+}
 
 #[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
 pub struct ModelBuilder {
     pub variables: Vec<Variable>,
     pub transitions: Vec<ModelTransition>,
+    #[serde(default)]
+    pub invariants: Vec<NamedPredicate>,
+    #[serde(default)]
+    pub operations: Vec<Operation>,
 
     pub messages: Vec<Message>,
 }
@@ -142,6 +475,8 @@ impl ModelBuilder {
         let mut mb = ModelBuilder {
             variables: vec![],
             transitions: vec![],
+            invariants: vec![],
+            operations: vec![],
             messages: vec![],
         };
         mb.variables.extend(model.get_variables());
@@ -152,6 +487,8 @@ impl ModelBuilder {
         let mut tsm = TransitionSystemModel::default();
         tsm.vars.extend(self.variables.clone());
         tsm.transitions.extend(get_formal_transitions(&self.transitions));
+        tsm.invariants.extend(self.invariants.clone());
+        tsm.uncontrollable.extend(get_uncontrollable_transitions(&self.transitions));
         tsm
     }
 
@@ -159,12 +496,30 @@ impl ModelBuilder {
         SPState::new_from_variables(&self.variables)
     }
 
+    pub fn add_invariant(&mut self, path: SPPath, predicate: Predicate) {
+        self.invariants.push(NamedPredicate::new(path, predicate));
+    }
+
+    /// Strengthen the guards of the controlled transitions with the
+    /// guards computed by the supervisor.
+    pub fn add_supervisor(&mut self, supervisor: &Supervisor) {
+        for mt in &mut self.transitions {
+            for (t, tt) in &mut mt.transitions {
+                if tt == &TransitionType::Controlled {
+                    supervisor.strengthen(t);
+                }
+            }
+        }
+    }
+
     pub fn add_message(&mut self, m: Message) {
         self.messages.push(m);
     }
 
     /// Operations can abstract away implementation details from the planner.
-    /// By defaut, only i -> e -> i are included in the formal representation.
+    /// By defaut, only i -> e -> f are included in the formal representation.
+    /// The runner sets the operation to "error" when the planner can not
+    /// reach `formal_post`. See `Operation` for timeouts and retries.
     pub fn add_operation(&mut self,
                          path: SPPath,
                          formal_pre: Predicate,
@@ -175,41 +530,23 @@ impl ModelBuilder {
                          formal_post_actions: Vec<Action>,
                          runner_post: Predicate,
                          runner_post_actions: Vec<Action>) -> SPPath {
-        let mut var = Variable::new(path.clone(), SPValueType::String,
-                                    vec!["i".to_spvalue(),
-                                         "e".to_spvalue(),
-                                         "f".to_spvalue()]);
-        var.initial_state = "i".to_spvalue();
-        let formal_pre = p!([path == "i"] && [formal_pre]);
-        let mut formal_actions = formal_actions.clone();
-        formal_actions.push(a!(path = "e"));
-
-        let formal_post = p!([path == "e"] && [formal_post]);
-        let mut formal_post_actions = formal_post_actions.clone();
-        formal_post_actions.push(a!(path = "f"));
-
-        let trans = vec![
-            ModelTransition {
-                transitions: vec![
-                    (Transition::new(path.add_child("formal_start".into()), formal_pre, formal_actions),
-                     TransitionType::Controlled),
-                    (Transition::new(path.add_child("runner_start".into()), runner_pre, runner_actions),
-                     TransitionType::Runner),
-                ]
-            },
-            ModelTransition {
-                transitions: vec![
-                    (Transition::new(path.add_child("formal_finish".into()), formal_post, formal_post_actions),
-                     TransitionType::Auto),
-                    (Transition::new(path.add_child("runner_finish".into()), runner_post, runner_post_actions),
-                     TransitionType::Runner),
-                ]
-            }
-        ];
+        self.insert_operation(Operation {
+            pre_actions: formal_actions,
+            runner_pre,
+            runner_pre_actions: runner_actions,
+            goal_actions: formal_post_actions,
+            runner_post,
+            runner_post_actions,
+            ..Operation::new(path, formal_pre, formal_post)
+        })
+    }
 
-        let path = var.path.clone();
-        self.variables.push(var);
-        self.transitions.extend(trans);
+    /// Add an operation together with its variables and transitions.
+    pub fn insert_operation(&mut self, op: Operation) -> SPPath {
+        self.variables.extend(op.variables());
+        self.transitions.extend(op.transitions());
+        let path = op.path.clone();
+        self.operations.push(op);
         path
     }
 
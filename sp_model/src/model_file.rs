@@ -0,0 +1,718 @@
+use crate::*;
+use serde::de::{self, Deserializer, Visitor};
+use serde::ser::{self, Serializer};
+use std::fmt;
+use std::path::Path;
+
+/// The formats a model file can be written in.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum ModelFormat {
+    Yaml,
+    Toml,
+    Json,
+}
+
+impl ModelFormat {
+    /// The format given by the extension of `path`.
+    pub fn from_path(path: &Path) -> Option<Self> {
+        match path.extension()?.to_str()? {
+            "yaml" | "yml" => Some(ModelFormat::Yaml),
+            "toml" => Some(ModelFormat::Toml),
+            "json" => Some(ModelFormat::Json),
+            _ => None,
+        }
+    }
+}
+
+/// A problem reading or writing a model file. Line and column are
+/// counted from 1 and point at the offending part of the file, when
+/// it is known.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct ModelFileError {
+    pub line: Option<usize>,
+    pub column: Option<usize>,
+    pub message: String,
+}
+
+impl ModelFileError {
+    fn new(message: impl Into<String>) -> Self {
+        ModelFileError {
+            line: None,
+            column: None,
+            message: message.into(),
+        }
+    }
+
+    fn at(line: usize, column: usize, message: impl Into<String>) -> Self {
+        // zero means unknown for both serde_json and serde_yaml.
+        ModelFileError {
+            line: Some(line).filter(|l| *l > 0),
+            column: Some(column).filter(|c| *c > 0),
+            message: message.into(),
+        }
+    }
+}
+
+impl fmt::Display for ModelFileError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match (self.line, self.column) {
+            (Some(line), Some(column)) => write!(f, "line {}, column {}: {}", line, column, self.message),
+            (Some(line), None) => write!(f, "line {}: {}", line, self.message),
+            _ => write!(f, "{}", self.message),
+        }
+    }
+}
+
+impl std::error::Error for ModelFileError {}
+
+/// A model written by hand, in YAML, TOML or JSON. Paths are written
+/// with "." as separator, predicates in the syntax of `pred_parser`
+/// and actions as `p:x := value`, see `Action::from_string`. Omitted
+/// guards and predicates are true.
+///
+/// ```yaml
+/// variables:
+///   - { path: cell.door, type: string, domain: [open, closed], initial: closed }
+///   - { path: cell.parts, type: int, range: [0, 10], initial: 0 }
+///   - { path: cell.cmd, type: string, domain: [open, close] }
+/// invariants:
+///   - { path: cell.safe, predicate: "!(p:cell.door == open && p:cell.parts > 5)" }
+/// transitions:
+///   - path: cell.open
+///     type: controlled
+///     guard: p:cell.door == closed
+///     actions: ["p:cell.door := open"]
+///     # executed by the runner together with the transition above.
+///     parts:
+///       - { path: cell.open_cmd, type: runner, actions: ["p:cell.cmd := open"] }
+/// operations:
+///   - { path: cell.fill, pre: "p:cell.door == closed", goal: "p:cell.parts == 10" }
+/// messages:
+///   - name: cell.door_state
+///     topic: door/state
+///     category: incoming
+///     message_type: std_msgs/msg/String
+///     variables: [{ path: cell.door, ros_path: data }]
+/// ```
+///
+/// Transition types are controlled, auto, effect and runner. Message
+/// categories are outgoing, incoming, service and action, message types
+/// json, json_flat or a ROS message type. The variables and transitions
+/// of operations are created when loading, see
+/// `ModelBuilder::insert_operation`, and are left out when exporting.
+#[derive(Debug, PartialEq, Clone, Default, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ModelFile {
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub variables: Vec<VariableDecl>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub invariants: Vec<InvariantDecl>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub transitions: Vec<TransitionDecl>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub operations: Vec<OperationDecl>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub messages: Vec<MessageDecl>,
+}
+
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct VariableDecl {
+    pub path: FilePath,
+    #[serde(rename = "type")]
+    pub value_type: FileValueType,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub domain: Vec<FileValue>,
+    /// Bounds of integer variables, the domain is all values in between.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub range: Option<(i32, i32)>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub initial: Option<FileValue>,
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum FileValueType {
+    Bool,
+    Int,
+    Float,
+    String,
+    Time,
+}
+
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct InvariantDecl {
+    pub path: FilePath,
+    pub predicate: FilePredicate,
+}
+
+/// A model transition. The transitions in `parts` are executed by the
+/// runner together with this one, see `ModelTransition`.
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct TransitionDecl {
+    pub path: FilePath,
+    #[serde(rename = "type")]
+    pub transition_type: FileTransitionType,
+    #[serde(default, skip_serializing_if = "FilePredicate::is_true")]
+    pub guard: FilePredicate,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub actions: Vec<FileAction>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cost: Option<Cost>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub parts: Vec<TransitionDecl>,
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum FileTransitionType {
+    Controlled,
+    Auto,
+    Effect,
+    Runner,
+}
+
+/// An operation, see `Operation` for the meaning of the fields.
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct OperationDecl {
+    pub path: FilePath,
+    #[serde(default, skip_serializing_if = "FilePredicate::is_true")]
+    pub pre: FilePredicate,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub pre_actions: Vec<FileAction>,
+    #[serde(default, skip_serializing_if = "FilePredicate::is_true")]
+    pub runner_pre: FilePredicate,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub runner_pre_actions: Vec<FileAction>,
+    pub goal: FilePredicate,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub goal_actions: Vec<FileAction>,
+    #[serde(default, skip_serializing_if = "FilePredicate::is_true")]
+    pub runner_post: FilePredicate,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub runner_post_actions: Vec<FileAction>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub states: Option<OperationStates>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_duration: Option<u32>,
+    #[serde(default, skip_serializing_if = "is_zero")]
+    pub max_retries: u32,
+}
+
+fn is_zero(x: &u32) -> bool {
+    *x == 0
+}
+
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct MessageDecl {
+    pub name: FilePath,
+    pub topic: FilePath,
+    pub category: FileMessageCategory,
+    /// json, json_flat or the type of a ROS message.
+    pub message_type: String,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub variables: Vec<MessageVariableDecl>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub variables_response: Vec<MessageVariableDecl>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub variables_feedback: Vec<MessageVariableDecl>,
+    #[serde(default, skip_serializing_if = "FilePredicate::is_true")]
+    pub send_predicate: FilePredicate,
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum FileMessageCategory {
+    OutGoing,
+    Incoming,
+    Service,
+    Action,
+}
+
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct MessageVariableDecl {
+    pub path: FilePath,
+    pub ros_path: FilePath,
+}
+
+/// A path written as text, e.g. `cell.robot.state`.
+#[derive(Debug, PartialEq, Clone)]
+pub struct FilePath(pub SPPath);
+
+impl Serialize for FilePath {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&self.0.to_string())
+    }
+}
+
+impl<'de> Deserialize<'de> for FilePath {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let s = String::deserialize(deserializer)?;
+        if s.is_empty() {
+            return Err(de::Error::custom("the path is empty"));
+        }
+        Ok(FilePath(SPPath::from(s)))
+    }
+}
+
+/// A predicate written in the syntax of `pred_parser`.
+#[derive(Debug, PartialEq, Clone)]
+pub struct FilePredicate(pub Predicate);
+
+impl FilePredicate {
+    fn is_true(&self) -> bool {
+        self.0 == Predicate::TRUE
+    }
+}
+
+impl Default for FilePredicate {
+    fn default() -> Self {
+        FilePredicate(Predicate::TRUE)
+    }
+}
+
+impl Serialize for FilePredicate {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        match self.0.to_parsable_string() {
+            Some(s) => serializer.serialize_str(&s),
+            None => Err(ser::Error::custom(format!("can not write the predicate {}", self.0))),
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for FilePredicate {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let s = String::deserialize(deserializer)?;
+        sp_domain::predicate_parser::pred_parser::pred(&s)
+            .map(FilePredicate)
+            .map_err(|e| de::Error::custom(format!("invalid predicate \"{}\": {}", s, e)))
+    }
+}
+
+/// An action written as `p:x := value`, see `Action::from_string`.
+#[derive(Debug, PartialEq, Clone)]
+pub struct FileAction(pub Action);
+
+impl Serialize for FileAction {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        match self.0.to_parsable_string() {
+            Some(s) => serializer.serialize_str(&s),
+            None => Err(ser::Error::custom(format!("can not write the action {}", self.0))),
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for FileAction {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let s = String::deserialize(deserializer)?;
+        sp_domain::predicate_parser::pred_parser::action(&s)
+            .map(FileAction)
+            .map_err(|e| de::Error::custom(format!("invalid action \"{}\": {}", s, e)))
+    }
+}
+
+/// A bool, integer, float or string value.
+#[derive(Debug, PartialEq, Clone)]
+pub struct FileValue(pub SPValue);
+
+impl Serialize for FileValue {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        match &self.0 {
+            SPValue::Bool(b) => serializer.serialize_bool(*b),
+            SPValue::Int32(i) => serializer.serialize_i32(*i),
+            SPValue::Float32(f) => serializer.serialize_f32(*f),
+            SPValue::String(s) => serializer.serialize_str(s),
+            v => Err(ser::Error::custom(format!("can not write the value {}", v))),
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for FileValue {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct ValueVisitor;
+
+        impl<'de> Visitor<'de> for ValueVisitor {
+            type Value = FileValue;
+
+            fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                write!(f, "a bool, integer, float or string")
+            }
+
+            fn visit_bool<E: de::Error>(self, v: bool) -> Result<FileValue, E> {
+                Ok(FileValue(v.to_spvalue()))
+            }
+
+            fn visit_i64<E: de::Error>(self, v: i64) -> Result<FileValue, E> {
+                i32::try_from(v)
+                    .map(|i| FileValue(i.to_spvalue()))
+                    .map_err(|_| E::custom(format!("the integer {} is too large", v)))
+            }
+
+            fn visit_u64<E: de::Error>(self, v: u64) -> Result<FileValue, E> {
+                i32::try_from(v)
+                    .map(|i| FileValue(i.to_spvalue()))
+                    .map_err(|_| E::custom(format!("the integer {} is too large", v)))
+            }
+
+            fn visit_f64<E: de::Error>(self, v: f64) -> Result<FileValue, E> {
+                Ok(FileValue((v as f32).to_spvalue()))
+            }
+
+            fn visit_str<E: de::Error>(self, v: &str) -> Result<FileValue, E> {
+                Ok(FileValue(v.to_spvalue()))
+            }
+        }
+
+        deserializer.deserialize_any(ValueVisitor)
+    }
+}
+
+fn value_type_to_file(path: &SPPath, t: SPValueType) -> Result<FileValueType, ModelFileError> {
+    match t {
+        SPValueType::Bool => Ok(FileValueType::Bool),
+        SPValueType::Int32 => Ok(FileValueType::Int),
+        SPValueType::Float32 => Ok(FileValueType::Float),
+        SPValueType::String => Ok(FileValueType::String),
+        SPValueType::Time => Ok(FileValueType::Time),
+        t => Err(ModelFileError::new(format!("{}: variables of type {:?} can not be written", path, t))),
+    }
+}
+
+fn value_to_file(path: &SPPath, v: &SPValue) -> Result<FileValue, ModelFileError> {
+    match v {
+        SPValue::Bool(_) | SPValue::Int32(_) | SPValue::Float32(_) | SPValue::String(_) => Ok(FileValue(v.clone())),
+        v => Err(ModelFileError::new(format!("{}: the value {} can not be written", path, v))),
+    }
+}
+
+fn predicate_to_file(path: &SPPath, p: &Predicate) -> Result<FilePredicate, ModelFileError> {
+    match p.to_parsable_string() {
+        Some(_) => Ok(FilePredicate(p.clone())),
+        None => Err(ModelFileError::new(format!("{}: the predicate {} can not be written", path, p))),
+    }
+}
+
+fn actions_to_file(path: &SPPath, actions: &[Action]) -> Result<Vec<FileAction>, ModelFileError> {
+    actions.iter().map(|a| match a.to_parsable_string() {
+        Some(_) => Ok(FileAction(a.clone())),
+        None => Err(ModelFileError::new(format!("{}: the action {} can not be written", path, a))),
+    }).collect()
+}
+
+fn transition_to_file(t: &Transition, tt: &TransitionType) -> Result<TransitionDecl, ModelFileError> {
+    let transition_type = match tt {
+        TransitionType::Controlled => FileTransitionType::Controlled,
+        TransitionType::Auto => FileTransitionType::Auto,
+        TransitionType::Effect => FileTransitionType::Effect,
+        TransitionType::Runner => FileTransitionType::Runner,
+    };
+    Ok(TransitionDecl {
+        path: FilePath(t.path.clone()),
+        transition_type,
+        guard: predicate_to_file(&t.path, &t.guard)?,
+        actions: actions_to_file(&t.path, &t.actions)?,
+        cost: t.cost,
+        parts: vec![],
+    })
+}
+
+fn message_variables_to_file(vars: &[MessageVariable]) -> Vec<MessageVariableDecl> {
+    vars.iter().map(|v| MessageVariableDecl {
+        path: FilePath(v.path.clone()),
+        ros_path: FilePath(v.ros_path.clone()),
+    }).collect()
+}
+
+fn message_variables_from_file(vars: Vec<MessageVariableDecl>) -> Vec<MessageVariable> {
+    vars.into_iter().map(|v| MessageVariable::new(&v.path.0, &v.ros_path.0)).collect()
+}
+
+/// The line and column of a byte offset in `text`.
+fn line_column(text: &str, offset: usize) -> (usize, usize) {
+    let before = &text[..offset.min(text.len())];
+    let line = before.matches('\n').count() + 1;
+    let column = before.chars().rev().take_while(|c| *c != '\n').count() + 1;
+    (line, column)
+}
+
+impl ModelFile {
+    pub fn parse(text: &str, format: ModelFormat) -> Result<Self, ModelFileError> {
+        match format {
+            ModelFormat::Yaml => serde_yaml::from_str(text).map_err(|e| {
+                let message = e.to_string();
+                // the message ends with the location, which we report separately.
+                let message = message.split(" at line ").next().unwrap_or_default().to_string();
+                match e.location() {
+                    Some(l) => ModelFileError::at(l.line(), l.column(), message),
+                    None => ModelFileError::new(message),
+                }
+            }),
+            ModelFormat::Toml => toml::from_str(text).map_err(|e| {
+                let message = e.message().to_string();
+                match e.span() {
+                    Some(span) => {
+                        let (line, column) = line_column(text, span.start);
+                        ModelFileError::at(line, column, message)
+                    }
+                    None => ModelFileError::new(message),
+                }
+            }),
+            ModelFormat::Json => serde_json::from_str(text).map_err(|e| {
+                let message = e.to_string();
+                let message = message.split(" at line ").next().unwrap_or_default().to_string();
+                ModelFileError::at(e.line(), e.column(), message)
+            }),
+        }
+    }
+
+    pub fn write(&self, format: ModelFormat) -> Result<String, ModelFileError> {
+        let to_error = |e: &dyn fmt::Display| ModelFileError::new(e.to_string());
+        match format {
+            ModelFormat::Yaml => serde_yaml::to_string(self).map_err(|e| to_error(&e)),
+            ModelFormat::Toml => toml::to_string_pretty(self).map_err(|e| to_error(&e)),
+            ModelFormat::Json => serde_json::to_string_pretty(self).map_err(|e| to_error(&e)),
+        }
+    }
+
+    /// The file describing `model`. Fails if the model has predicates or
+    /// actions that the text syntax can not express, e.g. timers.
+    pub fn from_model(model: &ModelBuilder) -> Result<Self, ModelFileError> {
+        // what the operations add is left out, it is added again when
+        // the file is loaded. other paths below an operation are kept.
+        let op_vars: Vec<SPPath> = model.operations.iter()
+            .flat_map(|o| o.variables())
+            .map(|v| v.path)
+            .collect();
+        let op_transitions: Vec<SPPath> = model.operations.iter()
+            .flat_map(|o| o.transitions())
+            .flat_map(|mt| mt.transitions)
+            .map(|(t, _)| t.path)
+            .collect();
+        let is_op_transition = |mt: &ModelTransition| {
+            mt.transitions.iter().any(|(t, _)| op_transitions.contains(&t.path))
+        };
+
+        let mut file = ModelFile::default();
+        for v in model.variables.iter().filter(|v| !op_vars.contains(&v.path)) {
+            let initial = match &v.initial_state {
+                SPValue::Unknown => None,
+                value => Some(value_to_file(&v.path, value)?),
+            };
+            // the domain of a range is implied.
+            let domain = if v.range.is_some() {
+                vec![]
+            } else {
+                v.domain.iter().map(|d| value_to_file(&v.path, d)).collect::<Result<_, _>>()?
+            };
+            file.variables.push(VariableDecl {
+                path: FilePath(v.path.clone()),
+                value_type: value_type_to_file(&v.path, v.value_type)?,
+                domain,
+                range: v.range,
+                initial,
+            });
+        }
+
+        for i in &model.invariants {
+            file.invariants.push(InvariantDecl {
+                path: FilePath(i.path.clone()),
+                predicate: predicate_to_file(&i.path, &i.predicate)?,
+            });
+        }
+
+        for mt in model.transitions.iter().filter(|mt| !is_op_transition(mt)) {
+            let mut parts = mt.transitions.iter().map(|(t, tt)| transition_to_file(t, tt));
+            let mut decl = match parts.next() {
+                Some(decl) => decl?,
+                None => continue,
+            };
+            decl.parts = parts.collect::<Result<_, _>>()?;
+            file.transitions.push(decl);
+        }
+
+        for o in &model.operations {
+            file.operations.push(OperationDecl {
+                path: FilePath(o.path.clone()),
+                pre: predicate_to_file(&o.path, &o.pre)?,
+                pre_actions: actions_to_file(&o.path, &o.pre_actions)?,
+                runner_pre: predicate_to_file(&o.path, &o.runner_pre)?,
+                runner_pre_actions: actions_to_file(&o.path, &o.runner_pre_actions)?,
+                goal: predicate_to_file(&o.path, &o.goal)?,
+                goal_actions: actions_to_file(&o.path, &o.goal_actions)?,
+                runner_post: predicate_to_file(&o.path, &o.runner_post)?,
+                runner_post_actions: actions_to_file(&o.path, &o.runner_post_actions)?,
+                states: Some(o.states.clone()).filter(|s| s != &OperationStates::default()),
+                max_duration: o.max_duration,
+                max_retries: o.max_retries,
+            });
+        }
+
+        for m in &model.messages {
+            let category = match m.category {
+                MessageCategory::OutGoing => FileMessageCategory::OutGoing,
+                MessageCategory::Incoming => FileMessageCategory::Incoming,
+                MessageCategory::Service => FileMessageCategory::Service,
+                MessageCategory::Action => FileMessageCategory::Action,
+            };
+            let message_type = match &m.message_type {
+                MessageType::Ros(t) => t.clone(),
+                MessageType::JsonFlat => "json_flat".to_string(),
+                MessageType::Json => "json".to_string(),
+            };
+            file.messages.push(MessageDecl {
+                name: FilePath(m.name.clone()),
+                topic: FilePath(m.topic.clone()),
+                category,
+                message_type,
+                variables: message_variables_to_file(&m.variables),
+                variables_response: message_variables_to_file(&m.variables_response),
+                variables_feedback: message_variables_to_file(&m.variables_feedback),
+                send_predicate: predicate_to_file(&m.name, &m.send_predicate)?,
+            });
+        }
+
+        Ok(file)
+    }
+
+    /// The model described by the file. Fails for declarations the
+    /// syntax allows but that do not make sense, e.g. a range on a
+    /// variable that is not an int.
+    pub fn into_model(self) -> Result<ModelBuilder, ModelFileError> {
+        let mut mb = ModelBuilder {
+            variables: vec![],
+            transitions: vec![],
+            invariants: vec![],
+            operations: vec![],
+            messages: vec![],
+        };
+
+        for v in self.variables {
+            let value_type = match v.value_type {
+                FileValueType::Bool => SPValueType::Bool,
+                FileValueType::Int => SPValueType::Int32,
+                FileValueType::Float => SPValueType::Float32,
+                FileValueType::String => SPValueType::String,
+                FileValueType::Time => SPValueType::Time,
+            };
+            if v.range.is_some() && !v.domain.is_empty() {
+                return Err(ModelFileError::new(format!("{}: a range can not be combined with a domain", v.path.0)));
+            }
+            if v.range.is_some() && value_type != SPValueType::Int32 {
+                return Err(ModelFileError::new(format!("{}: only int variables can have a range", v.path.0)));
+            }
+            let mut var = match v.range {
+                Some((min, max)) => Variable::new_range(v.path.0, min, max),
+                None => Variable::new(v.path.0, value_type, v.domain.into_iter().map(|d| d.0).collect()),
+            };
+            var.value_type = value_type;
+            if let Some(initial) = v.initial {
+                var.initial_state = initial.0;
+            }
+            mb.variables.push(var);
+        }
+
+        for i in self.invariants {
+            mb.add_invariant(i.path.0, i.predicate.0);
+        }
+
+        for t in self.transitions {
+            let mut parts = vec![];
+            let mut todo = vec![t];
+            while let Some(t) = todo.pop() {
+                let tt = match t.transition_type {
+                    FileTransitionType::Controlled => TransitionType::Controlled,
+                    FileTransitionType::Auto => TransitionType::Auto,
+                    FileTransitionType::Effect => TransitionType::Effect,
+                    FileTransitionType::Runner => TransitionType::Runner,
+                };
+                let mut transition = Transition::new(
+                    t.path.0,
+                    t.guard.0,
+                    t.actions.into_iter().map(|a| a.0).collect());
+                transition.cost = t.cost;
+                parts.push((transition, tt));
+                todo.extend(t.parts.into_iter().rev());
+            }
+            mb.transitions.push(ModelTransition { transitions: parts });
+        }
+
+        for o in self.operations {
+            let actions = |xs: Vec<FileAction>| xs.into_iter().map(|a| a.0).collect();
+            mb.insert_operation(Operation {
+                path: o.path.0,
+                pre: o.pre.0,
+                pre_actions: actions(o.pre_actions),
+                runner_pre: o.runner_pre.0,
+                runner_pre_actions: actions(o.runner_pre_actions),
+                goal: o.goal.0,
+                goal_actions: actions(o.goal_actions),
+                runner_post: o.runner_post.0,
+                runner_post_actions: actions(o.runner_post_actions),
+                states: o.states.unwrap_or_default(),
+                max_duration: o.max_duration,
+                max_retries: o.max_retries,
+            });
+        }
+
+        for m in self.messages {
+            let category = match m.category {
+                FileMessageCategory::OutGoing => MessageCategory::OutGoing,
+                FileMessageCategory::Incoming => MessageCategory::Incoming,
+                FileMessageCategory::Service => MessageCategory::Service,
+                FileMessageCategory::Action => MessageCategory::Action,
+            };
+            let message_type = match m.message_type.as_str() {
+                "json" => MessageType::Json,
+                "json_flat" => MessageType::JsonFlat,
+                t => MessageType::Ros(t.to_string()),
+            };
+            mb.add_message(Message {
+                name: m.name.0,
+                topic: m.topic.0,
+                category,
+                message_type,
+                variables: message_variables_from_file(m.variables),
+                variables_response: message_variables_from_file(m.variables_response),
+                variables_feedback: message_variables_from_file(m.variables_feedback),
+                send_predicate: m.send_predicate.0,
+            });
+        }
+
+        Ok(mb)
+    }
+}
+
+impl ModelBuilder {
+    /// Read a model from text in the format of `ModelFile`.
+    pub fn from_text(text: &str, format: ModelFormat) -> Result<Self, ModelFileError> {
+        ModelFile::parse(text, format).and_then(ModelFile::into_model)
+    }
+
+    /// Load a model file, the format is given by the extension. The
+    /// model is not validated, see `validate`.
+    pub fn load(path: &Path) -> Result<Self, ModelFileError> {
+        let format = ModelFormat::from_path(path)
+            .ok_or_else(|| ModelFileError::new(format!("{}: unknown model file format", path.display())))?;
+        let text = std::fs::read_to_string(path)
+            .map_err(|e| ModelFileError::new(format!("{}: {}", path.display(), e)))?;
+        ModelBuilder::from_text(&text, format)
+    }
+
+    /// The model in the format of `ModelFile`.
+    pub fn export(&self, format: ModelFormat) -> Result<String, ModelFileError> {
+        ModelFile::from_model(self)?.write(format)
+    }
+
+    /// Write the model to a file, the format is given by the extension.
+    pub fn save(&self, path: &Path) -> Result<(), ModelFileError> {
+        let format = ModelFormat::from_path(path)
+            .ok_or_else(|| ModelFileError::new(format!("{}: unknown model file format", path.display())))?;
+        let text = self.export(format)?;
+        std::fs::write(path, text).map_err(|e| ModelFileError::new(format!("{}: {}", path.display(), e)))
+    }
+}
@@ -0,0 +1,184 @@
+use crate::*;
+use std::collections::HashSet;
+use std::fmt;
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Serialize, Deserialize)]
+pub enum Severity {
+    /// The model can not be run.
+    Error,
+    /// The model can be run but probably does not do what was intended.
+    Warning,
+}
+
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
+pub enum DiagnosticKind {
+    /// The variable is declared more than once.
+    DuplicateVariable,
+    /// The path is used but there is no such variable.
+    UnknownVariable,
+    InitialValueWrongType { value: SPValue, expected: SPValueType },
+    InitialValueNotInDomain { value: SPValue },
+    /// An action assigns a value of the wrong type.
+    ActionValueWrongType { value: SPValue, expected: SPValueType },
+    ActionValueNotInDomain { value: SPValue },
+    /// The variable has no initial value and no incoming message sets it.
+    NoInitialValue,
+}
+
+/// A problem found by `ModelBuilder::validate`.
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
+pub struct ModelDiagnostic {
+    pub severity: Severity,
+    pub kind: DiagnosticKind,
+    /// The offending path, e.g. the variable that does not exist.
+    pub path: SPPath,
+    /// Where the path is used, e.g. a transition or a message. Empty
+    /// for problems with the variable declarations.
+    pub context: SPPath,
+}
+
+impl ModelDiagnostic {
+    fn error(kind: DiagnosticKind, path: &SPPath, context: &SPPath) -> Self {
+        ModelDiagnostic {
+            severity: Severity::Error,
+            kind,
+            path: path.clone(),
+            context: context.clone(),
+        }
+    }
+
+    pub fn is_error(&self) -> bool {
+        self.severity == Severity::Error
+    }
+}
+
+impl fmt::Display for ModelDiagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let severity = match self.severity {
+            Severity::Error => "error",
+            Severity::Warning => "warning",
+        };
+        write!(f, "{}: {}: ", severity, self.path)?;
+        match &self.kind {
+            DiagnosticKind::DuplicateVariable => write!(f, "the variable is declared more than once")?,
+            DiagnosticKind::UnknownVariable => write!(f, "there is no such variable")?,
+            DiagnosticKind::InitialValueWrongType { value, expected } => {
+                write!(f, "the initial value {} is not of type {:?}", value, expected)?
+            }
+            DiagnosticKind::InitialValueNotInDomain { value } => {
+                write!(f, "the initial value {} is not in the domain", value)?
+            }
+            DiagnosticKind::ActionValueWrongType { value, expected } => {
+                write!(f, "assigned {} which is not of type {:?}", value, expected)?
+            }
+            DiagnosticKind::ActionValueNotInDomain { value } => {
+                write!(f, "assigned {} which is not in the domain", value)?
+            }
+            DiagnosticKind::NoInitialValue => {
+                write!(f, "the variable has no initial value and is not set by any message")?
+            }
+        }
+        if self.context != SPPath::new() {
+            write!(f, " (in {})", self.context)?;
+        }
+        Ok(())
+    }
+}
+
+impl ModelBuilder {
+    /// Check the model for problems that would otherwise only show up
+    /// when running it. Returns every problem found.
+    pub fn validate(&self) -> Vec<ModelDiagnostic> {
+        let mut diagnostics = vec![];
+        let no_context = SPPath::new();
+
+        let mut declared = HashSet::new();
+        for v in &self.variables {
+            if !declared.insert(v.path.clone()) {
+                diagnostics.push(ModelDiagnostic::error(
+                    DiagnosticKind::DuplicateVariable, &v.path, &no_context));
+            }
+            let value = &v.initial_state;
+            if value == &SPValue::Unknown {
+                continue;
+            }
+            if !value.is_type(v.value_type) {
+                diagnostics.push(ModelDiagnostic::error(
+                    DiagnosticKind::InitialValueWrongType { value: value.clone(), expected: v.value_type },
+                    &v.path, &no_context));
+            } else if !v.domain.is_empty() && !v.domain.contains(value) {
+                diagnostics.push(ModelDiagnostic::error(
+                    DiagnosticKind::InitialValueNotInDomain { value: value.clone() },
+                    &v.path, &no_context));
+            }
+        }
+
+        let variable = |path: &SPPath| self.variables.iter().find(|v| &v.path == path);
+        let check_paths = |paths: Vec<SPPath>, context: &SPPath, diagnostics: &mut Vec<ModelDiagnostic>| {
+            for p in paths {
+                if !declared.contains(&p) {
+                    diagnostics.push(ModelDiagnostic::error(
+                        DiagnosticKind::UnknownVariable, &p, context));
+                }
+            }
+        };
+
+        for mt in &self.transitions {
+            for (t, _) in &mt.transitions {
+                check_paths(t.guard.support(), &t.path, &mut diagnostics);
+                for a in &t.actions {
+                    let mut paths = vec![a.var.clone()];
+                    paths.extend(a.value.support());
+                    check_paths(paths, &t.path, &mut diagnostics);
+
+                    let (var, value) = match (variable(&a.var), &a.value) {
+                        (Some(var), Compute::PredicateValue(PredicateValue::SPValue(value))) => (var, value),
+                        _ => continue,
+                    };
+                    if !value.is_type(var.value_type) {
+                        diagnostics.push(ModelDiagnostic::error(
+                            DiagnosticKind::ActionValueWrongType { value: value.clone(), expected: var.value_type },
+                            &a.var, &t.path));
+                    } else if !var.domain.is_empty() && !var.domain.contains(value) {
+                        diagnostics.push(ModelDiagnostic::error(
+                            DiagnosticKind::ActionValueNotInDomain { value: value.clone() },
+                            &a.var, &t.path));
+                    }
+                }
+            }
+        }
+
+        for i in &self.invariants {
+            check_paths(i.predicate.support(), &i.path, &mut diagnostics);
+        }
+
+        let mut incoming = HashSet::new();
+        for m in &self.messages {
+            let vars = m.variables.iter()
+                .chain(m.variables_response.iter())
+                .chain(m.variables_feedback.iter());
+            for mv in vars {
+                check_paths(vec![mv.path.clone()], &m.name, &mut diagnostics);
+                if m.category != MessageCategory::OutGoing {
+                    incoming.insert(mv.path.clone());
+                }
+            }
+            check_paths(m.send_predicate.support(), &m.name, &mut diagnostics);
+        }
+
+        for v in &self.variables {
+            if v.initial_state == SPValue::Unknown
+                && v.value_type != SPValueType::Time
+                && !incoming.contains(&v.path) {
+                diagnostics.push(ModelDiagnostic {
+                    severity: Severity::Warning,
+                    kind: DiagnosticKind::NoInitialValue,
+                    path: v.path.clone(),
+                    context: no_context.clone(),
+                });
+            }
+        }
+
+        diagnostics
+    }
+}
@@ -0,0 +1,122 @@
+use sp_domain::*;
+use sp_model::*;
+
+const CELL: &str = r#"
+variables:
+  - { path: cell.door, type: string, domain: [open, closed], initial: closed }
+  - { path: cell.parts, type: int, range: [0, 10], initial: 0 }
+  - { path: cell.speed, type: float, initial: 0.5 }
+  - { path: cell.cmd, type: string, domain: [open, close], initial: close }
+invariants:
+  - { path: cell.safe, predicate: "!(p:cell.door == open && p:cell.parts > 5)" }
+transitions:
+  - path: cell.open
+    type: controlled
+    guard: p:cell.door == closed
+    actions: ["p:cell.door := open"]
+    cost: { cost: 1, duration: 500, energy: 0 }
+    parts:
+      - { path: cell.open_cmd, type: runner, actions: ["p:cell.cmd := open"] }
+  - { path: cell.add, type: auto, guard: "p:cell.parts < 10", actions: ["p:cell.parts := p:cell.parts + 1"] }
+operations:
+  - { path: cell.fill, pre: "p:cell.door == closed", goal: "p:cell.parts == 10", max_retries: 2 }
+messages:
+  - name: cell.door_state
+    topic: door/state
+    category: incoming
+    message_type: std_msgs/msg/String
+    variables: [{ path: cell.door, ros_path: data }]
+"#;
+
+#[test]
+fn load_yaml_model() {
+    let mb = ModelBuilder::from_text(CELL, ModelFormat::Yaml).unwrap();
+    let errors: Vec<_> = mb.validate().into_iter().filter(|d| d.is_error()).collect();
+    assert!(errors.is_empty(), "{:?}", errors);
+
+    let parts = mb.variables.iter().find(|v| v.path == SPPath::from("cell.parts")).unwrap();
+    assert_eq!(parts.range, Some((0, 10)));
+    assert_eq!(parts.domain.len(), 11);
+
+    let open = &mb.transitions[0].transitions;
+    assert_eq!(open.len(), 2);
+    assert_eq!(open[0].1, TransitionType::Controlled);
+    assert_eq!(open[0].0.cost.unwrap().duration, 500);
+    assert_eq!(open[1].0.path, SPPath::from("cell.open_cmd"));
+
+    // the operation brings its own variables and transitions.
+    assert_eq!(mb.operations[0].max_retries, 2);
+    assert!(mb.variables.iter().any(|v| v.path == SPPath::from("cell.fill")));
+    assert_eq!(mb.transitions.len(), 2 + mb.operations[0].transitions().len());
+
+    assert_eq!(mb.messages[0].message_type, MessageType::Ros("std_msgs/msg/String".into()));
+    assert_eq!(mb.messages[0].category, MessageCategory::Incoming);
+}
+
+#[test]
+fn export_and_load_again() {
+    let mb = ModelBuilder::from_text(CELL, ModelFormat::Yaml).unwrap();
+    for format in [ModelFormat::Yaml, ModelFormat::Toml, ModelFormat::Json] {
+        let text = mb.export(format).unwrap();
+        let loaded = ModelBuilder::from_text(&text, format)
+            .unwrap_or_else(|e| panic!("{:?}: {}\n{}", format, e, text));
+        assert_eq!(loaded, mb, "{:?}", format);
+    }
+}
+
+#[test]
+fn user_paths_below_operations_are_exported() {
+    let yaml = r#"
+variables:
+  - { path: cell.fill.counter, type: int, range: [0, 3], initial: 0 }
+transitions:
+  - { path: cell.fill.abort, type: controlled, guard: "p:cell.fill == executing", actions: ["p:cell.fill := error"] }
+operations:
+  - { path: cell.fill, pre: "p:cell.fill.counter < 3", goal: "p:cell.fill.counter == 3" }
+"#;
+    let mb = ModelBuilder::from_text(yaml, ModelFormat::Yaml).unwrap();
+    let text = mb.export(ModelFormat::Yaml).unwrap();
+    assert!(text.contains("cell.fill.abort"), "{}", text);
+    assert!(text.contains("cell.fill.counter"), "{}", text);
+    assert_eq!(ModelBuilder::from_text(&text, ModelFormat::Yaml).unwrap(), mb);
+}
+
+#[test]
+fn errors_point_at_the_line() {
+    let yaml = "variables:\n  - { path: x, type: bool }\ntransitions:\n  - { path: t, type: controlled, guard: \"p:x ==\" }\n";
+    let e = ModelBuilder::from_text(yaml, ModelFormat::Yaml).unwrap_err();
+    assert_eq!(e.line, Some(4), "{}", e);
+    assert!(e.message.contains("invalid predicate"), "{}", e);
+
+    let toml = "[[variables]]\npath = \"x\"\ntype = \"bool\"\n\n[[transitions]]\npath = \"t\"\ntype = \"controlled\"\nactions = [\"p:x = true\"]\n";
+    let e = ModelBuilder::from_text(toml, ModelFormat::Toml).unwrap_err();
+    assert_eq!(e.line, Some(8), "{}", e);
+    assert!(e.message.contains("invalid action"), "{}", e);
+
+    let json = "{\n  \"variables\": [\n    { \"path\": \"x\", \"typ\": \"bool\" }\n  ]\n}";
+    let e = ModelBuilder::from_text(json, ModelFormat::Json).unwrap_err();
+    assert_eq!(e.line, Some(3), "{}", e);
+    assert!(e.message.contains("typ"), "{}", e);
+}
+
+#[test]
+fn ranges_are_only_for_ints() {
+    let yaml = "variables:\n  - { path: x, type: int, range: [0, 3], domain: [0, 1] }\n";
+    let e = ModelBuilder::from_text(yaml, ModelFormat::Yaml).unwrap_err();
+    assert!(e.message.starts_with("x: a range can not be combined with a domain"), "{}", e);
+
+    let yaml = "variables:\n  - { path: x, type: bool, range: [0, 3] }\n";
+    let e = ModelBuilder::from_text(yaml, ModelFormat::Yaml).unwrap_err();
+    assert!(e.message.starts_with("x: only int variables"), "{}", e);
+}
+
+#[test]
+fn export_fails_for_timers() {
+    let mut mb = ModelBuilder::from_text(CELL, ModelFormat::Yaml).unwrap();
+    let timer = Predicate::TON(
+        PredicateValue::SPPath("cell.started".into(), None),
+        1000.to_predicate_value());
+    mb.add_invariant("cell.slow".into(), timer);
+    let e = mb.export(ModelFormat::Yaml).unwrap_err();
+    assert!(e.message.starts_with("cell.slow"), "{}", e);
+}
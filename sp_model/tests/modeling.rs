@@ -137,3 +137,181 @@ fn empty_model_with_operation() {
     assert!(result.is_ok());
     assert!(result.unwrap().plan_found);
 }
+
+#[test]
+fn model_transitions_run_atomically() {
+    #[derive(Resource)]
+    struct Model {
+        #[Variable(type = "bool", initial = false)]
+        ready: Variable,
+        #[Variable(type = "bool", initial = false)]
+        started: Variable,
+    }
+
+    let m = Model::new("m");
+    let ready = m.ready.path.clone();
+    let started = m.started.path.clone();
+    let mut mb = ModelBuilder::from(&m);
+    let op = mb.add_operation("op".into(),
+                              Predicate::TRUE, vec![],
+                              p!(ready), vec![a!(started)],
+                              Predicate::TRUE, vec![],
+                              Predicate::TRUE, vec![]);
+
+    let (controlled, uncontrolled) = get_runner_transitions(&mb.transitions);
+    // start, reset and recover, and finish
+    assert_eq!(controlled.len(), 3);
+    assert_eq!(uncontrolled.len(), 1);
+
+    // the merged transition is named after the formal part, which the plans use.
+    let start = &controlled[0];
+    assert_eq!(start.path, op.add_child("formal_start"));
+
+    // it is enabled only when the runner part is.
+    let mut state = mb.get_initial_state();
+    assert!(!start.eval(&state));
+    state.add_variable(ready, true.to_spvalue());
+    assert!(start.eval(&state));
+
+    // and it takes the actions of both parts.
+    start.next(&mut state).unwrap();
+    state.take_transition();
+    assert!(p!([op == "e"] && [started]).eval(&state));
+}
+
+#[test]
+fn operation_lifecycle() {
+    #[derive(Resource)]
+    struct Model {
+        #[Variable(type = "bool", initial = false)]
+        done: Variable,
+    }
+
+    let m = Model::new("m");
+    let done = m.done.path.clone();
+    let mut mb = ModelBuilder::from(&m);
+    let op = Operation::new("op".into(), Predicate::TRUE, p!(done))
+        .with_max_duration(0)
+        .with_retries(1);
+    mb.insert_operation(op.clone());
+
+    // the planner sees start, finish, reset, retry and recover, but not the timeout.
+    let tsm = mb.make_tsm();
+    let formal: Vec<_> = tsm.transitions.iter().map(|t| t.path.leaf()).collect();
+    assert_eq!(formal, vec!["formal_start", "formal_finish", "reset", "retry", "recover"]);
+    assert!(tsm.uncontrollable.contains(&op.path.add_child("retry")));
+
+    let (controlled, uncontrolled) = get_runner_transitions(&mb.transitions);
+    let mut state = mb.get_initial_state();
+    let fire = |state: &mut SPState, ts: &[Transition], name: &str| {
+        let t = ts.iter().find(|t| t.path.leaf() == name).unwrap();
+        assert!(t.eval(state), "{} is not enabled", name);
+        t.next(state).unwrap();
+        state.take_transition();
+    };
+
+    // times out immediately, and is retried once.
+    fire(&mut state, &controlled, "formal_start");
+    assert!(op.is_executing(&state));
+    std::thread::sleep(std::time::Duration::from_millis(2));
+    fire(&mut state, &uncontrolled, "timeout");
+    assert!(op.is_timeout(&state));
+    fire(&mut state, &uncontrolled, "retry");
+    assert_eq!(state.sp_value_from_path(&op.retries()), Some(&1.to_spvalue()));
+
+    fire(&mut state, &controlled, "formal_start");
+    std::thread::sleep(std::time::Duration::from_millis(2));
+    fire(&mut state, &uncontrolled, "timeout");
+    let retry = uncontrolled.iter().find(|t| t.path.leaf() == "retry").unwrap();
+    assert!(!retry.eval(&state));
+
+    // out of retries, it has to be recovered explicitly.
+    fire(&mut state, &controlled, "recover");
+    assert_eq!(state.sp_value_from_path(&op.path), Some(&"i".to_spvalue()));
+    assert_eq!(state.sp_value_from_path(&op.retries()), Some(&0.to_spvalue()));
+
+    // a finished operation can be reset by the planner.
+    state.add_variable(op.path.clone(), op.states.executing.to_spvalue());
+    state.add_variable(done, true.to_spvalue());
+    fire(&mut state, &uncontrolled, "formal_finish");
+    assert!(op.is_finished(&state));
+    assert_eq!(state.sp_value_from_path(&op.retries()), Some(&0.to_spvalue()));
+    fire(&mut state, &controlled, "reset");
+    assert_eq!(state.sp_value_from_path(&op.path), Some(&"i".to_spvalue()));
+}
+
+#[test]
+fn operation_recovers_without_retries() {
+    let mut mb = ModelBuilder::from(&Model::new("m"));
+    let op = Operation::new("op".into(), Predicate::TRUE, Predicate::FALSE);
+    mb.insert_operation(op.clone());
+
+    let (controlled, _) = get_runner_transitions(&mb.transitions);
+    let recover = controlled.iter().find(|t| t.path.leaf() == "recover").unwrap();
+    let mut state = mb.get_initial_state();
+    assert!(!recover.eval(&state));
+
+    for failed in [&op.states.error, &op.states.timeout] {
+        state.add_variable(op.path.clone(), failed.to_spvalue());
+        assert!(recover.eval(&state));
+        recover.next(&mut state).unwrap();
+        state.take_transition();
+        assert_eq!(state.sp_value_from_path(&op.path), Some(&"i".to_spvalue()));
+    }
+}
+
+#[test]
+fn model_validation() {
+    #[derive(Resource)]
+    struct Model {
+        #[Variable(type = "int", initial = 7, domain = "1 2 3")]
+        x: Variable,
+        #[Variable(type = "bool")]
+        flag: Variable,
+    }
+
+    let m = Model::new("m");
+    let x = m.x.path.clone();
+    let flag = m.flag.path.clone();
+    let missing = SPPath::from("m.missing");
+    let mut mb = ModelBuilder::from(&m);
+    let op = mb.insert_operation(Operation::new("op".into(), p!(missing), p!(x == 2)));
+    let set_x = Transition::new("set_x".into(), Predicate::TRUE, vec![a!(x = "two"), a!(flag)]);
+    mb.transitions.push(ModelTransition { transitions: vec![(set_x, TransitionType::Controlled)] });
+    mb.add_message(Message {
+        name: "msg".into(),
+        variables: vec![MessageVariable { ros_path: "data".into(), path: "m.gone".into() }],
+        ..Message::default()
+    });
+
+    let diagnostics = mb.validate();
+    let errors: Vec<_> = diagnostics.iter().filter(|d| d.is_error())
+        .map(|d| (d.kind.clone(), d.path.to_string(), d.context.to_string()))
+        .collect();
+    assert_eq!(errors, vec![
+        (DiagnosticKind::InitialValueNotInDomain { value: 7.to_spvalue() }, "m.x".into(), "".into()),
+        (DiagnosticKind::UnknownVariable, "m.missing".into(), op.add_child("formal_start").to_string()),
+        (DiagnosticKind::ActionValueWrongType { value: "two".to_spvalue(), expected: SPValueType::Int32 },
+         "m.x".into(), "set_x".into()),
+        (DiagnosticKind::UnknownVariable, "m.gone".into(), "msg".into()),
+    ]);
+
+    // flag is only set by a transition, so nothing gives it a value.
+    let warnings: Vec<_> = diagnostics.iter().filter(|d| !d.is_error()).map(|d| &d.path).collect();
+    assert_eq!(warnings, vec![&flag]);
+    assert_eq!(diagnostics[2].to_string(),
+               "error: m.x: assigned two which is not of type Int32 (in set_x)");
+}
+
+#[test]
+fn models_saved_before_invariants_and_operations_still_load() {
+    let mb: ModelBuilder =
+        serde_json::from_str(r#"{"variables": [], "transitions": [], "messages": []}"#).unwrap();
+    assert!(mb.invariants.is_empty());
+
+    let tsm: TransitionSystemModel = serde_json::from_str(r#"{
+        "name": "old", "vars": [], "state_predicates": [], "transitions": [], "invariants": [],
+        "state_id": "00000000-0000-0000-0000-000000000000"
+    }"#).unwrap();
+    assert!(tsm.uncontrollable.is_empty());
+}
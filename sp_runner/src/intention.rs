@@ -0,0 +1,205 @@
+use serde::{Deserialize, Serialize};
+use sp_domain::*;
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Serialize, Deserialize)]
+pub enum IntentionState {
+    /// Waiting for intentions with higher priority.
+    Requested,
+    /// The planner works towards the goal.
+    Active,
+    Completed,
+    /// The invariant was broken, or the goal can not be reached.
+    Failed,
+    Cancelled,
+}
+
+impl IntentionState {
+    /// The value of the state variable of the intention.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            IntentionState::Requested => "requested",
+            IntentionState::Active => "active",
+            IntentionState::Completed => "completed",
+            IntentionState::Failed => "failed",
+            IntentionState::Cancelled => "cancelled",
+        }
+    }
+
+    pub fn is_finished(&self) -> bool {
+        matches!(self, IntentionState::Completed | IntentionState::Failed | IntentionState::Cancelled)
+    }
+}
+
+/// Something the runner should achieve. The state of the intention is
+/// exposed in the state variable `path`.
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
+pub struct Intention {
+    pub path: SPPath,
+    pub goal: Predicate,
+    /// Must hold until the goal is reached.
+    pub invariant: Option<Predicate>,
+    /// Only the intentions with the highest priority are active.
+    pub priority: i32,
+    pub state: IntentionState,
+}
+
+impl Intention {
+    pub fn new(path: SPPath, goal: Predicate) -> Self {
+        Intention {
+            path,
+            goal,
+            invariant: None,
+            priority: 0,
+            state: IntentionState::Requested,
+        }
+    }
+
+    pub fn with_invariant(mut self, invariant: Predicate) -> Self {
+        self.invariant = Some(invariant);
+        self
+    }
+
+    pub fn with_priority(mut self, priority: i32) -> Self {
+        self.priority = priority;
+        self
+    }
+}
+
+/// The intentions of a runner. Requested intentions become active when
+/// no unfinished intention has a higher priority, active intentions go
+/// back to requested when one with a higher priority is added.
+#[derive(Debug, PartialEq, Clone, Default, Serialize, Deserialize)]
+pub struct Intentions {
+    intentions: Vec<Intention>,
+}
+
+impl Intentions {
+    pub fn new(intentions: Vec<Intention>) -> Self {
+        let mut i = Intentions::default();
+        intentions.into_iter().for_each(|x| i.add(x));
+        i
+    }
+
+    /// Add an intention, replacing any intention with the same path.
+    pub fn add(&mut self, mut intention: Intention) {
+        intention.state = IntentionState::Requested;
+        self.intentions.retain(|i| i.path != intention.path);
+        self.intentions.push(intention);
+    }
+
+    pub fn cancel(&mut self, path: &SPPath) {
+        self.set_state(path, IntentionState::Cancelled);
+    }
+
+    /// Mark an intention as failed, e.g. when its goal can not be reached.
+    pub fn fail(&mut self, path: &SPPath) {
+        self.set_state(path, IntentionState::Failed);
+    }
+
+    fn set_state(&mut self, path: &SPPath, state: IntentionState) {
+        if let Some(i) = self.intentions.iter_mut().find(|i| &i.path == path) {
+            if !i.state.is_finished() {
+                i.state = state;
+            }
+        }
+    }
+
+    pub fn list(&self) -> &[Intention] {
+        &self.intentions
+    }
+
+    pub fn active(&self) -> impl Iterator<Item = &Intention> {
+        self.intentions.iter().filter(|i| i.state == IntentionState::Active)
+    }
+
+    /// The goals and invariants of the active intentions.
+    pub fn goals(&self) -> Vec<(Predicate, Option<Predicate>)> {
+        self.active().map(|i| (i.goal.clone(), i.invariant.clone())).collect()
+    }
+
+    /// Complete the active intentions whose goals hold in `state`, fail
+    /// those whose invariants do not, and activate the intentions with
+    /// the highest priority.
+    pub fn update(&mut self, state: &SPState) {
+        for i in self.intentions.iter_mut().filter(|i| i.state == IntentionState::Active) {
+            if i.goal.eval(state) {
+                i.state = IntentionState::Completed;
+            } else if i.invariant.as_ref().map(|inv| !inv.eval(state)).unwrap_or(false) {
+                i.state = IntentionState::Failed;
+            }
+        }
+
+        let top = self.intentions.iter()
+            .filter(|i| !i.state.is_finished())
+            .map(|i| i.priority)
+            .max();
+        for i in self.intentions.iter_mut().filter(|i| !i.state.is_finished()) {
+            i.state = if Some(i.priority) == top {
+                IntentionState::Active
+            } else {
+                IntentionState::Requested
+            };
+        }
+    }
+
+    /// The state variables of the intentions.
+    pub fn state(&self) -> SPState {
+        let mut state = SPState::new();
+        for i in &self.intentions {
+            state.add_variable(i.path.clone(), i.state.as_str().to_spvalue());
+        }
+        state
+    }
+}
+
+#[cfg(test)]
+mod intention_tests {
+    use super::*;
+
+    fn states(intentions: &Intentions) -> Vec<IntentionState> {
+        intentions.list().iter().map(|i| i.state).collect()
+    }
+
+    #[test]
+    fn intention_lifecycle() {
+        let x = SPPath::from("x");
+        let ok = SPPath::from("ok");
+        let mut intentions = Intentions::new(vec![
+            Intention::new("a".into(), p!(x == 1)),
+            Intention::new("b".into(), p!(x == 2)).with_invariant(p!(ok)),
+        ]);
+        let mut state = state!(x => 0, ok => true);
+        assert_eq!(states(&intentions), vec![IntentionState::Requested; 2]);
+
+        intentions.update(&state);
+        assert_eq!(states(&intentions), vec![IntentionState::Active; 2]);
+        assert_eq!(intentions.goals().len(), 2);
+
+        // a higher priority intention takes over
+        intentions.add(Intention::new("c".into(), p!(x == 3)).with_priority(1));
+        intentions.update(&state);
+        assert_eq!(
+            states(&intentions),
+            vec![IntentionState::Requested, IntentionState::Requested, IntentionState::Active]
+        );
+        intentions.cancel(&"c".into());
+        intentions.update(&state);
+        assert_eq!(
+            states(&intentions),
+            vec![IntentionState::Active, IntentionState::Active, IntentionState::Cancelled]
+        );
+
+        state.add_variable(x, 1.to_spvalue());
+        state.add_variable(ok, false.to_spvalue());
+        intentions.update(&state);
+        assert_eq!(
+            states(&intentions),
+            vec![IntentionState::Completed, IntentionState::Failed, IntentionState::Cancelled]
+        );
+        assert!(intentions.goals().is_empty());
+
+        let vars = intentions.state();
+        assert_eq!(vars.sp_value_from_path(&"a".into()), Some(&"completed".to_spvalue()));
+        assert_eq!(vars.sp_value_from_path(&"b".into()), Some(&"failed".to_spvalue()));
+    }
+}
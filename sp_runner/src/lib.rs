@@ -1,9 +1,18 @@
+mod plan;
+pub use plan::*;
+
+mod intention;
+pub use intention::*;
+
 mod ticker;
 pub use ticker::*;
 
 mod transition_planner;
 pub use transition_planner::*;
 
+mod operation_planner;
+pub use operation_planner::*;
+
 mod runner;
 pub use runner::*;
 
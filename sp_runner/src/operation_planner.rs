@@ -0,0 +1,369 @@
+use sp_domain::*;
+use sp_formal::*;
+use sp_model::*;
+use sp_ros::*;
+use super::*;
+use std::collections::HashSet;
+
+// some planning constants
+pub const LVL1_MAX_STEPS: u32 = 20;
+
+/// A new plan of operations for the runner.
+#[derive(Debug, PartialEq, Clone)]
+pub struct OperationPlan {
+    /// The operations to start, reset or recover, in order. These are the
+    /// paths of the controlled operation transitions.
+    pub plan: Vec<SPPath>,
+    /// False if the planner found that the goals can not be reached,
+    /// planner errors are retried instead.
+    pub plan_found: bool,
+}
+
+/// The upper planning level. Chooses which operations to run to reach
+/// high level goals, the transition planner then plans for the goals of
+/// the operations that are executing.
+///
+/// The planning model only contains the operations and the variables
+/// they depend on. Each operation is a single step that takes it from
+/// init to finished and makes its goal true. Goals of the form `x == v`
+/// become assignments, any other variable of the goal may take any
+/// value.
+#[derive(Debug, Clone)]
+pub struct OperationPlanner {
+    pub plan: Vec<SPPath>, // current plan
+    pub model: TransitionSystemModel, // planning model
+    pub operations: Vec<Operation>,
+    pub prev_state: SPState, // to check if something relevant for this planner has changed
+    pub prev_goals: Vec<(Predicate, Option<Predicate>)>, // previous goals
+    pub store: PlanCache, // cache
+    pub planner: Box<dyn Planner>, // planning backend
+    pub max_steps: u32, // planning horizon
+}
+
+/// Assignments that make `goal` true, as far as they can be derived.
+fn goal_effects(goal: &Predicate) -> Vec<Action> {
+    let assign = |p: &SPPath, v: &SPValue| {
+        Action::new(p.clone(), Compute::PredicateValue(PredicateValue::SPValue(v.clone())))
+    };
+    let effects = match goal {
+        Predicate::TRUE => vec![],
+        Predicate::AND(xs) => xs.iter().flat_map(goal_effects).collect(),
+        Predicate::EQ(PredicateValue::SPPath(p, _), PredicateValue::SPValue(v))
+        | Predicate::EQ(PredicateValue::SPValue(v), PredicateValue::SPPath(p, _)) => {
+            vec![assign(p, v)]
+        }
+        Predicate::NOT(x) => match x.as_ref() {
+            Predicate::EQ(PredicateValue::SPPath(p, _), PredicateValue::SPValue(SPValue::Bool(b))) => {
+                vec![assign(p, &(!b).to_spvalue())]
+            }
+            _ => vec![],
+        },
+        _ => vec![],
+    };
+
+    // anything that could not be turned into an assignment
+    let assigned: HashSet<SPPath> = effects.iter().map(|a| a.var.clone()).collect();
+    let mut rest: Vec<SPPath> = goal.support().into_iter().filter(|p| !assigned.contains(p)).collect();
+    rest.sort();
+    rest.dedup();
+    effects.into_iter()
+        .chain(rest.into_iter().map(|p| Action::new(p, Compute::Any)))
+        .collect()
+}
+
+/// The planning model of the operation level.
+pub fn operation_model(model: &TransitionSystemModel, operations: &[Operation]) -> TransitionSystemModel {
+    let mut transitions = vec![];
+    for op in operations {
+        let s = &op.states;
+        let in_state = |v: &str| Predicate::EQ(
+            PredicateValue::SPPath(op.path.clone(), None),
+            PredicateValue::SPValue(v.to_spvalue()));
+        let set_state = |v: &str| Action::new(
+            op.path.clone(),
+            Compute::PredicateValue(PredicateValue::SPValue(v.to_spvalue())));
+
+        let mut actions = op.pre_actions.clone();
+        actions.extend(op.goal_actions.clone());
+        let written: HashSet<SPPath> = actions.iter().map(|a| a.var.clone()).collect();
+        actions.extend(goal_effects(&op.goal).into_iter().filter(|a| !written.contains(&a.var)));
+        actions.push(set_state(&s.finished));
+        transitions.push(Transition::new(
+            op.path.add_child("formal_start"),
+            Predicate::AND(vec![in_state(&s.init), op.pre.clone()]),
+            actions));
+        transitions.push(Transition::new(
+            op.path.add_child("reset"),
+            in_state(&s.finished),
+            vec![set_state(&s.init)]));
+        // failed operations are only brought back to init on request.
+        let recover = op.path.add_child("recover");
+        transitions.extend(op.transitions().into_iter()
+            .flat_map(|mt| mt.transitions)
+            .map(|(t, _)| t)
+            .filter(|t| t.path == recover));
+    }
+
+    let state_predicates: Vec<NamedPredicate> = model.state_predicates.iter()
+        .filter(|p| transitions.iter().any(|t| t.guard.support().contains(&p.path)))
+        .cloned()
+        .collect();
+    let mut used: HashSet<SPPath> = HashSet::new();
+    for t in &transitions {
+        used.extend(t.guard.support());
+        used.extend(t.actions.iter().map(|a| a.var.clone()));
+    }
+    for p in &state_predicates {
+        used.extend(p.predicate.support());
+    }
+    let vars: Vec<Variable> = model.vars.iter().filter(|v| used.contains(&v.path)).cloned().collect();
+    let invariants = model.invariants.iter()
+        .filter(|i| i.predicate.support().iter().all(|p| used.contains(p)))
+        .cloned()
+        .collect();
+
+    TransitionSystemModel {
+        name: format!("{}_operations", model.name),
+        vars,
+        state_predicates,
+        transitions,
+        invariants,
+        ..TransitionSystemModel::default()
+    }
+}
+
+impl OperationPlanner {
+    pub fn from(model: &RunnerModel) -> Self {
+        OperationPlanner {
+            plan: vec![],
+            model: operation_model(&model.tsm, &model.operations),
+            operations: model.operations.clone(),
+            prev_state: SPState::new(),
+            prev_goals: vec![],
+            store: PlanCache::default(),
+            planner: model.planner.create(),
+            max_steps: model.config.operation_horizon,
+        }
+    }
+
+    /// Only keep parts of the state that are relevant to this planner.
+    pub fn filter_state(&self, state: &SPState) -> SPState {
+        let paths: Vec<_> = self.model.vars.iter().map(|v| v.path.clone()).collect();
+        state.filter_by_paths(&paths)
+    }
+
+    /// The operation part of the state, it only changes when
+    /// operations start, finish or fail.
+    fn operation_state(&self, state: &SPState) -> SPState {
+        let paths: Vec<_> = self.operations.iter().map(|o| o.path.clone()).collect();
+        state.filter_by_paths(&paths)
+    }
+
+    async fn plan_with_cache(
+        &mut self,
+        goals: &[(Predicate, Option<Predicate>)],
+        state: &SPState,
+        cancel: &CancellationToken,
+        progress: Option<&ProgressSender>) -> Result<PlanningResult, PlanningError> {
+        let key = PlanCache::key(
+            &self.model, goals, state, &[], self.max_steps, self.planner.as_ref());
+        if let Some(result) = self.store.get(&key) {
+            return Ok(result);
+        }
+        let result = self.planner
+            .plan_async(&self.model, goals, state, self.max_steps, cancel, progress)
+            .await?;
+        // no plan within the horizon is not a result worth keeping.
+        if result.plan_found {
+            if let Err(e) = self.store.insert(&key, &result) {
+                log_warn!("could not cache plan: {}", e);
+            }
+        }
+        Ok(result)
+    }
+
+    /// Compute a new plan towards `goals` if they have changed, or if
+    /// no operation is executing anymore, or if one of them has failed.
+    /// Returns `None` if the current plan can be kept.
+    pub async fn compute_new_plan(
+        &mut self,
+        state: SPState,
+        goals: &[(Predicate, Option<Predicate>)],
+        cancel: &CancellationToken,
+        progress: Option<&ProgressSender>) -> Option<OperationPlan> {
+        // goals may depend on variables that no operation changes.
+        let goals_reached = goals.iter().all(|(g, _)| g.eval(&state));
+        let state = self.filter_state(&state);
+        if state == self.prev_state && goals == self.prev_goals.as_slice() {
+            return None;
+        }
+        let operations_changed = self.operation_state(&state) != self.operation_state(&self.prev_state);
+        self.prev_state = state.clone();
+
+        if goals_reached {
+            self.prev_goals = goals.to_vec();
+            if self.plan.is_empty() {
+                return None;
+            }
+            self.plan.clear();
+            return Some(OperationPlan { plan: vec![], plan_found: true });
+        }
+
+        let failed = self.operations.iter().any(|o| o.is_error(&state) || o.is_timeout(&state));
+        let idle = !self.operations.iter().any(|o| o.is_executing(&state));
+        if goals == self.prev_goals.as_slice() && !(operations_changed && (failed || idle)) {
+            return None;
+        }
+        if goals != self.prev_goals.as_slice() {
+            let pg = Predicate::AND(goals.iter().map(|(c,_)| c.clone()).collect());
+            log_info!("operation planner replanning because goal changed. new goal: {}", pg);
+        }
+
+        let result = self.plan_with_cache(goals, &state, cancel, progress).await;
+        let result = match result {
+            Ok(result) if result.plan_found => result,
+            Err(PlanningError::Cancelled) => {
+                // try again next time
+                self.prev_state = SPState::new();
+                return None;
+            }
+            Err(e) => {
+                // the intentions may still be reachable, try again next time
+                log_warn!("planner error: {}", e);
+                self.prev_state = SPState::new();
+                return None;
+            }
+            Ok(_) => {
+                log_warn!("No plan was found for operation planner!");
+                self.prev_goals = goals.to_vec();
+                self.plan.clear();
+                return Some(OperationPlan { plan: vec![], plan_found: false });
+            }
+        };
+
+        log_info!(
+            "New plan was found for operation planner! time to solve {}ms",
+            result.time_to_solve.as_millis()
+        );
+        self.prev_goals = goals.to_vec();
+        self.plan = result
+            .trace
+            .iter()
+            .filter(|f| f.transition != SPPath::default())
+            .map(|f| f.transition.clone())
+            .collect();
+
+        Some(OperationPlan { plan: self.plan.clone(), plan_found: true })
+    }
+}
+
+#[cfg(test)]
+mod test_operation_planner {
+    use super::*;
+
+    #[derive(Resource)]
+    struct Model {
+        #[Variable(type = "bool", initial = false)]
+        picked: Variable,
+        #[Variable(type = "bool", initial = false)]
+        placed: Variable,
+    }
+
+    fn runner_model() -> (RunnerModel, SPPath, SPPath, SPPath) {
+        let m = Model::new("m");
+        let picked = m.picked.path.clone();
+        let placed = m.placed.path.clone();
+        let mut mb = ModelBuilder::from(&m);
+        let pick = mb.insert_operation(Operation::new("pick".into(), p!(!picked), p!(picked)));
+        let place = mb.insert_operation(Operation::new("place".into(), p!(picked), p!(placed)));
+        let mut rm = RunnerModel::from(mb);
+        rm.planner = PlannerBackend::Explicit(PlanObjective::Steps);
+        (rm, pick, place, placed)
+    }
+
+    #[test]
+    fn operations_are_single_steps() {
+        let (rm, pick, _, _) = runner_model();
+        let model = operation_model(&rm.tsm, &rm.operations);
+        let names: Vec<_> = model.transitions.iter().map(|t| t.path.to_string()).collect();
+        assert_eq!(names, vec!["pick.formal_start", "pick.reset", "pick.recover",
+                               "place.formal_start", "place.reset", "place.recover"]);
+
+        let start = &model.transitions[0];
+        assert_eq!(start.path, pick.add_child("formal_start"));
+        let written: Vec<_> = start.actions.iter().map(|a| a.var.to_string()).collect();
+        assert_eq!(written, vec!["m.picked", "pick"]);
+    }
+
+    #[tokio::test]
+    async fn plans_operations_towards_goals() {
+        let (rm, pick, place, placed) = runner_model();
+        let mut op = OperationPlanner::from(&rm);
+        let cancel = CancellationToken::new();
+        let goals = vec![(p!(placed), None)];
+
+        let mut state = rm.initial_state.clone();
+        let plan = op.compute_new_plan(state.clone(), &goals, &cancel, None).await.unwrap();
+        assert_eq!(plan.plan, vec![pick.add_child("formal_start"), place.add_child("formal_start")]);
+
+        // no replanning while an operation executes
+        state.add_variable(pick.clone(), "e".to_spvalue());
+        assert_eq!(op.compute_new_plan(state.clone(), &goals, &cancel, None).await, None);
+
+        // the goal has been reached
+        state.add_variable(placed, true.to_spvalue());
+        let plan = op.compute_new_plan(state, &goals, &cancel, None).await.unwrap();
+        assert!(plan.plan.is_empty());
+    }
+
+    #[tokio::test]
+    async fn planner_errors_are_not_missing_plans() {
+        let (mut rm, _, _, placed) = runner_model();
+        rm.planner = PlannerBackend::NuXmv(NuXmvConfig {
+            binary: Some("/nonexistent/nuXmv".into()),
+            ..NuXmvConfig::default()
+        });
+        let mut op = OperationPlanner::from(&rm);
+        let cancel = CancellationToken::new();
+        let goals = vec![(p!(placed), None)];
+
+        let state = rm.initial_state.clone();
+        assert_eq!(op.compute_new_plan(state.clone(), &goals, &cancel, None).await, None);
+        // tried again even though nothing has changed.
+        assert_eq!(op.compute_new_plan(state, &goals, &cancel, None).await, None);
+        assert_eq!(op.prev_state, SPState::new());
+    }
+
+    #[tokio::test]
+    async fn failed_operations_are_recovered() {
+        let (rm, pick, place, placed) = runner_model();
+        let mut op = OperationPlanner::from(&rm);
+        let cancel = CancellationToken::new();
+        let goals = vec![(p!(placed), None)];
+
+        // pick has no retries, so it has to be recovered before it is started again.
+        let mut state = rm.initial_state.clone();
+        state.add_variable(pick.clone(), "error".to_spvalue());
+        let plan = op.compute_new_plan(state.clone(), &goals, &cancel, None).await.unwrap();
+        assert!(plan.plan_found);
+        assert_eq!(plan.plan, vec![pick.add_child("recover"),
+                                   pick.add_child("formal_start"),
+                                   place.add_child("formal_start")]);
+
+        // which the runner can execute.
+        let (controlled_transitions, uncontrolled_transitions) =
+            get_runner_transitions(&rm.transitions);
+        let mut ticker = Ticker {
+            state,
+            controlled_transitions,
+            uncontrolled_transitions,
+            operation_plan: Plan::new(plan.plan),
+            .. Ticker::default()
+        };
+        ticker.update_state_paths();
+        assert_eq!(ticker.tick_operation_plan(), Some(pick.add_child("recover")));
+        ticker.state.take_transition();
+        assert_eq!(ticker.state.sp_value_from_path(&pick), Some(&"i".to_spvalue()));
+        assert_eq!(ticker.tick_operation_plan(), Some(pick.add_child("formal_start")));
+    }
+}
@@ -0,0 +1,169 @@
+use serde::{Deserialize, Serialize};
+use sp_domain::*;
+use std::fmt;
+use std::time::SystemTime;
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Serialize, Deserialize)]
+pub enum StepState {
+    /// Waiting for the earlier steps.
+    Pending,
+    /// Next in line, waiting for its guard to become true.
+    Running,
+    Done,
+    /// The transition could not be taken, the plan is stopped.
+    Failed,
+}
+
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
+pub struct PlanEntry {
+    pub transition: SPPath,
+    pub state: StepState,
+    /// When the step started running.
+    pub started: Option<SystemTime>,
+    /// When the step was done or failed.
+    pub finished: Option<SystemTime>,
+}
+
+/// A sequential plan executed by the runner. The cursor points at the
+/// step that should be taken next, all steps before it are done.
+#[derive(Debug, PartialEq, Clone, Default, Serialize, Deserialize)]
+pub struct Plan {
+    steps: Vec<PlanEntry>,
+    cursor: usize,
+}
+
+impl Plan {
+    pub fn new(transitions: Vec<SPPath>) -> Self {
+        let steps = transitions
+            .into_iter()
+            .map(|transition| PlanEntry {
+                transition,
+                state: StepState::Pending,
+                started: None,
+                finished: None,
+            })
+            .collect();
+        let mut plan = Plan { steps, cursor: 0 };
+        plan.start_current();
+        plan
+    }
+
+    pub fn steps(&self) -> &[PlanEntry] {
+        &self.steps
+    }
+
+    pub fn cursor(&self) -> usize {
+        self.cursor
+    }
+
+    /// The transition to take next, if the plan is neither done nor failed.
+    pub fn current(&self) -> Option<&SPPath> {
+        self.steps
+            .get(self.cursor)
+            .filter(|s| s.state == StepState::Running)
+            .map(|s| &s.transition)
+    }
+
+    /// The steps that have been executed, in order.
+    pub fn executed(&self) -> &[PlanEntry] {
+        &self.steps[..self.cursor]
+    }
+
+    /// The steps left to execute, including the current one.
+    pub fn remaining(&self) -> &[PlanEntry] {
+        &self.steps[self.cursor..]
+    }
+
+    pub fn is_done(&self) -> bool {
+        self.cursor == self.steps.len()
+    }
+
+    pub fn is_failed(&self) -> bool {
+        self.steps.iter().any(|s| s.state == StepState::Failed)
+    }
+
+    /// Mark the current step as done and start the next one.
+    pub fn advance(&mut self) {
+        if self.current().is_some() {
+            let step = &mut self.steps[self.cursor];
+            step.state = StepState::Done;
+            step.finished = Some(SystemTime::now());
+            self.cursor += 1;
+            self.start_current();
+        }
+    }
+
+    /// Mark the current step as failed, which stops the plan.
+    pub fn fail(&mut self) {
+        if self.current().is_some() {
+            let step = &mut self.steps[self.cursor];
+            step.state = StepState::Failed;
+            step.finished = Some(SystemTime::now());
+        }
+    }
+
+    fn start_current(&mut self) {
+        if let Some(step) = self.steps.get_mut(self.cursor) {
+            step.state = StepState::Running;
+            step.started = Some(SystemTime::now());
+        }
+    }
+}
+
+impl fmt::Display for Plan {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let done = self
+            .steps
+            .iter()
+            .filter(|s| s.state == StepState::Done)
+            .count();
+        write!(f, "plan {}/{}:", done, self.steps.len())?;
+        for s in &self.steps {
+            let mark = match s.state {
+                StepState::Pending => " ",
+                StepState::Running => ">",
+                StepState::Done => "x",
+                StepState::Failed => "!",
+            };
+            write!(f, " [{}] {}", mark, s.transition)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod plan_tests {
+    use super::*;
+
+    #[test]
+    fn steps_run_in_order() {
+        let mut plan = Plan::new(vec!["a".into(), "b".into()]);
+        assert_eq!(plan.current(), Some(&SPPath::from("a")));
+        assert_eq!(plan.steps()[0].state, StepState::Running);
+        assert!(plan.steps()[0].started.is_some());
+        assert_eq!(plan.steps()[1].state, StepState::Pending);
+
+        plan.advance();
+        assert_eq!(plan.cursor(), 1);
+        assert_eq!(plan.current(), Some(&SPPath::from("b")));
+        assert_eq!(plan.executed().len(), 1);
+        assert!(plan.executed()[0].finished.is_some());
+        assert_eq!(plan.to_string(), "plan 1/2: [x] a [>] b");
+
+        plan.advance();
+        assert!(plan.is_done());
+        assert_eq!(plan.current(), None);
+        assert!(plan.remaining().is_empty());
+    }
+
+    #[test]
+    fn failed_plans_stop() {
+        let mut plan = Plan::new(vec!["a".into(), "b".into()]);
+        plan.fail();
+        assert!(plan.is_failed());
+        assert!(!plan.is_done());
+        assert_eq!(plan.current(), None);
+        plan.advance();
+        assert_eq!(plan.cursor(), 0);
+    }
+}
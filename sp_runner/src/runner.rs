@@ -1,9 +1,13 @@
 use super::transition_planner::*;
+use super::ticker::*;
+use super::operation_planner::*;
+use super::intention::*;
+use serde::{Deserialize, Serialize};
 use sp_domain::*;
 use sp_model::*;
 use sp_ros::*;
 use sp_formal::*;
-use std::time::{Duration, Instant};
+use std::time::Duration;
 use std::sync::{Arc, Mutex};
 
 #[derive(Debug, PartialEq, Clone)]
@@ -16,38 +20,127 @@ pub struct RunnerModel {
 
     /// Low level planning model
     pub tsm: TransitionSystemModel,
+
+    /// The transitions executed by the runner.
+    pub transitions: Vec<ModelTransition>,
+
+    /// Operations, the goals of executing operations are planned for.
+    pub operations: Vec<Operation>,
+
+    /// The planner used to compute new plans.
+    pub planner: PlannerBackend,
+
+    /// Initial intentions, the operation planner chooses operations to
+    /// reach the goals of the active ones.
+    pub intentions: Vec<Intention>,
+
+    pub config: RunnerConfig,
+
+    /// Problems found when validating the model. The runner does not
+    /// start if any of them is an error.
+    pub diagnostics: Vec<ModelDiagnostic>,
+}
+
+/// Settings of the runner loop.
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
+pub struct RunnerConfig {
+    /// How often the runner ticks, also when no state changes arrive.
+    pub tick_period: Duration,
+    /// Capacity of the channel to the runner.
+    pub input_channel_size: usize,
+    /// Capacity of the channel of incoming state changes.
+    pub state_channel_size: usize,
+    /// How the uncontrolled transitions are fired in each tick. Use the
+    /// default sequential semantics to execute what the planner plans.
+    pub firing_semantics: FiringSemantics,
+    /// What to do when transitions write the same variables in a tick.
+    pub conflict_policy: ConflictPolicy,
+    /// The maximum number of operations in a plan.
+    pub operation_horizon: u32,
+    /// The maximum number of transitions in a plan for the executing operations.
+    pub transition_horizon: u32,
+}
+
+impl Default for RunnerConfig {
+    fn default() -> Self {
+        RunnerConfig {
+            tick_period: Duration::from_millis(1000),
+            input_channel_size: 2,
+            state_channel_size: 2,
+            firing_semantics: FiringSemantics::default(),
+            conflict_policy: ConflictPolicy::default(),
+            operation_horizon: LVL1_MAX_STEPS,
+            transition_horizon: LVL0_MAX_STEPS,
+        }
+    }
 }
 
 impl RunnerModel {
     pub fn from(model: ModelBuilder) -> Self {
-        let mut tsm = TransitionSystemModel::default();
-        let vars = model.variables.clone();
-        tsm.vars.extend(vars);
         RunnerModel {
+            diagnostics: model.validate(),
             initial_state: model.get_initial_state(),
+            tsm: model.make_tsm(),
+            transitions: model.transitions,
+            operations: model.operations,
             messages: model.messages,
-            tsm,
+            planner: PlannerBackend::default(),
+            intentions: vec![],
+            config: RunnerConfig::default(),
         }
     }
 }
 
-#[derive(Debug, PartialEq, Clone)]
+#[derive(Debug)]
 pub enum SPRunnerInput {
     Tick,
     StateChange(SPState),
-    NewPlan(Vec<SPPath>),
+    NewPlan(PartialOrderPlan),
+    NewOperationPlan(Vec<SPPath>),
+    AddIntention(Box<Intention>),
+    CancelIntention(SPPath),
+    /// The goals of the intentions can not be reached.
+    FailIntentions(Vec<SPPath>),
+    ListIntentions(tokio::sync::oneshot::Sender<Vec<Intention>>),
 }
 
 pub async fn launch_model(runner_model: RunnerModel) -> Result<(), SPError> {
+    let (tx_runner, rx_runner) =
+        tokio::sync::mpsc::channel(runner_model.config.input_channel_size);
+    launch_model_with_input(runner_model, tx_runner, rx_runner).await
+}
+
+/// Like `launch_model`, but the channel to the runner is created by the
+/// caller, who can then send inputs to it, e.g. intentions.
+pub async fn launch_model_with_input(
+    runner_model: RunnerModel,
+    tx_runner: tokio::sync::mpsc::Sender<SPRunnerInput>,
+    rx_runner: tokio::sync::mpsc::Receiver<SPRunnerInput>,
+) -> Result<(), SPError> {
+    let mut errors = 0;
+    for d in &runner_model.diagnostics {
+        if d.is_error() {
+            errors += 1;
+            log_error!("{}", d);
+        } else {
+            log_warn!("{}", d);
+        }
+    }
+    if errors > 0 {
+        return Err(SPError::No(format!("the model has {} error(s), not starting", errors)));
+    }
+
     log_info!("startar SP!");
 
-    let (tx_runner, rx_runner) = tokio::sync::mpsc::channel(2);
-    let (tx_new_state, rx_new_state) = tokio::sync::mpsc::channel(2);
+    let config = runner_model.config.clone();
+    let (tx_new_state, rx_new_state) = tokio::sync::mpsc::channel(config.state_channel_size);
     let (tx_runner_state, rx_runner_state) = tokio::sync::watch::channel(runner_model.initial_state.clone());
+    let (tx_intentions, rx_intentions) = tokio::sync::watch::channel(Intentions::default());
+    let (tx_plan, rx_plan) = tokio::sync::watch::channel(PartialOrderPlan::default());
 
 
     tokio::spawn(merger(rx_new_state, tx_runner.clone()));
-    tokio::spawn(ticker_async(std::time::Duration::from_millis(1000), tx_runner.clone()));
+    tokio::spawn(ticker_async(config.tick_period, tx_runner.clone()));
 
     let _ros_comm = sp_ros::RosComm::new(
         rx_runner_state.clone(),
@@ -56,6 +149,7 @@ pub async fn launch_model(runner_model: RunnerModel) -> Result<(), SPError> {
     ).await?;
 
 
+    let operation_planner = OperationPlanner::from(&runner_model);
     let transition_planner = TransitionPlanner::from(&runner_model);
 
     let runner_handle = tokio::spawn(async move {
@@ -63,6 +157,8 @@ pub async fn launch_model(runner_model: RunnerModel) -> Result<(), SPError> {
             &runner_model,
             rx_runner,
             tx_runner_state,
+            tx_intentions,
+            tx_plan,
         ).await;
     });
 
@@ -70,6 +166,9 @@ pub async fn launch_model(runner_model: RunnerModel) -> Result<(), SPError> {
         planner(
             tx_runner.clone(),
             rx_runner_state.clone(),
+            rx_intentions,
+            rx_plan,
+            operation_planner,
             transition_planner,
         ).await;
     });
@@ -87,30 +186,82 @@ pub async fn launch_model(runner_model: RunnerModel) -> Result<(), SPError> {
 async fn planner(
     tx_input: tokio::sync::mpsc::Sender<SPRunnerInput>,
     runner_out: tokio::sync::watch::Receiver<SPState>,
+    mut intentions: tokio::sync::watch::Receiver<Intentions>,
+    runner_plan: tokio::sync::watch::Receiver<PartialOrderPlan>,
+    mut operation_planner: OperationPlanner,
     mut transition_planner: TransitionPlanner,
 ) {
     let mut t_runner_out = runner_out.clone();
     let t_tx_input = tx_input.clone();
 
+    let (tx_progress, mut rx_progress) = tokio::sync::mpsc::unbounded_channel();
     tokio::spawn(async move {
+        while let Some(progress) = rx_progress.recv().await {
+            log_info!("planning: {:?}", progress);
+        }
+    });
+
+    tokio::spawn(async move {
+        let mut replan = false;
         loop {
-            tokio::select! {
-                _ = t_runner_out.changed() => {
-                    let ro = t_runner_out.borrow().clone();
-                    let mut tpc = transition_planner.clone();
-                    let x = tokio::task::spawn_blocking(move || {
-                        let plan = tpc.compute_new_plan(ro);
-                        (plan, tpc)
-                    }).await;
-                    if let Ok((plan, tpc)) = x {
-                        transition_planner = tpc;
-                        if let Some(plan) = plan {
-                            println!("new plan computed");
-                            let cmd = SPRunnerInput::NewPlan(plan);
-                            let _res = t_tx_input.send(cmd).await;
-                        }
+            if !replan {
+                let alive = tokio::select! {
+                    r = t_runner_out.changed() => r.is_ok(),
+                    r = intentions.changed() => r.is_ok(),
+                };
+                if !alive {
+                    break;
+                }
+            }
+            replan = false;
+
+            let ro = t_runner_out.borrow_and_update().clone();
+            let (goals, active) = {
+                let intentions = intentions.borrow_and_update();
+                let active: Vec<_> = intentions.active().map(|i| i.path.clone()).collect();
+                (intentions.goals(), active)
+            };
+            let cancel = CancellationToken::new();
+            let round = async {
+                let plan = operation_planner.compute_new_plan(ro.clone(), &goals, &cancel, Some(&tx_progress)).await;
+                if let Some(plan) = plan {
+                    log_info!("new operation plan computed");
+                    if !plan.plan_found {
+                        let cmd = SPRunnerInput::FailIntentions(active);
+                        let _res = t_tx_input.send(cmd).await;
                     }
-                },
+                    let cmd = SPRunnerInput::NewOperationPlan(plan.plan);
+                    let _res = t_tx_input.send(cmd).await;
+                }
+                transition_planner.update_plan_progress(&runner_plan.borrow());
+                let plan = transition_planner.compute_new_plan(ro, &cancel, Some(&tx_progress)).await;
+                if let Some(plan) = plan {
+                    println!("new plan computed");
+                    if !plan.state_change.projection().state.is_empty() {
+                        let cmd = SPRunnerInput::StateChange(plan.state_change);
+                        let _res = t_tx_input.send(cmd).await;
+                    }
+                    let cmd = SPRunnerInput::NewPlan(plan.partial_order_plan);
+                    let _res = t_tx_input.send(cmd).await;
+                }
+            };
+            tokio::pin!(round);
+
+            let outdated = tokio::select! {
+                _ = &mut round => None,
+                r = t_runner_out.changed() => Some(r.is_ok()),
+                r = intentions.changed() => Some(r.is_ok()),
+            };
+            if let Some(alive) = outdated {
+                if !alive {
+                    round.await;
+                    break;
+                }
+                // the round is planning for an outdated state or goal,
+                // stop it and start over.
+                cancel.cancel();
+                round.await;
+                replan = true;
             }
         }
     });
@@ -120,19 +271,35 @@ async fn planner(
 async fn runner(
     model: &RunnerModel,
     mut rx_input: tokio::sync::mpsc::Receiver<SPRunnerInput>,
-    tx_state_out: tokio::sync::watch::Sender<SPState>
+    tx_state_out: tokio::sync::watch::Sender<SPState>,
+    tx_intentions: tokio::sync::watch::Sender<Intentions>,
+    tx_plan: tokio::sync::watch::Sender<PartialOrderPlan>,
 ) {
     log_info!("Runner start");
 
-    let mut now = Instant::now();
-
-    let mut ticker = crate::Ticker::default();
-    ticker.state = model.initial_state.clone();
+    let (controlled_transitions, uncontrolled_transitions) =
+        get_runner_transitions(&model.transitions);
+    let mut ticker = crate::Ticker {
+        state: model.initial_state.clone(),
+        controlled_transitions,
+        uncontrolled_transitions,
+        predicates: model.tsm.state_predicates.clone(),
+        semantics: model.config.firing_semantics.clone(),
+        conflict_policy: model.config.conflict_policy.clone(),
+        .. crate::Ticker::default()
+    };
+    let mut intentions = Intentions::new(model.intentions.clone());
+    intentions.update(&ticker.state);
+    ticker.state.extend(intentions.state());
+    ticker.update_state_paths();
+    let _res = tx_intentions.send(intentions.clone());
 
     loop {
         let mut state_has_probably_changed = false;
         let mut ticked = false;
         let mut last_fired_transitions = vec![];
+        let mut plan_progress = Some(steps_done_and_failed(&ticker.partial_order_plan));
+        let prev_intentions = intentions.clone();
         let input = rx_input.recv().await;
         if let Some(input) = input {
             match input {
@@ -156,11 +323,66 @@ async fn runner(
                     last_fired_transitions = ticker.tick_transitions();
                     ticked = true;
                 },
-                SPRunnerInput::NewPlan(_plan) => {
-                    // runner.set_plan(plan_name, plan);
+                SPRunnerInput::NewPlan(plan) => {
+                    ticker.partial_order_plan = plan;
+                    plan_progress = None;
+                    last_fired_transitions = ticker.tick_transitions();
+                },
+                SPRunnerInput::NewOperationPlan(plan) => {
+                    ticker.operation_plan = crate::Plan::new(plan);
+                    log_info!("New operation {}", ticker.operation_plan);
+                    last_fired_transitions = ticker.tick_transitions();
+                },
+                SPRunnerInput::AddIntention(intention) => {
+                    log_info!("New intention: {}", intention.path);
+                    intentions.add(*intention);
+                },
+                SPRunnerInput::CancelIntention(path) => {
+                    intentions.cancel(&path);
+                },
+                SPRunnerInput::FailIntentions(paths) => {
+                    paths.iter().for_each(|p| intentions.fail(p));
+                },
+                SPRunnerInput::ListIntentions(reply) => {
+                    let _res = reply.send(intentions.list().to_vec());
                 },
             }
 
+            if !last_fired_transitions.is_empty() {
+                ticker.state.take_transition();
+            }
+
+            intentions.update(&ticker.state);
+            if intentions != prev_intentions {
+                for i in intentions.list().iter().filter(|i| !prev_intentions.list().contains(i)) {
+                    log_info!("intention {}: {}", i.path, i.state.as_str());
+                }
+                let state_id = ticker.state.id();
+                ticker.state.extend(intentions.state());
+                if state_id != ticker.state.id() {
+                    ticker.update_state_paths();
+                }
+                let _res = tx_intentions.send(intentions.clone());
+                state_has_probably_changed = true;
+            }
+
+            for event in ticker.events.drain(..) {
+                if event.is_error() {
+                    log_error!("{}", event);
+                } else {
+                    log_warn!("{}", event);
+                }
+            }
+
+            if plan_progress != Some(steps_done_and_failed(&ticker.partial_order_plan)) {
+                if ticker.partial_order_plan.is_failed() {
+                    log_warn!("{}", ticker.partial_order_plan);
+                } else {
+                    log_info!("{}", ticker.partial_order_plan);
+                }
+                let _res = tx_plan.send(ticker.partial_order_plan.clone());
+            }
+
             // if there's nothing to do in this cycle, continue
             if !state_has_probably_changed && last_fired_transitions.is_empty() && !ticked {
                 continue;
@@ -210,7 +432,10 @@ async fn merger(
     let ms_out = ms_arc.clone();
     tokio::spawn(async move {
         loop {
-            rx.changed().await;
+            if rx.changed().await.is_err() {
+                // the sender is gone, no more states will arrive.
+                break;
+            }
             let mut states = {
                 let mut x = ms_out.lock().unwrap();
                 let res = x.states.clone();
@@ -238,6 +463,13 @@ async fn merger(
 /// Tries to extend the state only if the state does not contain the same
 /// path or if that path has the same value, else will leave the state unchanged
 /// and returns false.
+/// How many steps of the plan are done and how many have failed.
+fn steps_done_and_failed(plan: &PartialOrderPlan) -> (usize, usize) {
+    let done = plan.steps.iter().filter(|s| s.done).count();
+    let failed = plan.steps.iter().filter(|s| s.failed).count();
+    (done, failed)
+}
+
 fn try_extend(state: &mut SPState, other_state: SPState) -> Option<SPState> {
     let can_extend = other_state.projection().state.iter().all(|(p, v)| {
         let self_v = state.sp_value_from_path(p);
@@ -251,7 +483,7 @@ fn try_extend(state: &mut SPState, other_state: SPState) -> Option<SPState> {
     }
 }
 
-/// The ticker that sends a tick to the runner at an interval defined by ´freq´
+/// The ticker that sends a tick to the runner at an interval defined by ´period´
 async fn ticker_async(period: Duration, tx_runner: tokio::sync::mpsc::Sender<SPRunnerInput>) {
     let mut ticker = tokio::time::interval(period);
     ticker.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Skip);
@@ -1,4 +1,90 @@
+use serde::{Deserialize, Serialize};
 use sp_domain::*;
+use sp_formal::*;
+use crate::*;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fmt;
+
+/// How the uncontrolled transitions are fired in a tick. A transition
+/// is never fired if it writes a variable that was already written in
+/// the same tick, it is instead fired in a later tick.
+///
+/// The planner assumes that one transition is taken at a time, and
+/// that its guard is evaluated in the state left by the transition
+/// before it. `Sequential` and `Priority` follow these semantics:
+/// every tick is a sequence of steps in the formal model, and thus
+/// the runner can only do what the planner thinks is possible.
+/// `Synchronous` only does so when the transitions fired together do
+/// not read what the others write.
+#[derive(Debug, PartialEq, Eq, Clone, Serialize, Deserialize, Default)]
+pub enum FiringSemantics {
+    /// In the order of the transitions, each one is evaluated in the
+    /// state left by the ones fired before it.
+    #[default]
+    Sequential,
+    /// All transitions are evaluated in the state at the start of the
+    /// tick, and the enabled ones are then applied together.
+    Synchronous,
+    /// Like `Sequential`, but the listed transitions are fired first,
+    /// in the given order. The rest follow in their usual order.
+    Priority(Vec<SPPath>),
+}
+
+/// What to do when uncontrolled transitions that are enabled at the
+/// same time write the same variables.
+#[derive(Debug, PartialEq, Eq, Clone, Serialize, Deserialize, Default)]
+pub enum ConflictPolicy {
+    /// None of the conflicting transitions are fired.
+    Error,
+    /// The transition listed first wins, transitions that are not
+    /// listed lose against those that are. Otherwise the first
+    /// transition in firing order wins.
+    Priority(Vec<SPPath>),
+    /// The first transition in firing order wins.
+    #[default]
+    FirstWins,
+}
+
+/// Things that happen in the ticker that the model should not allow.
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
+pub enum RunnerEvent {
+    /// The transitions were enabled in the same tick and all write
+    /// `paths`. `fired` is the one that won according to the conflict
+    /// policy, if any.
+    WriteConflict {
+        transitions: Vec<SPPath>,
+        paths: Vec<SPPath>,
+        fired: Option<SPPath>,
+    },
+    /// The actions of the transition could not be applied.
+    ActionFailed { transition: SPPath, message: String },
+}
+
+impl RunnerEvent {
+    /// Whether the model did not execute as intended.
+    pub fn is_error(&self) -> bool {
+        !matches!(self, RunnerEvent::WriteConflict { fired: Some(_), .. })
+    }
+}
+
+impl fmt::Display for RunnerEvent {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RunnerEvent::WriteConflict { transitions, paths, fired } => {
+                let transitions: Vec<String> = transitions.iter().map(|t| t.to_string()).collect();
+                let paths: Vec<String> = paths.iter().map(|p| p.to_string()).collect();
+                write!(f, "write conflict between {} on {}", transitions.join(", "), paths.join(", "))?;
+                match fired {
+                    Some(t) => write!(f, ", fired {}", t),
+                    None => write!(f, ", none fired"),
+                }
+            },
+            RunnerEvent::ActionFailed { transition, message } => {
+                write!(f, "the actions of {} failed: {}", transition, message)
+            },
+        }
+    }
+}
 
 #[derive(Debug, PartialEq, Clone, Default)]
 pub struct Ticker {
@@ -10,50 +96,207 @@ pub struct Ticker {
     pub uncontrolled_transitions: Vec<Transition>,
     pub predicates: Vec<NamedPredicate>,
 
-    /// Allowed to run
-    pub controlled_queue: Vec<SPPath>,
+    pub semantics: FiringSemantics,
+
+    pub conflict_policy: ConflictPolicy,
+
+    /// Events of the ticks so far, drained by the runner.
+    pub events: Vec<RunnerEvent>,
+
+    /// The operations to start, in order.
+    pub operation_plan: Plan,
+
+    /// The controlled transitions allowed to run. Steps may run in
+    /// parallel once their predecessors are done.
+    pub partial_order_plan: PartialOrderPlan,
 }
 
 impl Ticker {
     pub fn tick_transitions(&mut self) -> Vec<SPPath> {
         let mut fired = self.tick_uncontrolled();
-        if let Some(p) = self.tick_first_controlled() {
+        if let Some(p) = self.tick_operation_plan() {
             fired.push(p);
         }
+        fired.extend(self.tick_partial_order_plan());
         fired
     }
 
+    /// Fires the enabled uncontrolled transitions according to the
+    /// firing semantics. The controlled transitions are fired after
+    /// them, in the state they leave.
     pub fn tick_uncontrolled(&mut self) -> Vec<SPPath> {
         self.state.upd_preds(&self.predicates);
-        self.uncontrolled_transitions
-            .iter()
-            .flat_map(|t| {
-                if !t.actions.is_empty() && t.eval(&self.state) {
-                    // TODO: handle errors
-                    let _r = t.next(&mut self.state);
-                    self.state.upd_preds(&self.predicates);
-                    Some(t.path.clone())
-                } else {
-                    None
-                }
+        let pre = match self.semantics {
+            FiringSemantics::Synchronous => Some(self.state.clone()),
+            _ => None,
+        };
+
+        let blocked = self.resolve_conflicts();
+
+        let mut fired = vec![];
+        for i in self.firing_order() {
+            let t = &self.uncontrolled_transitions[i];
+            if t.actions.is_empty() || blocked.contains(&i) {
+                continue;
+            }
+            let enabled = match &pre {
+                Some(pre) => t.eval(pre) && t.actions.iter().all(|a| a.eval(&self.state)),
+                None => t.eval(&self.state),
+            };
+            if !enabled {
+                continue;
+            }
+            let result = match &pre {
+                Some(pre) => apply_from(t, pre, &mut self.state),
+                None => t.next(&mut self.state),
+            };
+            match result {
+                Ok(()) => {
+                    if pre.is_none() {
+                        self.state.upd_preds(&self.predicates);
+                    }
+                    fired.push(t.path.clone());
+                },
+                Err(e) => self.events.push(RunnerEvent::ActionFailed {
+                    transition: t.path.clone(),
+                    message: e.to_string(),
+                }),
+            }
+        }
+        self.state.upd_preds(&self.predicates);
+        fired
+    }
+
+    /// Indices of the uncontrolled transitions in the order they are fired.
+    fn firing_order(&self) -> Vec<usize> {
+        let mut order: Vec<usize> = (0..self.uncontrolled_transitions.len()).collect();
+        if let FiringSemantics::Priority(priorities) = &self.semantics {
+            order.sort_by_key(|i| {
+                let path = &self.uncontrolled_transitions[*i].path;
+                priorities.iter().position(|p| p == path).unwrap_or(priorities.len())
+            });
+        }
+        order
+    }
+
+    /// Finds the uncontrolled transitions that are enabled at the start
+    /// of the tick and write the same variables, and decides which of
+    /// them to fire according to the conflict policy. Transitions that
+    /// only become enabled during a sequential tick are not in conflict,
+    /// they are evaluated again in the next tick. Returns the
+    /// transitions that may not fire in this tick.
+    fn resolve_conflicts(&mut self) -> HashSet<usize> {
+        let mut candidates: Vec<usize> = self.firing_order()
+            .into_iter()
+            .filter(|i| {
+                let t = &self.uncontrolled_transitions[*i];
+                !t.actions.is_empty() && t.eval(&self.state)
             })
-            .collect()
+            .collect();
+        if let ConflictPolicy::Priority(priorities) = &self.conflict_policy {
+            candidates.sort_by_key(|i| {
+                let path = &self.uncontrolled_transitions[*i].path;
+                priorities.iter().position(|p| p == path).unwrap_or(priorities.len())
+            });
+        }
+
+        let mut owners: HashMap<SPPath, usize> = HashMap::new();
+        let mut blocked = HashSet::new();
+        for i in candidates {
+            let writes = self.uncontrolled_transitions[i].modifies();
+            let mut conflicts: BTreeMap<usize, Vec<SPPath>> = BTreeMap::new();
+            for path in &writes {
+                if let Some(owner) = owners.get(path) {
+                    conflicts.entry(*owner).or_default().push(path.clone());
+                }
+            }
+            if conflicts.is_empty() {
+                owners.extend(writes.into_iter().map(|p| (p, i)));
+                continue;
+            }
+
+            blocked.insert(i);
+            for (owner, mut paths) in conflicts {
+                let winner = self.uncontrolled_transitions[owner].path.clone();
+                let fired = if self.conflict_policy == ConflictPolicy::Error {
+                    blocked.insert(owner);
+                    None
+                } else {
+                    Some(winner.clone())
+                };
+                paths.sort();
+                self.events.push(RunnerEvent::WriteConflict {
+                    transitions: vec![winner, self.uncontrolled_transitions[i].path.clone()],
+                    paths,
+                    fired,
+                });
+            }
+        }
+        blocked
+    }
+
+    /// Fires the current step of the operation plan if it is enabled.
+    /// The plan fails if the step is not a controlled transition or if
+    /// its actions can not be applied.
+    pub fn tick_operation_plan(&mut self) -> Option<SPPath> {
+        self.state.upd_preds(&self.predicates);
+        let plan = &mut self.operation_plan;
+        let current = plan.current()?;
+        let t = match self.controlled_transitions.iter().find(|t| &t.path == current) {
+            Some(t) => t,
+            None => {
+                plan.fail();
+                return None;
+            }
+        };
+        if !t.eval(&self.state) {
+            return None;
+        }
+        if let Err(e) = t.next(&mut self.state) {
+            self.events.push(RunnerEvent::ActionFailed {
+                transition: t.path.clone(),
+                message: e.to_string(),
+            });
+            plan.fail();
+            return None;
+        }
+        self.state.upd_preds(&self.predicates);
+        plan.advance();
+        Some(t.path.clone())
     }
 
-    pub fn tick_first_controlled(&mut self) -> Option<SPPath> {
+    /// Fires all enabled steps of the partial order plan whose
+    /// predecessors are done. Like in `tick_operation_plan`, a step fails if it
+    /// is not a controlled transition or if its actions can not be
+    /// applied, which stops the steps that depend on it.
+    pub fn tick_partial_order_plan(&mut self) -> Vec<SPPath> {
         self.state.upd_preds(&self.predicates);
-        if let Some(first) = self.controlled_queue.first() {
-            if let Some(first) = self.controlled_transitions.iter().find(|t|&t.path == first) {
-                if first.eval(&self.state) {
-                    // TODO: handle errors
-                    let _r = first.next(&mut self.state);
-                    self.state.upd_preds(&self.predicates);
-                    let _throw_first = self.controlled_queue.pop();
-                    return Some(first.path.clone());
+        let mut fired = vec![];
+        for step in self.partial_order_plan.ready() {
+            let path = &self.partial_order_plan.steps[step].transition;
+            let t = match self.controlled_transitions.iter().find(|t| &t.path == path) {
+                Some(t) => t,
+                None => {
+                    self.partial_order_plan.set_failed(step);
+                    continue;
                 }
+            };
+            if !t.eval(&self.state) {
+                continue;
+            }
+            if let Err(e) = t.next(&mut self.state) {
+                self.events.push(RunnerEvent::ActionFailed {
+                    transition: t.path.clone(),
+                    message: e.to_string(),
+                });
+                self.partial_order_plan.set_failed(step);
+                continue;
             }
+            self.state.upd_preds(&self.predicates);
+            self.partial_order_plan.set_done(step);
+            fired.push(t.path.clone());
         }
-        None
+        fired
     }
 
     /// After changing anything in the Ticker, run this method to update the state variables.
@@ -73,6 +316,20 @@ impl Ticker {
     }
 }
 
+/// Applies the actions of `t`, computed in the state `pre`, as next
+/// values of `state`.
+fn apply_from(t: &Transition, pre: &SPState, state: &mut SPState) -> Result<(), SPError> {
+    let mut next = pre.clone();
+    t.next(&mut next)?;
+    for a in &t.actions {
+        let value = next.state_value_from_path(&a.var).and_then(|v| v.next_value().clone());
+        if let Some(value) = value {
+            state.next_from_path(&a.var, value)?;
+        }
+    }
+    Ok(())
+}
+
 
 
 #[cfg(test)]
@@ -98,7 +355,7 @@ mod test_new_ticker {
         let mut ticker = Ticker {
             state: s,
             uncontrolled_transitions: vec![t1],
-            controlled_queue: vec![t2.path.clone()],
+            operation_plan: Plan::new(vec![t2.path.clone()]),
             controlled_transitions: vec![t2],
             .. Ticker::default()
         };
@@ -108,4 +365,209 @@ mod test_new_ticker {
         println!("FIRED: {:?}", res);
     }
 
+    #[test]
+    fn plan_steps_fire_in_order() {
+        let x = SPPath::from("x");
+
+        let t1 = Transition::new("first".into(), p!(x == 0), vec![a!(x = 1)]);
+        let t2 = Transition::new("second".into(), p!(x == 1), vec![a!(x = 2)]);
+        let t3 = Transition::new("third".into(), p!(x == 2), vec![a!(x = 3)]);
+
+        let mut ticker = Ticker {
+            state: state!(x => 0),
+            operation_plan: Plan::new(vec!["first".into(), "second".into(), "third".into()]),
+            controlled_transitions: vec![t1, t2, t3],
+            .. Ticker::default()
+        };
+        ticker.update_state_paths();
+
+        let mut fired = vec![];
+        while let Some(t) = ticker.tick_operation_plan() {
+            ticker.state.take_transition();
+            fired.push(t);
+        }
+        assert_eq!(fired, vec![SPPath::from("first"), SPPath::from("second"), SPPath::from("third")]);
+        assert!(ticker.operation_plan.is_done());
+        assert!(ticker.operation_plan.steps().iter().all(|s| s.state == StepState::Done));
+    }
+
+    fn counters(semantics: FiringSemantics) -> Ticker {
+        let x = SPPath::from("x");
+        let y = SPPath::from("y");
+
+        // copy_x reads what inc_x writes
+        let inc_x = Transition::new("inc_x".into(), p!(x == 0), vec![a!(x = 1)]);
+        let copy_x = Transition::new("copy_x".into(), p!(x == 1), vec![a!(y = x)]);
+        let mut ticker = Ticker {
+            state: state!(x => 0, y => 0),
+            uncontrolled_transitions: vec![inc_x, copy_x],
+            semantics,
+            .. Ticker::default()
+        };
+        ticker.update_state_paths();
+        ticker
+    }
+
+    #[test]
+    fn sequential_firing() {
+        let mut ticker = counters(FiringSemantics::Sequential);
+        let fired = ticker.tick_uncontrolled();
+        ticker.state.take_transition();
+        assert_eq!(fired, vec![SPPath::from("inc_x"), SPPath::from("copy_x")]);
+        assert_eq!(ticker.state.sp_value_from_path(&"y".into()), Some(&1.to_spvalue()));
+    }
+
+    #[test]
+    fn synchronous_firing() {
+        let mut ticker = counters(FiringSemantics::Synchronous);
+        // copy_x is not enabled in the state at the start of the tick
+        let fired = ticker.tick_uncontrolled();
+        ticker.state.take_transition();
+        assert_eq!(fired, vec![SPPath::from("inc_x")]);
+        assert_eq!(ticker.state.sp_value_from_path(&"y".into()), Some(&0.to_spvalue()));
+
+        let fired = ticker.tick_uncontrolled();
+        ticker.state.take_transition();
+        assert_eq!(fired, vec![SPPath::from("copy_x")]);
+        assert_eq!(ticker.state.sp_value_from_path(&"y".into()), Some(&1.to_spvalue()));
+    }
+
+    #[test]
+    fn priority_firing() {
+        let x = SPPath::from("x");
+        let low = Transition::new("low".into(), p!(x == 0), vec![a!(x = 1)]);
+        let high = Transition::new("high".into(), p!(x == 0), vec![a!(x = 2)]);
+        let mut ticker = Ticker {
+            state: state!(x => 0),
+            uncontrolled_transitions: vec![low, high],
+            semantics: FiringSemantics::Priority(vec!["high".into()]),
+            .. Ticker::default()
+        };
+        ticker.update_state_paths();
+
+        // both write x, only the first one in priority order fires
+        let fired = ticker.tick_uncontrolled();
+        ticker.state.take_transition();
+        assert_eq!(fired, vec![SPPath::from("high")]);
+        assert_eq!(ticker.state.sp_value_from_path(&x), Some(&2.to_spvalue()));
+    }
+
+    fn conflicting(policy: ConflictPolicy) -> Ticker {
+        let x = SPPath::from("x");
+        let y = SPPath::from("y");
+        let a = Transition::new("a".into(), p!(x == 0), vec![a!(x = 1)]);
+        let b = Transition::new("b".into(), p!(x == 0), vec![a!(x = 2), a!(y = 2)]);
+        let mut ticker = Ticker {
+            state: state!(x => 0, y => 0),
+            uncontrolled_transitions: vec![a, b],
+            conflict_policy: policy,
+            .. Ticker::default()
+        };
+        ticker.update_state_paths();
+        ticker
+    }
+
+    #[test]
+    fn write_conflicts_first_wins() {
+        let mut ticker = conflicting(ConflictPolicy::FirstWins);
+        assert_eq!(ticker.tick_uncontrolled(), vec![SPPath::from("a")]);
+        assert_eq!(ticker.events, vec![RunnerEvent::WriteConflict {
+            transitions: vec!["a".into(), "b".into()],
+            paths: vec!["x".into()],
+            fired: Some("a".into()),
+        }]);
+        assert!(!ticker.events[0].is_error());
+    }
+
+    #[test]
+    fn write_conflicts_by_priority() {
+        let mut ticker = conflicting(ConflictPolicy::Priority(vec!["b".into()]));
+        assert_eq!(ticker.tick_uncontrolled(), vec![SPPath::from("b")]);
+        ticker.state.take_transition();
+        assert_eq!(ticker.state.sp_value_from_path(&"y".into()), Some(&2.to_spvalue()));
+        assert!(matches!(&ticker.events[..],
+                         [RunnerEvent::WriteConflict { fired: Some(t), .. }] if t == &SPPath::from("b")));
+    }
+
+    #[test]
+    fn write_conflicts_as_errors() {
+        let mut ticker = conflicting(ConflictPolicy::Error);
+        assert!(ticker.tick_uncontrolled().is_empty());
+        assert_eq!(ticker.events.len(), 1);
+        assert!(ticker.events[0].is_error());
+        assert_eq!(ticker.events[0].to_string(), "write conflict between a, b on x, none fired");
+    }
+
+    #[test]
+    fn unknown_plan_steps_fail() {
+        let mut ticker = Ticker {
+            operation_plan: Plan::new(vec!["missing".into()]),
+            .. Ticker::default()
+        };
+        assert_eq!(ticker.tick_operation_plan(), None);
+        assert!(ticker.operation_plan.is_failed());
+        assert_eq!(ticker.operation_plan.steps()[0].state, StepState::Failed);
+    }
+
+    #[test]
+    fn partial_order_plan_fires_in_parallel() {
+        let r1 = SPPath::from(&["r1", "busy"]);
+        let r2 = SPPath::from(&["r2", "busy"]);
+
+        let t1 = Transition::new("r1_start".into(), p!(!r1), vec![a!(r1)]);
+        let t2 = Transition::new("r2_start".into(), p!(!r2), vec![a!(r2)]);
+
+        let mut ticker = Ticker {
+            state: state!(r1 => false, r2 => false),
+            controlled_transitions: vec![t1, t2],
+            partial_order_plan: PartialOrderPlan {
+                steps: vec![
+                    PlanStep { transition: "r1_start".into(), predecessors: vec![], done: false, failed: false },
+                    PlanStep { transition: "r2_start".into(), predecessors: vec![], done: false, failed: false },
+                ],
+            },
+            .. Ticker::default()
+        };
+        ticker.update_state_paths();
+
+        let res = ticker.tick_transitions();
+        assert_eq!(res, vec![SPPath::from("r1_start"), SPPath::from("r2_start")]);
+        assert!(ticker.partial_order_plan.is_done());
+    }
+
+    #[test]
+    fn partial_order_plan_steps_fail_once() {
+        let x = SPPath::from("x");
+        let broken = Transition::new(
+            "broken".into(),
+            Predicate::TRUE,
+            vec![Action::new(x.clone(), Compute::Function(vec![]))],
+        );
+        let step = |transition: &str, predecessors: Vec<usize>| PlanStep {
+            transition: transition.into(),
+            predecessors,
+            done: false,
+            failed: false,
+        };
+
+        let mut ticker = Ticker {
+            state: state!(x => 0),
+            controlled_transitions: vec![broken],
+            partial_order_plan: PartialOrderPlan {
+                steps: vec![step("missing", vec![]), step("broken", vec![]), step("after", vec![1])],
+            },
+            .. Ticker::default()
+        };
+        ticker.update_state_paths();
+
+        for _ in 0..3 {
+            assert!(ticker.tick_partial_order_plan().is_empty());
+        }
+        let failed: Vec<_> = ticker.partial_order_plan.steps.iter().map(|s| s.failed).collect();
+        assert_eq!(failed, vec![true, true, false]);
+        assert!(ticker.partial_order_plan.ready().is_empty());
+        assert_eq!(ticker.events.len(), 1);
+        assert!(ticker.events[0].is_error());
+    }
+
 }
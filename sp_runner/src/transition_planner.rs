@@ -1,21 +1,36 @@
 use sp_domain::*;
 use sp_formal::*;
-use std::time::{Duration,Instant};
+use sp_model::*;
+use sp_ros::*;
 use super::*;
-use std::collections::HashSet;
 
 // some planning constants
-const LVL0_MAX_STEPS: u32 = 100;
+pub const LVL0_MAX_STEPS: u32 = 100;
+
+/// A new plan for the runner.
+#[derive(Debug, PartialEq, Clone)]
+pub struct TransitionPlan {
+    /// The controlled transitions to take, in order.
+    pub plan: Vec<SPPath>,
+    /// The same plan where independent steps may be taken in
+    /// parallel, this is what the runner executes.
+    pub partial_order_plan: PartialOrderPlan,
+    /// State changes to make, e.g. setting operations that can not
+    /// be completed to "error".
+    pub state_change: SPState,
+}
 
 #[derive(Debug, Clone)]
 pub struct TransitionPlanner {
     pub plan: Vec<SPPath>, // current plan
+    pub partial_order_plan: PartialOrderPlan, // current plan, as far as the runner has come
     pub model: TransitionSystemModel, // planning model
-    pub bad_state: bool, // todo.
+    pub operations: Vec<Operation>, // goals are taken from executing operations
     pub prev_state: SPState, // to check if something relevant for this planner has changed
     pub prev_goals: Vec<(Predicate, Option<Predicate>)>, // previous goals
-    pub store: planning::PlanningStore, // cache
-    pub disabled_operation_check: Instant,
+    pub store: PlanCache, // cache
+    pub planner: Box<dyn Planner>, // planning backend
+    pub max_steps: u32, // planning horizon
 
     pub simulation_ticker: Ticker,
 }
@@ -36,583 +51,335 @@ pub fn check_goals_fast(ticker: &mut Ticker, goals: &[&Predicate]) -> bool {
         let _fired = ticker.tick_transitions();
         let changed = ticker.state.take_transition();
 
-        // if ticker.bad_state(&ticker.state) {
-        //     return false;
-        // }
-
         goals.retain(|g| !g.eval(&ticker.state));
         if goals.is_empty() {
             return true;
         } else if !changed {
-            // double check with complete search in the normal
-            // case where we fail early in the plan this is still
-            // fast, but it saves us from the issue descibed
-            // above. worst case we waste some time here but but
-            // it should be faster than replanning anyway.
-            //
-            // actually this becomes quite expensive... for now just replan
-
-            // println!("NOT CHECKING GOALS COMPLETE, replan instead.");
-            // return self.check_goals_complete(s, &goals, plan, ts_model);
-            return false; // think about how we want to do it...
+            // a complete search could save us from the issue
+            // described above, but it is too expensive. replan instead.
+            return false;
         }
     }
 }
 
-// /// A slower, but more forgiving forward search to goal.
-// /// This handles the case described in check_goals_fast.
-// fn check_goals_complete(
-//     state: &SPState, goals: &[&Predicate], plan: &SPPlan, ts_model: &TransitionSystemModel,
-//     disabled_paths: &[SPPath]
-// ) -> bool {
-//     if goals.iter().all(|g| g.eval(state)) {
-//         return true;
-//     }
-
-//     let trans: Vec<Transition> = ts_model
-//         .transitions
-//         .iter()
-//         .filter(|t| plan.included_trans.contains(t.path()))
-//         .cloned()
-//         .collect();
-
-//     let tm = SPTicker::create_transition_map(&trans, &plan.plan);
-
-//     fn rec<'a>(
-//         state: &SPState, goals: &[&Predicate], tm: &Vec<Vec<&'a Transition>>,
-//         visited: &mut Vec<SPState>, ts_model: &TransitionSystemModel,
-//     ) -> bool {
-//         if goals.iter().all(|g| g.eval(&state)) {
-//             return true;
-//         }
-
-//         let new_states: Vec<SPState> = tm
-//             .iter()
-//             .flat_map(|ts| {
-//                 if ts
-//                     .iter()
-//                     .all(|t| t.eval(&state) && !bad_state(&state, ts_model))
-//                 {
-//                     // transitions enabled. clone the state to start a new search branch.
-//                     let mut state = state.clone();
-
-//                     // take all actions
-//                     ts.iter().flat_map(|t| t.actions.iter()).for_each(|a| {
-//                         // if actions write to the same path, only the first will be used
-//                         let _res = a.next(&mut state);
-//                     });
-
-//                     // update state predicates
-//                     update_predicates(&mut state, &ts_model.state_predicates);
-
-//                     // next -> cur
-//                     if state.take_transition() && !visited.contains(&state) {
-//                         visited.push(state.clone());
-//                         Some(state)
-//                     } else {
-//                         None
-//                     }
-//                 } else {
-//                     None
-//                 }
-//             })
-//             .collect();
-
-//         new_states
-//             .iter()
-//             .any(|s| rec(&s, goals, tm, visited, ts_model))
-//     }
-
-//     let mut visited = vec![state.clone()];
-//     rec(state, &goals, &tm, &mut visited, ts_model)
-// }
-
 impl TransitionPlanner {
-//     /// Get the current goals and invariants given a state.
-//     fn goals(&mut self, state: &SPState) -> Vec<(Predicate, Option<Predicate>)> {
-//         self.operations.iter().filter_map(|op| {
-//             if op.is_executing(state) {
-//                 Some((op.get_goal(Some(state)), None))
-//             } else {
-//                 None
-//             }
-//         }).collect()
-//     }
-
-//     pub fn block_all(&mut self) -> SPPlan {
-//         self.prev_goals.clear();
-//         let block_plan = SPPlan {
-//             plan: planning::block_all(&self.model),
-//             included_trans: Vec::new(),
-//             state_change: SPState::new(),
-//         };
-
-//         // update current plan
-//         self.plan = block_plan.clone();
-
-//         block_plan
-//     }
-
-//     /// Only keep parts of the state that are relevant to this planner.
-//     pub fn filter_state(&self, state: SPState) -> SPState {
-//         // Planning index
-//         let mut to_keep = vec![SPPath::from_slice(&["runner", "plans", "1"])];
-//         // We want to keep any paths contained in our formal model.
-//         to_keep.extend(self.model.get_state_paths());
-//         // As well as the state of any operations in the model.
-//         to_keep.extend(self.operations.iter().map(|o|o.path().clone()).collect::<Vec<_>>());
-
-//         // TODO. temporary effect flags
-//         self.model.transitions.iter().for_each(|t| {
-//             if t.type_ == TransitionType::Effect {
-//                 to_keep.push(t.path().add_parent("effects"));
-//             }
-//         });
-
-//         state.filter_by_paths(&to_keep)
-//     }
-
-//     pub fn compute_new_plan(
-//         &mut self,
-//         mut state: SPState) -> Option<SPPlan> {
-//         let new_state = self.filter_state(state.clone());
-
-//         // nothing has changed, no need to do anything.
-//         if new_state == self.prev_state {
-//             // back off a bit
-//             std::thread::sleep(Duration::from_millis(1));
-//             return None;
-//         }
-
-//         // hack to add /auto_guards ...
-//         // this is used only in the planner
-//         let agp = SPPath::from_string("auto_guards");
-//         if let Some(ag) = self.model.state_predicates.iter().find(|v| v.path() == &agp) {
-//             if let VariableType::Predicate(p) = ag.variable_type() {
-//                 let on = p.eval(&new_state);
-//                 state.add_variable(agp, on.to_spvalue());
-//             }
-//         }
-
-//         self.prev_state = new_state.clone();
-
-//         if !self.bad_state {
-//             // previously we had the above
-//             // temp_ts.specs.extend(self.transition_system_models[1].specs.iter().cloned());
-//             let bad: Vec<_> = self.model
-//                 .invariants
-//                 .iter()
-//                 .filter_map(|s| {
-//                     if !s.invariant().eval(&state) {
-//                         Some(s)
-//                     } else {
-//                         None
-//                     }
-//                 })
-//                 .collect();
-
-//             if !bad.is_empty() {
-//                 let mut temp_ts = self.model.clone();
-//                 // try to find a way out of this situation by temporarily relaxing the specs
-//                 // and instead planning to a new state where the specs holds.
-//                 temp_ts
-//                     .invariants
-//                     .retain(|spec| !bad.iter().any(|b| b.path() == spec.path()));
-//                 let goals = bad
-//                     .iter()
-//                     .map(|b| (b.invariant().clone(), None))
-//                     .collect::<Vec<_>>();
-//                 if let Ok(pr) = planning::plan(&temp_ts, goals.as_slice(),
-//                                                &state, LVL0_MAX_STEPS) {
-//                     let plan = pr
-//                         .trace
-//                         .iter()
-//                         .map(|x| x.transition.to_string())
-//                         .collect::<Vec<_>>()
-//                         .join("\n");
-
-//                     let spec_names = bad
-//                         .iter()
-//                         .map(|b| b.path().to_string())
-//                         .collect::<Vec<_>>()
-//                         .join(", ");
-//                     log_error!("We are in a forbidden state! Spec(s) {} are violated. A way to get out could be \n{}", spec_names, plan);
-
-//                     self.bad_state = true; // TODO: should not be needed
-//                 }
-//             }
-//         } else {
-//             self.bad_state = bad_state(&state, &self.model);
-//         }
-
-//         if self.bad_state {
-//             return None;
-//         }
-
-//         let goals = self.goals(&state);
-//         println!("Transition planner goals:");
-//         for g in &goals {
-//             println!("{}", g.0);
-//         }
-//         println!("--");
-
-//         let disabled_operations: HashSet<SPPath> = self.operations.iter()
-//             .filter(|o|o.is_error(&state))
-//             .map(|o|o.path().clone()).collect();
-
-//         // TODO: Move to other function. This code handles error operations and
-//         // resets them if posssible. Will be checked now and then.
-//         if Instant::now().duration_since(self.disabled_operation_check).as_secs() > 5 {
-//             self.disabled_operation_check = Instant::now();
-
-//             let fixed_ops: Vec<SPPath> = disabled_operations.iter().filter_map(|p| {
-//                 // check if the state if OK so we can remove any error states.
-//                 println!(
-//                     "checking if we can remove error on low level operation: {}",
-//                     p
-//                 );
-//                 let op = self.operations.iter().find(|o|o.path() == p)
-//                     .expect("no operation for disabled path");
-//                 let goal = vec![(op.get_goal(None).clone(), None)];
-
-//                 let mut ts = self.model.clone();
-//                 let removed: Vec<SPPath> = state.sub_state_projection(&SPPath::from_string("effects"))
-//                     .state.into_iter().filter(|(_k,v)|
-//                                               v.value() == &false.to_spvalue()).map(|(k,_v)| k.drop_root()).collect();
-//                 ts.transitions.retain(|t| !(t.type_ == TransitionType::Effect && removed.contains(t.path())));
-
-//                 let pr = planning::plan(
-//                     &ts,
-//                     goal.as_slice(),
-//                     &state,
-//                     LVL0_MAX_STEPS,
-//                 );
-
-//                 if let Ok(pr) = &pr {
-//                     if !pr.plan_found {
-//                         println!("operation still problematic...");
-//                         None
-//                     } else {
-//                         println!("operation fixed! {}", p);
-//                         Some(p)
-//                     }
-//                 } else {
-//                     None // syntax error
-//                 }
-//             }).cloned().collect();
-
-//             if !fixed_ops.is_empty() {
-//                 fixed_ops.iter().for_each(|p| {
-//                     println!("fixed operation {} so we are returning plan that blocks everything.", p);
-//                     self.plan.state_change.add_variable(p.clone(), "i".to_spvalue());
-//                 });
-//                 return Some(self.plan.clone());
-//             }
-//         }
-
-//         // check if planner is enabled
-//         let planner = SPPath::from_slice(&["runner", "planner", "0"]);
-//         if state
-//             .sp_value_from_path(&planner)
-//             .unwrap_or(&false.to_spvalue())
-//             != &true.to_spvalue()
-//         {
-//             let block_plan = self.block_all();
-
-//             return Some(block_plan);
-//         }
-
-//         //println!("TS {} GOT GOALS", i);
-
-//         let gr: Vec<&Predicate> = goals.iter().map(|g| &g.0).collect();
-
-//         let replan = {
-//             let ok = goals == self.prev_goals;
-//             if !ok {
-//                 let pg = Predicate::AND(goals.iter().map(|(c,_)| c.clone()).collect());
-//                 log_info!("transition planner replanning because goal changed. new goal: {}", pg);
-//                 self.prev_goals.iter().for_each(|g| println!("prev goals {}", g.0));
-//             }
-//             !ok
-//         } || {
-//             let now = Instant::now();
-
-//             let ok = {
-//                 let mut tsm = self.model.clone();
-//                 let removed: Vec<SPPath> = state.sub_state_projection(&SPPath::from_string("effects")).state
-//                     .into_iter().filter(|(_k,v)| v.value() == &false.to_spvalue()).map(|(k,_v)| k.drop_root()).collect();
-//                 tsm.transitions.retain(|t| !(t.type_ == TransitionType::Effect && removed.contains(t.path())));
-//                 check_goals_fast(
-//                     &state,
-//                     &gr,
-//                     &self.plan,
-//                     &tsm,
-//                 )
-//             };
-
-//             if now.elapsed().as_millis() > 100 {
-//                 println!(
-//                     "WARNINIG goal check for transition planner: {} (took {}ms)",
-//                     ok,
-//                     now.elapsed().as_millis()
-//                 );
-//             }
-
-//             if !ok {
-//                 println!(
-//                     "goal check for transition planner: {} (took {}ms)",
-//                     ok,
-//                     now.elapsed().as_millis()
-//                 );
-//                 let pg = Predicate::AND(goals.iter().map(|(c,_)| c.clone()).collect());
-//                 log_info!("transition planner replanning because we cannot reach goal: {}", pg);
-//             }
-//             !ok
-//         };
-
-//         if !replan {
-//             // we are done!
-//             println!("did not have to replan transition planner.");
-//             return None;
-//         }
-
-
-//         println!("transition planner replanning ");
-
-//         let planner_result = {
-//             // in the default case we should ensure monotonicity of the planning problem,
-//             // because 1) sops and high level plans need it
-//             // 2) operations (and errors!) are more intuitive this way.
-
-//             let mono = SPPath::from_slice(&["runner", "planner", "monotonic"]);
-//             let mut pr = if state
-//                 .sp_value_from_path(&mono)
-//                 .unwrap_or(&false.to_spvalue())
-//                 == &true.to_spvalue()
-//             {
-//                 let modifies: HashSet<SPPath> =
-//                     goals.iter().map(|(a, _)| a.support()).flatten().collect();
-//                 let product_state: HashSet<SPPath> =
-//                     self.model.vars.iter().filter_map(|v| {
-//                         if v.path().path.contains(&String::from("product_state")) {
-//                             Some(v.path().clone())
-//                         } else {
-//                             None
-//                         }
-//                     }).collect();
-//                 // HACK below!
-//                 let no_change: HashSet<&SPPath> = product_state.difference(&modifies).collect();
-//                 let no_change_specs: Vec<Predicate> = no_change
-//                     .iter()
-//                     .flat_map(|p| {
-//                         state.sp_value_from_path(p).map(|val| {
-//                             Predicate::EQ(
-//                                 PredicateValue::SPPath((*p).clone(), None),
-//                                 PredicateValue::SPValue(val.clone()),
-//                             )
-//                         })
-//                     })
-//                     .collect();
-//                 // TODO: these specs should really be extended to include any auto trans which can change them.
-//                 // the extending should be computed whenever the model changes
-//                 // this is temporary.
-//                 let mut tts = self.model.clone();
-//                 if !no_change_specs.is_empty() {
-//                     let no_change_pred = Predicate::AND(no_change_specs);
-//                     tts.invariants
-//                         .push(Specification::new_transition_invariant("monotonicity_constraints", no_change_pred));
-//                 }
-
-//                 // skip heuristic for the low level (cannot use cache if we dont serialize the extra invariants)
-
-//                 let removed: Vec<SPPath> = state.sub_state_projection(&SPPath::from_string("effects")).state
-//                     .into_iter().filter(|(_k,v)| v.value() == &false.to_spvalue()).map(|(k,_v)| k.drop_root()).collect();
-//                 tts.transitions.retain(|t| !(t.type_ == TransitionType::Effect && removed.contains(t.path())));
-
-//                 // planning::plan_with_cache(&tts, &goals, state, LVL0_MAX_STEPS, &mut self.store)
-//                 planning::plan(&tts, &goals, &state, LVL0_MAX_STEPS)
-//             } else {
-//                 // skip heuristic for the low level
-//                 // planning::plan_with_cache(ts, &goals, state, 2 * LVL0_MAX_STEPS, &mut self.store)
-//                 planning::plan(&self.model, &goals, &state, 2 * LVL0_MAX_STEPS)
-//             };
-
-//             if let Ok(ref mut pr) = pr {
-//                 planning::bubble_up_delibs(&self.model, &gr, pr);
-//             }
-//             pr
-//         };
-
-//         let plan_p = SPPath::from_slice(&["runner", "plans", "0"]);
-
-//         let mut no_plan = true;
-//         let mut time_to_solve = 0;
-//         let (tr, s, trans) = match planner_result {
-//             Ok(planner_result) => {
-//                 if planner_result.plan_found {
-//                     no_plan = false;
-//                 }
-//                 time_to_solve = planner_result.time_to_solve.as_millis();
-//                 let (tr, s) = planning::convert_planning_result(&self.model, &planner_result, &plan_p);
-//                 let trans: Vec<_> = planner_result
-//                     .trace
-//                     .iter()
-//                     .filter_map(|f| {
-//                         if f.transition.is_empty() {
-//                             None
-//                         } else {
-//                             Some(f.transition.clone())
-//                         }
-//                     })
-//                     .collect();
-//                 (tr, s, trans)
-//             },
-//             Err(err) => {
-//                 log_warn!("planner error: {}", err);
-//                 (planning::block_all(&self.model), SPState::new(), vec![])
-//             }
-//         };
-
-//         let mut plan = SPPlan {
-//             plan: tr,
-//             included_trans: trans,
-//             state_change: s,
-//         };
-
-//         // hack to reset the user visible planning steps when we replan
-//         // because sp_ui cannot remove states anyway (TODO!), we set dummy values
-//         let filtered_state: Vec<_> = state.projection().state
-//             .into_iter()
-//             .filter_map(|(p, _)| {
-//                 let in_new_plan = plan.state_change.projection()
-//                     .state
-//                     .iter()
-//                     .find(|(path,_)| &p == path).is_some();
-
-//                 if !in_new_plan && p.is_child_of(&plan_p) {
-//                     Some((p.clone(), "-".to_spvalue()))
-//                 } else {
-//                     None
-//                 }
-//             }).collect();
-//         plan.state_change.add_variables(filtered_state);
-
-//         if no_plan {
-//             // temp
-//             std::fs::copy(
-//                 "./last_planning_request.bmc",
-//                 "./last_failed_planning_request.bmc",
-//             )
-//                 .expect("file copy failed");
-//             // no low level plan found, we are in trouble.
-
-//             println!("no low level plan found!");
-
-//             // look for the problematic goals
-//             let executing_ops: Vec<&Operation> =
-//                 self.operations.iter()
-//                 .filter(|o| o.is_executing(&state)).collect();
-//             for o in &executing_ops {
-//                 let op_path = o.path();
-//                 let goal = o.get_goal(Some(&state));
-//                 println!("checking low level operation: {} -- {:?}", op_path, goal);
-//                 println!("disabled ops {:?}", disabled_operations);
-//                 if disabled_operations.contains(op_path) {
-//                     continue;
-//                 }
-//                 let goal = vec![(goal.clone(), None)];
-
-
-//                 let mut ts = self.model.clone();
-//                 let removed: Vec<SPPath> = state.sub_state_projection(&SPPath::from_string("effects"))
-//                     .state.into_iter().filter(|(_k,v)|
-//                                               v.value() == &false.to_spvalue()).map(|(k,_v)| k.drop_root()).collect();
-//                 ts.transitions.retain(|t| !(t.type_ == TransitionType::Effect && removed.contains(t.path())));
-
-
-//                 let pr = planning::plan(
-//                     &ts,
-//                     goal.as_slice(),
-//                     &state,
-//                     LVL0_MAX_STEPS,
-//                 );
-//                 let set_error =
-//                     match pr {
-//                         Ok(pr) => {
-//                             if !pr.plan_found {
-//                                 println!("offending low level operation: {}", op_path);
-//                                 log_warn!("offending low level operation: {}", op_path);
-//                                 // TODO, verify that this works
-//                                 true
-//                             } else {
-//                                 false
-//                             }
-//                         },
-//                         Err(e) => {
-//                             println!("offending low level operation: {}, syntax error: {}", op_path, e);
-//                             log_warn!("offending low level operation: {}, syntax error: {}", op_path, e);
-//                             true
-//                         }
-//                     };
-//                 if set_error {
-//                     plan.state_change
-//                         .add_variable(op_path.clone(), "error".to_spvalue());
-//                     return Some(plan);
-//                 }
-//             }
-//             if !executing_ops.is_empty() && disabled_operations.is_empty() {
-//                 // panic!("NO PLAN FOUND BUT ALSO NOW OFFENDING OPS");
-//                 log_error!("NO PLAN FOUND BUT ALSO NO OFFENDING OPS.\n\
-//                             It could be that the planning horizon needs to be increased.");
-//             }
-//         }
-
-//         // update last set of goals
-//         if no_plan {
-//             log_warn!(
-//                 "No plan was found for transition planner! time to fail {}ms",
-//                 time_to_solve,
-//             );
-//             self.prev_goals.clear();
-//         } else {
-//             log_info!(
-//                 "New plan was found for transition planner! time to solve {}ms",
-//                 time_to_solve
-//             );
-//             self.prev_goals = goals.clone();
-//         }
-
-//         // we save out own copy of the plan because we are
-//         // not necessarily in sync with the runner
-//         self.plan = plan.clone();
-
-//         // rename paths before sending plan to the runner.
-//         // return early as soon as we have a new plan
-//         return Some(plan);
-//     }
-
-    pub fn compute_new_plan(
+    /// Get the current goals and invariants given a state.
+    fn goals(&self, state: &SPState) -> Vec<(Predicate, Option<Predicate>)> {
+        self.operations.iter().filter_map(|op| {
+            if op.is_executing(state) {
+                Some((op.goal.clone(), None))
+            } else {
+                None
+            }
+        }).collect()
+    }
+
+    /// Only keep parts of the state that are relevant to this planner.
+    pub fn filter_state(&self, state: &SPState) -> SPState {
+        let paths: Vec<_> = self.model.vars.iter().map(|v| v.path.clone()).collect();
+        state.filter_by_paths(&paths)
+    }
+
+    /// Keep track of how far the runner has come in the current plan.
+    /// Plans that are not ours, e.g. an old plan the runner has not yet
+    /// replaced, are ignored.
+    pub fn update_plan_progress(&mut self, plan: &PartialOrderPlan) {
+        if plan.same_steps(&self.partial_order_plan) {
+            self.partial_order_plan = plan.clone();
+        }
+    }
+
+    fn set_plan(&mut self, trace: &[PlanningFrame]) {
+        self.plan = trace
+            .iter()
+            .filter(|f| f.transition != SPPath::default() && !self.model.uncontrollable.contains(&f.transition))
+            .map(|f| f.transition.clone())
+            .collect();
+        self.partial_order_plan = PartialOrderPlan::from_trace(&self.model, trace);
+    }
+
+    fn new_plan(&self, state_change: SPState) -> TransitionPlan {
+        TransitionPlan {
+            plan: self.plan.clone(),
+            partial_order_plan: self.partial_order_plan.clone(),
+            state_change,
+        }
+    }
+
+    /// Simulate the runner following what is left of the current plan.
+    fn plan_reaches_goals(&mut self, state: &SPState, goals: &[(Predicate, Option<Predicate>)]) -> bool {
+        self.simulation_ticker.state = state.clone();
+        self.simulation_ticker.partial_order_plan = self.partial_order_plan.clone();
+        self.simulation_ticker.update_state_paths();
+        let goals: Vec<&Predicate> = goals.iter().map(|(g, _)| g).collect();
+        check_goals_fast(&mut self.simulation_ticker, &goals)
+    }
+
+    async fn plan_with_cache(
+        &mut self,
+        goals: &[(Predicate, Option<Predicate>)],
+        state: &SPState,
+        cancel: &CancellationToken,
+        progress: Option<&ProgressSender>) -> Result<PlanningResult, PlanningError> {
+        let key = PlanCache::key(
+            &self.model, goals, state, &[], self.max_steps, self.planner.as_ref());
+        if let Some(result) = self.store.get(&key) {
+            return Ok(result);
+        }
+        let result = self.planner
+            .plan_async(&self.model, goals, state, self.max_steps, cancel, progress)
+            .await?;
+        // no plan within the horizon is not a result worth keeping.
+        if result.plan_found {
+            if let Err(e) = self.store.insert(&key, &result) {
+                log_warn!("could not cache plan: {}", e);
+            }
+        }
+        Ok(result)
+    }
+
+    /// Compute a new plan if the goals have changed or the current
+    /// plan no longer reaches them. Returns `None` if the current
+    /// plan can be kept.
+    pub async fn compute_new_plan(
         &mut self,
-        mut _state: SPState) -> Option<Vec<SPPath>> {
+        state: SPState,
+        cancel: &CancellationToken,
+        progress: Option<&ProgressSender>) -> Option<TransitionPlan> {
+        let state = self.filter_state(&state);
+
+        // nothing has changed, no need to do anything.
+        if state == self.prev_state {
+            return None;
+        }
+        self.prev_state = state.clone();
+
+        let goals = self.goals(&state);
+        if goals.is_empty() {
+            self.prev_goals.clear();
+            if self.plan.is_empty() {
+                return None;
+            }
+            self.set_plan(&[]);
+            return Some(self.new_plan(SPState::new()));
+        }
+
+        if goals == self.prev_goals && self.plan_reaches_goals(&state, &goals) {
+            // we are done!
+            return None;
+        }
+        if goals != self.prev_goals {
+            let pg = Predicate::AND(goals.iter().map(|(c,_)| c.clone()).collect());
+            log_info!("transition planner replanning because goal changed. new goal: {}", pg);
+        }
+
+        let result = self.plan_with_cache(&goals, &state, cancel, progress).await;
+        let result = match result {
+            Ok(result) if result.plan_found => result,
+            Err(PlanningError::Cancelled) => {
+                // try again next time
+                self.prev_state = SPState::new();
+                return None;
+            }
+            Err(e) => {
+                // not the same as there being no plan, try again next time
+                log_warn!("planner error: {}", e);
+                self.prev_state = SPState::new();
+                return None;
+            }
+            Ok(_) => {
+                log_warn!("No plan was found for transition planner!");
+                self.prev_goals.clear();
+                self.set_plan(&[]);
+                let state_change = self.offending_operations(&state, cancel).await;
+                return Some(self.new_plan(state_change));
+            }
+        };
+
+        log_info!(
+            "New plan was found for transition planner! time to solve {}ms",
+            result.time_to_solve.as_millis()
+        );
+        self.prev_goals = goals;
+        self.set_plan(&result.trace);
 
-        None
+        Some(self.new_plan(SPState::new()))
     }
 
-    pub fn from(model: &RunnerModel) -> Self {
+    /// Plan for each executing operation on its own. The operations
+    /// whose goals can not be reached are set to "error", planner
+    /// errors say nothing about that.
+    async fn offending_operations(&mut self, state: &SPState, cancel: &CancellationToken) -> SPState {
+        let mut state_change = SPState::new();
+        let executing: Vec<_> = self.operations.iter()
+            .filter(|o| o.is_executing(state))
+            .cloned()
+            .collect();
+        for op in executing {
+            let goal = vec![(op.goal.clone(), None)];
+            let offending = match self.plan_with_cache(&goal, state, cancel, None).await {
+                Ok(result) => !result.plan_found,
+                Err(PlanningError::Cancelled) => false,
+                Err(e) => {
+                    log_warn!("could not check low level operation: {}, error: {}", op.path, e);
+                    false
+                }
+            };
+            if offending {
+                log_warn!("offending low level operation: {}", op.path);
+                state_change.add_variable(op.path.clone(), op.states.error.to_spvalue());
+            }
+        }
+        state_change
+    }
 
-        let simulation_ticker = Ticker::default();
+    pub fn from(model: &RunnerModel) -> Self {
+        // operations are started by the operation planner, this level
+        // only plans for their goals.
+        let mut tsm = model.tsm.clone();
+        tsm.transitions.retain(|t| {
+            !model.operations.iter().any(|o| t.path.parent() == o.path)
+        });
+
+        // the runner as seen by the planner
+        let (uncontrolled_transitions, controlled_transitions) = tsm.transitions
+            .iter()
+            .cloned()
+            .partition(|t| tsm.uncontrollable.contains(&t.path));
+        let simulation_ticker = Ticker {
+            controlled_transitions,
+            uncontrolled_transitions,
+            predicates: model.tsm.state_predicates.clone(),
+            .. Ticker::default()
+        };
 
         let tp = TransitionPlanner {
             plan: vec![],
-            model: model.tsm.clone(),
-            bad_state: false,
+            partial_order_plan: PartialOrderPlan::default(),
+            model: tsm,
+            operations: model.operations.clone(),
             prev_state: SPState::new(),
             prev_goals: vec![],
-            store: planning::PlanningStore::default(),
-            disabled_operation_check: std::time::Instant::now(),
+            store: PlanCache::default(),
+            planner: model.planner.create(),
+            max_steps: model.config.transition_horizon,
             simulation_ticker,
         };
 
         tp
     }
 }
+
+#[cfg(test)]
+mod test_transition_planner {
+    use super::*;
+
+    #[derive(Resource)]
+    struct Model {
+        #[Variable(type = "bool", initial = false)]
+        x: Variable,
+        #[Variable(type = "bool", initial = false)]
+        y: Variable,
+        #[Variable(type = "bool", initial = false)]
+        z: Variable,
+    }
+
+    fn runner_model() -> (RunnerModel, SPPath, SPPath) {
+        let m = Model::new("m");
+        let x = m.x.path.clone();
+        let y = m.y.path.clone();
+        let mut mb = ModelBuilder::from(&m);
+        mb.transitions.push(ModelTransition {
+            transitions: vec![(
+                Transition::new("set_x".into(), p!(!x), vec![a!(x)]),
+                TransitionType::Controlled,
+            )],
+        });
+        mb.transitions.push(ModelTransition {
+            transitions: vec![(
+                Transition::new("set_y".into(), p!([x] && [!y]), vec![a!(y)]),
+                TransitionType::Controlled,
+            )],
+        });
+        let reachable = mb.add_operation("op".into(),
+                                         Predicate::TRUE, vec![],
+                                         Predicate::TRUE, vec![],
+                                         p!(y), vec![],
+                                         Predicate::TRUE, vec![]);
+        let unreachable = mb.add_operation("bad_op".into(),
+                                           Predicate::TRUE, vec![],
+                                           Predicate::TRUE, vec![],
+                                           Predicate::FALSE, vec![],
+                                           Predicate::TRUE, vec![]);
+        let mut rm = RunnerModel::from(mb);
+        rm.planner = PlannerBackend::Explicit(PlanObjective::Steps);
+        (rm, reachable, unreachable)
+    }
+
+    #[tokio::test]
+    async fn plans_for_executing_operations() {
+        let (rm, op, _) = runner_model();
+        let mut tp = TransitionPlanner::from(&rm);
+        let cancel = CancellationToken::new();
+
+        // operations are started by the operation planner
+        let names: Vec<_> = tp.model.transitions.iter().map(|t| t.path.clone()).collect();
+        assert_eq!(names, vec![SPPath::from("set_x"), SPPath::from("set_y")]);
+
+        let mut state = rm.initial_state.clone();
+        assert_eq!(tp.compute_new_plan(state.clone(), &cancel, None).await, None);
+
+        state.add_variable(op, "e".to_spvalue());
+        let plan = tp.compute_new_plan(state.clone(), &cancel, None).await.unwrap();
+        assert_eq!(plan.plan, vec![SPPath::from("set_x"), SPPath::from("set_y")]);
+
+        // the plan still reaches the goal
+        state.add_variable(SPPath::from(&["m", "z"]), true.to_spvalue());
+        assert_eq!(tp.compute_new_plan(state.clone(), &cancel, None).await, None);
+
+        // the runner executes the same plan, where set_y waits for set_x.
+        let mut runner_plan = plan.partial_order_plan;
+        let predecessors: Vec<_> = runner_plan.steps.iter().map(|s| s.predecessors.clone()).collect();
+        assert_eq!(predecessors, vec![vec![], vec![0]]);
+
+        // and it does so from where the runner is, not from the start.
+        runner_plan.set_done(0);
+        tp.update_plan_progress(&runner_plan);
+        state.add_variable(SPPath::from(&["m", "x"]), true.to_spvalue());
+        assert_eq!(tp.compute_new_plan(state, &cancel, None).await, None);
+    }
+
+    #[tokio::test]
+    async fn offending_operations_are_set_to_error() {
+        let (rm, op, bad_op) = runner_model();
+        let mut tp = TransitionPlanner::from(&rm);
+        let cancel = CancellationToken::new();
+
+        let mut state = rm.initial_state.clone();
+        state.add_variable(op.clone(), "e".to_spvalue());
+        state.add_variable(bad_op.clone(), "e".to_spvalue());
+        let plan = tp.compute_new_plan(state, &cancel, None).await.unwrap();
+        assert!(plan.plan.is_empty());
+        assert_eq!(plan.state_change.sp_value_from_path(&bad_op), Some(&"error".to_spvalue()));
+        assert_eq!(plan.state_change.sp_value_from_path(&op), None);
+        // only the plan for the reachable operation is cached.
+        assert_eq!(tp.store.metrics().inserts, 1);
+    }
+
+    #[tokio::test]
+    async fn planner_errors_do_not_fail_operations() {
+        let (mut rm, op, _) = runner_model();
+        rm.planner = PlannerBackend::NuXmv(NuXmvConfig {
+            binary: Some("/nonexistent/nuXmv".into()),
+            ..NuXmvConfig::default()
+        });
+        let mut tp = TransitionPlanner::from(&rm);
+        let cancel = CancellationToken::new();
+
+        let mut state = rm.initial_state.clone();
+        state.add_variable(op, "e".to_spvalue());
+        assert_eq!(tp.compute_new_plan(state.clone(), &cancel, None).await, None);
+        // and it tries again, even if nothing has changed.
+        assert_eq!(tp.prev_state, SPState::new());
+    }
+}